@@ -0,0 +1,80 @@
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+
+use crate::settings::Settings;
+
+/// Below this the detail is lowered by one level
+const MIN_FPS: f64 = 30.0;
+/// Above this the detail is raised back by one level
+const RECOVERED_FPS: f64 = 50.0;
+/// Time in seconds to wait after changing the level so the frame rate can settle before
+/// changing it again
+const SETTLE_TIME: f32 = 2.0;
+
+/// The trees and fences are hidden from this level
+pub const HIDE_PROPS_LEVEL: u32 = 1;
+/// The low poly boxes of the buildings far from the camera are hidden from this level
+pub const HIDE_DISTANT_BUILDINGS_LEVEL: u32 = 2;
+
+/// Hides details of the city when the frame rate drops, see [`Settings::adaptive_detail`]
+pub struct AdaptiveDetailPlugin;
+
+impl Plugin for AdaptiveDetailPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+            app.add_plugins(FrameTimeDiagnosticsPlugin::default());
+        }
+        app.init_resource::<DetailReduction>()
+            .add_systems(Update, adapt_detail);
+    }
+}
+
+/// How much detail is hidden to keep the frame rate up, 0 shows everything
+#[derive(Resource, Default)]
+pub struct DetailReduction {
+    pub level: u32,
+    since_change: f32,
+}
+
+/// Lowers the detail one level at a time while the frame rate is below [`MIN_FPS`] and raises
+/// it back once it's above [`RECOVERED_FPS`]
+fn adapt_detail(
+    settings: Res<Settings>,
+    diagnostics: Res<DiagnosticsStore>,
+    time: Res<Time>,
+    mut reduction: ResMut<DetailReduction>,
+) {
+    if !settings.adaptive_detail {
+        if reduction.level != 0 {
+            reduction.level = 0;
+        }
+        return;
+    }
+
+    // Only the level needs to trigger change detection
+    reduction.bypass_change_detection().since_change += time.delta_secs();
+    if reduction.since_change < SETTLE_TIME {
+        return;
+    }
+    let Some(fps) = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed())
+    else {
+        return;
+    };
+
+    let level = if fps < MIN_FPS {
+        (reduction.level + 1).min(HIDE_DISTANT_BUILDINGS_LEVEL)
+    } else if fps > RECOVERED_FPS {
+        reduction.level.saturating_sub(1)
+    } else {
+        reduction.level
+    };
+    if level != reduction.level {
+        info!("Adaptive detail level changed to {level} at {fps:.0} fps");
+        reduction.level = level;
+        reduction.since_change = 0.0;
+    }
+}
@@ -1,235 +1,849 @@
-use bevy::{color::palettes::css::WHITE, prelude::*};
+use bevy::{
+    asset::{UntypedAssetId, UntypedAssetLoadFailedEvent},
+    camera::primitives::MeshAabb,
+    platform::collections::HashSet,
+    prelude::*,
+};
 use rand::RngExt;
 
+use crate::{
+    bus::{BUS_SIZE, BUS_STOP_SIZE},
+    emergency::SirenAssets,
+    manifest::{BuildingManifest, BuildingSet},
+    placement::{SMOKESTACK_HEIGHT, SMOKESTACK_RADIUS},
+    season::Season,
+};
+
 #[derive(Resource)]
 pub struct CityAssets {
-    pub cars: Vec<Handle<Scene>>,
+    pub cars: Vec<CarAsset>,
+    /// The ambulances, police cars and fire trucks, picked instead of a car once in a while
+    pub emergency_vehicles: Vec<CarAsset>,
     pub crossroad: Handle<Scene>,
+    /// Crossroad with its -z side closed
+    pub road_junction: Handle<Scene>,
+    /// Corner going from -x to +z
+    pub road_bend: Handle<Scene>,
+    /// Dead end with its road leaving from the +x side
+    pub road_end: Handle<Scene>,
+    /// Covers 3 by 3 road tiles with its exits in the middle of each side
+    pub roundabout: Handle<Scene>,
     pub road_straight: Handle<Scene>,
+    pub road_bridge: Handle<Scene>,
     pub high_density: Buildings,
     pub medium_density: Buildings,
     pub low_density: Buildings,
-    pub ground_tile: (
-        Handle<Mesh>,
-        Handle<StandardMaterial>,
-        Handle<StandardMaterial>,
-    ),
+    pub industrial: Buildings,
+    pub ground_tiles: GroundTiles,
     pub tree_small: Handle<Scene>,
     pub tree_large: Handle<Scene>,
+    /// The colormap of the trees recolored for each season of [`Season::ALL`], filled by
+    /// `build_season_textures` once the colormap of the kit is loaded
+    pub tree_textures: [Handle<Image>; Season::ALL.len()],
+    /// The material swapped on the trees in each season of [`Season::ALL`]
+    pub tree_materials: [Handle<StandardMaterial>; Season::ALL.len()],
     pub path_stones_long: Handle<Scene>,
     pub fence: Handle<Scene>,
+    pub bench: Handle<Scene>,
+    pub smokestack: (Handle<Mesh>, Handle<StandardMaterial>),
+    pub siren: SirenAssets,
+    pub bus: (Handle<Mesh>, Handle<StandardMaterial>),
+    pub bus_stop: (Handle<Mesh>, Handle<StandardMaterial>),
+    pub pedestrians: Vec<(Handle<Mesh>, Handle<StandardMaterial>)>,
 }
 
 impl CityAssets {
-    pub fn get_random_car<R: RngExt>(&self, rng: &mut R) -> Handle<Scene> {
-        self.cars[rng.random_range(0..self.cars.len())].clone()
+    pub fn get_random_car<R: RngExt>(&self, rng: &mut R) -> &CarAsset {
+        &self.cars[rng.random_range(0..self.cars.len())]
+    }
+
+    pub fn get_random_emergency_vehicle<R: RngExt>(&self, rng: &mut R) -> &CarAsset {
+        &self.emergency_vehicles[rng.random_range(0..self.emergency_vehicles.len())]
+    }
+
+    pub fn get_random_pedestrian<R: RngExt>(
+        &self,
+        rng: &mut R,
+    ) -> (Mesh3d, MeshMaterial3d<StandardMaterial>) {
+        let (mesh, material) = &self.pedestrians[rng.random_range(0..self.pedestrians.len())];
+        (Mesh3d(mesh.clone()), MeshMaterial3d(material.clone()))
+    }
+
+    /// Assets made of default handles, for generating the city without loading or rendering
+    /// anything
+    ///
+    /// The random choices of the blocks depend on how many variants of each asset there are and
+    /// the buildings are placed from their footprints, so these match the loaded assets and the
+    /// same config gives the same city with or without them.
+    pub fn headless() -> Self {
+        let manifest = BuildingManifest::load();
+        Self {
+            cars: CAR_MODELS
+                .iter()
+                .map(|model| CarAsset {
+                    scene: default(),
+                    heavy: is_heavy_vehicle(model),
+                })
+                .collect(),
+            emergency_vehicles: EMERGENCY_MODELS
+                .iter()
+                .map(|model| CarAsset {
+                    scene: default(),
+                    heavy: is_heavy_vehicle(model),
+                })
+                .collect(),
+            crossroad: default(),
+            road_junction: default(),
+            road_bend: default(),
+            road_end: default(),
+            roundabout: default(),
+            road_straight: default(),
+            road_bridge: default(),
+            high_density: Buildings::headless(&manifest.high_density),
+            medium_density: Buildings::headless(&manifest.medium_density),
+            low_density: Buildings::headless(&manifest.low_density),
+            industrial: Buildings::headless(&manifest.industrial),
+            ground_tiles: GroundTiles::default(),
+            tree_small: default(),
+            tree_large: default(),
+            tree_textures: default(),
+            tree_materials: default(),
+            path_stones_long: default(),
+            fence: default(),
+            bench: default(),
+            smokestack: default(),
+            siren: default(),
+            bus: default(),
+            bus_stop: default(),
+            pedestrians: PEDESTRIAN_COLORS.map(|_| default()).to_vec(),
+        }
+    }
+
+    pub fn building_sets(&self) -> [&Buildings; 4] {
+        [
+            &self.high_density,
+            &self.medium_density,
+            &self.low_density,
+            &self.industrial,
+        ]
+    }
+
+    /// Every scene loaded from the kits
+    fn scenes(&self) -> impl Iterator<Item = &Handle<Scene>> {
+        let cars = self.cars.iter().chain(&self.emergency_vehicles);
+        cars.map(|car| &car.scene).chain([
+            &self.crossroad,
+            &self.road_junction,
+            &self.road_bend,
+            &self.road_end,
+            &self.roundabout,
+            &self.road_straight,
+            &self.road_bridge,
+            &self.tree_small,
+            &self.tree_large,
+            &self.path_stones_long,
+            &self.fence,
+            &self.bench,
+        ])
+    }
+
+    /// Every mesh, scene and texture the city is built from
+    fn handles(&self) -> impl Iterator<Item = UntypedAssetId> {
+        let buildings = self.building_sets().into_iter().flat_map(|buildings| {
+            let meshes = buildings.meshes.iter().map(|mesh| mesh.id().untyped());
+            let textures = buildings
+                .textures
+                .iter()
+                .map(|texture| texture.id().untyped());
+            meshes.chain(textures)
+        });
+        // Only the summer texture is loaded, the others are made from it
+        self.scenes()
+            .map(|scene| scene.id().untyped())
+            .chain(buildings)
+            .chain([
+                self.ground_tiles.mesh.id().untyped(),
+                self.tree_textures[Season::Summer.index()].id().untyped(),
+            ])
+    }
+
+    /// Returns how many assets of the city are done loading, along with the total
+    ///
+    /// The assets that failed to load count as done since they won't ever finish.
+    pub fn load_progress(
+        &self,
+        asset_server: &AssetServer,
+        placeholders: &PlaceholderAssets,
+    ) -> (usize, usize) {
+        self.handles().fold((0, 0), |(done, total), id| {
+            let state = asset_server.recursive_dependency_load_state(id);
+            let is_done = state.is_loaded() || state.is_failed() || placeholders.0.contains(&id);
+            (done + usize::from(is_done), total + 1)
+        })
     }
 }
 
+/// The assets replaced by a placeholder in [`replace_failed_assets`]
+///
+/// The failure is only reported on the glTF file so the assets loaded from inside of it never
+/// leave their loading state.
+#[derive(Resource, Default)]
+pub struct PlaceholderAssets(HashSet<UntypedAssetId>);
+
+pub struct CarAsset {
+    pub scene: Handle<Scene>,
+    /// Trucks, vans and other heavy vehicles that drive slower than the other cars
+    pub heavy: bool,
+}
+
+/// The mesh of the ground tile of each block and the materials it can be covered with
+#[derive(Default)]
+pub struct GroundTiles {
+    pub mesh: Handle<Mesh>,
+    /// A box one unit wide and long stretched along the roads for the sidewalks, and its
+    /// material
+    pub sidewalk: (Handle<Mesh>, Handle<StandardMaterial>),
+    pub grass_material: Handle<StandardMaterial>,
+    pub sidewalk_material: Handle<StandardMaterial>,
+    pub asphalt_material: Handle<StandardMaterial>,
+    pub water_material: Handle<StandardMaterial>,
+}
+
+/// The mesh and material of a building followed by its low poly version, the materials it
+/// switches between during the day and at night and which of the building set it was picked from
+pub type BuildingWithLod = (
+    (Mesh3d, MeshMaterial3d<StandardMaterial>),
+    (Mesh3d, MeshMaterial3d<StandardMaterial>),
+    BuildingMaterial,
+    BuildingModel,
+);
+
+/// The indices of the mesh and material of a building in its building set, and of its tint
+/// when the buildings are tinted
+///
+/// The handles are the same for every building without assets, these tell the picked buildings
+/// apart in the headless app.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct BuildingModel {
+    pub mesh: usize,
+    pub material: usize,
+    pub tint: Option<usize>,
+}
+
+/// A building material along with the copy used when the windows of the building are lit
+#[derive(Clone, Default)]
+pub struct BuildingMaterial {
+    pub unlit: Handle<StandardMaterial>,
+    pub lit: Handle<StandardMaterial>,
+}
+
+impl BuildingMaterial {
+    fn new(material: StandardMaterial, materials: &mut Assets<StandardMaterial>) -> Self {
+        // The kits don't have a separate window texture so the whole texture glows, the darker
+        // walls stay dimmer than the windows
+        let lit = StandardMaterial {
+            emissive: WINDOW_LIGHT,
+            emissive_texture: material.base_color_texture.clone(),
+            ..material.clone()
+        };
+        Self {
+            unlit: materials.add(material),
+            lit: materials.add(lit),
+        }
+    }
+}
+
+/// Height of the curb of the road pieces of the kit
+const SIDEWALK_HEIGHT: f32 = 0.02;
+
+/// Warm light coming out of the lit windows at night
+const WINDOW_LIGHT: LinearRgba = LinearRgba::rgb(500.0, 350.0, 180.0);
+
 pub struct Buildings {
     meshes: Vec<Handle<Mesh>>,
-    materials: Vec<Handle<StandardMaterial>>,
+    /// Half size on the x and z axis of each mesh in `meshes`
+    footprints: Vec<Vec2>,
+    materials: Vec<BuildingMaterial>,
+    /// The textures used by `materials`
+    textures: Vec<Handle<Image>>,
+    /// [`TINT_BUCKETS`] tinted copies of each material in `materials`
+    tinted_materials: Vec<Vec<BuildingMaterial>>,
+    /// Low poly version of each mesh in `meshes`, filled by `build_lod_meshes` once the mesh is
+    /// loaded
+    lod_meshes: Vec<Handle<Mesh>>,
+    lod_material: Handle<StandardMaterial>,
 }
 
 impl Buildings {
-    pub fn get_random_building<R: RngExt>(
+    fn new(
+        meshes: Vec<(Handle<Mesh>, Vec2)>,
+        materials: Vec<BuildingMaterial>,
+        textures: Vec<Handle<Image>>,
+        tinted_materials: Vec<Vec<BuildingMaterial>>,
+        lod_material: Handle<StandardMaterial>,
+        mesh_assets: &Assets<Mesh>,
+    ) -> Self {
+        let (meshes, footprints): (Vec<_>, Vec<_>) = meshes.into_iter().unzip();
+        let lod_meshes = meshes
+            .iter()
+            .map(|_| mesh_assets.reserve_handle())
+            .collect();
+        Self {
+            meshes,
+            footprints,
+            materials,
+            textures,
+            tinted_materials,
+            lod_meshes,
+            lod_material,
+        }
+    }
+
+    /// The buildings of the set with default handles, see [`CityAssets::headless`]
+    fn headless(set: &BuildingSet) -> Self {
+        Self {
+            meshes: vec![default(); set.meshes.len()],
+            footprints: set
+                .meshes
+                .iter()
+                .map(|building| Vec2::from_array(building.footprint))
+                .collect(),
+            materials: vec![BuildingMaterial::default(); set.materials.len()],
+            tinted_materials: vec![
+                vec![BuildingMaterial::default(); TINT_BUCKETS];
+                set.materials.len()
+            ],
+            textures: Vec::new(),
+            lod_meshes: vec![default(); set.meshes.len()],
+            lod_material: default(),
+        }
+    }
+
+    pub fn footprints(&self) -> &[Vec2] {
+        &self.footprints
+    }
+
+    /// Picks a random building and returns it along with its low poly version
+    pub fn get_random_building<R: RngExt>(&self, rng: &mut R, tinted: bool) -> BuildingWithLod {
+        let index = rng.random_range(0..self.meshes.len());
+        self.get_building(index, rng, tinted)
+    }
+
+    /// Returns the building at the given index of `footprints` with a random material, along
+    /// with its low poly version
+    ///
+    /// When `tinted` is set the material is one of the tinted copies of the material instead.
+    pub fn get_building<R: RngExt>(
         &self,
+        index: usize,
         rng: &mut R,
-    ) -> (Mesh3d, MeshMaterial3d<StandardMaterial>) {
-        let mesh = self.meshes[rng.random_range(0..self.meshes.len())].clone();
-        let material = self.materials[rng.random_range(0..self.materials.len())].clone();
-        (Mesh3d(mesh), MeshMaterial3d(material))
+        tinted: bool,
+    ) -> BuildingWithLod {
+        let material_index = rng.random_range(0..self.materials.len());
+        let tint = tinted.then(|| rng.random_range(0..TINT_BUCKETS));
+        let material = match tint {
+            Some(tint) => self.tinted_materials[material_index][tint].clone(),
+            None => self.materials[material_index].clone(),
+        };
+        (
+            (
+                Mesh3d(self.meshes[index].clone()),
+                MeshMaterial3d(material.unlit.clone()),
+            ),
+            (
+                Mesh3d(self.lod_meshes[index].clone()),
+                MeshMaterial3d(self.lod_material.clone()),
+            ),
+            material,
+            BuildingModel {
+                mesh: index,
+                material: material_index,
+                tint,
+            },
+        )
+    }
+}
+
+/// Number of tints the buildings can get, every tint is a different material so this is kept
+/// low to keep the buildings batched together
+pub const TINT_BUCKETS: usize = 8;
+
+/// The color multiplied with the texture of the buildings using the given tint bucket
+///
+/// The tints are spread around the hue wheel but stay close to white so the textures still read
+/// well.
+fn building_tint(bucket: usize) -> Color {
+    let hue = 360.0 * bucket as f32 / TINT_BUCKETS as f32;
+    Color::hsl(hue, 0.3, 0.85)
+}
+
+/// Where the assets are loaded from
+///
+/// Every path to a kenney asset goes through this so the layout of the kits is only defined
+/// once. The buildings come from the kits listed in `assets/buildings.ron` which uses paths
+/// relative to [`AssetPaths::KENNEY_DIR`].
+pub struct AssetPaths;
+
+impl AssetPaths {
+    /// Set to "https://github.com/bevyengine/bevy_asset_files/raw/main/kenney" to load the web
+    /// assets instead of the local ones
+    pub const KENNEY_DIR: &str = "kenney";
+    pub const CAR_KIT: &str = "car-kit";
+    pub const ROAD_KIT: &str = "city-kit-roads";
+    pub const SUBURBAN_KIT: &str = "city-kit-suburban";
+
+    /// Path of a file relative to the kenney directory
+    pub fn kenney(path: &str) -> String {
+        format!("{}/{path}", Self::KENNEY_DIR)
+    }
+
+    pub fn car(name: &str) -> String {
+        Self::kenney(&format!("{}/{name}.glb", Self::CAR_KIT))
+    }
+
+    pub fn road(file: &str) -> String {
+        Self::kenney(&format!("{}/{file}", Self::ROAD_KIT))
+    }
+
+    pub fn suburban(file: &str) -> String {
+        Self::kenney(&format!("{}/{file}", Self::SUBURBAN_KIT))
     }
 }
 
+// TODO generate color variations
+const CAR_MODELS: [&str; 12] = [
+    "hatchback-sports",
+    "suv",
+    "suv-luxury",
+    "sedan",
+    "sedan-sports",
+    "truck",
+    "truck-flat",
+    "van",
+    "delivery",
+    "delivery-flat",
+    "taxi",
+    "garbage-truck",
+];
+
+const EMERGENCY_MODELS: [&str; 3] = ["ambulance", "police", "firetruck"];
+
+fn is_heavy_vehicle(model: &str) -> bool {
+    matches!(
+        model,
+        "truck"
+            | "truck-flat"
+            | "van"
+            | "delivery"
+            | "delivery-flat"
+            | "garbage-truck"
+            | "firetruck"
+    )
+}
+
+const PEDESTRIAN_COLORS: [Color; 4] = [
+    Color::srgb_u8(200, 60, 60),
+    Color::srgb_u8(60, 90, 200),
+    Color::srgb_u8(230, 200, 80),
+    Color::srgb_u8(70, 70, 70),
+];
+
+fn load_scene(asset_server: &AssetServer, path: String) -> Handle<Scene> {
+    asset_server.load(GltfAssetLabel::Scene(0).from_asset(path))
+}
+
 pub fn load_assets(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut mesh_assets: ResMut<Assets<Mesh>>,
+    images: Res<Assets<Image>>,
 ) {
-    // load local assets instead of web assets
-    // let base_url = "https://github.com/bevyengine/bevy_asset_files/raw/main/kenney";
-    let base_url = "kenney";
-
-    let cars = {
-        // TODO generate color variations
-        [
-            "hatchback-sports",
-            "suv",
-            "suv-luxury",
-            "sedan",
-            "sedan-sports",
-            "truck",
-            "truck-flat",
-            "van",
-            "delivery",
-            "delivery-flat",
-            "taxi",
-            "garbage-truck",
-            "ambulance",
-            "police",
-            "firetruck",
-        ]
+    let cars = CAR_MODELS
         .iter()
-        .map(|t| {
-            asset_server
-                .load(GltfAssetLabel::Scene(0).from_asset(format!("{base_url}/car-kit/{t}.glb")))
+        .map(|t| CarAsset {
+            scene: load_scene(&asset_server, AssetPaths::car(t)),
+            heavy: is_heavy_vehicle(t),
         })
-        .collect::<Vec<_>>()
-    };
+        .collect::<Vec<_>>();
+    let emergency_vehicles = EMERGENCY_MODELS
+        .iter()
+        .map(|t| CarAsset {
+            scene: load_scene(&asset_server, AssetPaths::car(t)),
+            heavy: is_heavy_vehicle(t),
+        })
+        .collect::<Vec<_>>();
+
+    let crossroad = load_scene(&asset_server, AssetPaths::road("road-crossroad-path.glb"));
+    let road_junction = load_scene(
+        &asset_server,
+        AssetPaths::road("road-intersection-path.glb"),
+    );
+    let road_bend = load_scene(&asset_server, AssetPaths::road("road-bend-sidewalk.glb"));
+    let road_end = load_scene(&asset_server, AssetPaths::road("road-end.glb"));
+    let roundabout = load_scene(&asset_server, AssetPaths::road("road-roundabout.glb"));
+    let road_straight = load_scene(&asset_server, AssetPaths::road("road-straight.glb"));
+    let road_bridge = load_scene(&asset_server, AssetPaths::road("road-bridge.glb"));
 
-    let crossroad = asset_server.load(
-        GltfAssetLabel::Scene(0)
-            .from_asset(format!("{base_url}/city-kit-roads/road-crossroad-path.glb")),
+    let manifest = BuildingManifest::load();
+    let high_density = load_building_set(
+        &manifest.high_density,
+        &asset_server,
+        &mut materials,
+        &mesh_assets,
     );
-    let road_straight = asset_server.load(
-        GltfAssetLabel::Scene(0).from_asset(format!("{base_url}/city-kit-roads/road-straight.glb")),
+    let medium_density = load_building_set(
+        &manifest.medium_density,
+        &asset_server,
+        &mut materials,
+        &mesh_assets,
+    );
+    let low_density = load_building_set(
+        &manifest.low_density,
+        &asset_server,
+        &mut materials,
+        &mesh_assets,
+    );
+    let industrial = load_building_set(
+        &manifest.industrial,
+        &asset_server,
+        &mut materials,
+        &mesh_assets,
     );
 
-    let high_density = {
-        let materials = ["colormap", "variation-a", "variation-b"]
-            .iter()
-            .map(|variation| {
-                materials.add(StandardMaterial {
-                    base_color_texture: Some(asset_server.load(format!(
-                        "{base_url}/city-kit-commercial/Textures/{variation}.png"
-                    ))),
-                    ..Default::default()
-                })
-            })
-            .collect::<Vec<_>>();
+    let ground_tiles = load_ground_tiles(&asset_server, &mut materials, &mut mesh_assets);
 
-        let mut meshes = ["a", "b", "c", "d", "e"]
-            .iter()
-            .map(|t| {
-                asset_server.load(
-                    GltfAssetLabel::Primitive {
-                        mesh: 0,
-                        primitive: 0,
-                    }
-                    .from_asset(format!(
-                        "{base_url}/city-kit-commercial/building-skyscraper-{t}.glb"
-                    )),
-                )
-            })
-            .collect::<Vec<_>>();
-        meshes.extend(["m", "l"].iter().map(|t| {
-            asset_server.load(
-                GltfAssetLabel::Primitive {
-                    mesh: 0,
-                    primitive: 0,
-                }
-                .from_asset(format!("{base_url}/city-kit-commercial/building-{t}.glb")),
-            )
-        }));
+    let tree_small = load_scene(&asset_server, AssetPaths::suburban("tree-small.glb"));
+    let tree_large = load_scene(&asset_server, AssetPaths::suburban("tree-large.glb"));
+    let tree_textures = Season::ALL.map(|season| match season {
+        Season::Summer => asset_server.load(AssetPaths::suburban("Textures/colormap.png")),
+        _ => images.reserve_handle(),
+    });
+    // Matches the material of the trees in the kit, only the texture changes with the seasons
+    let tree_materials = tree_textures.each_ref().map(|texture| {
+        materials.add(StandardMaterial {
+            base_color_texture: Some(texture.clone()),
+            perceptual_roughness: 1.0,
+            double_sided: true,
+            cull_mode: None,
+            ..default()
+        })
+    });
 
-        Buildings { meshes, materials }
-    };
+    let path_stones_long = load_scene(&asset_server, AssetPaths::suburban("path-stones-long.glb"));
 
-    let medium_density = {
-        let materials = ["colormap", "variation-a", "variation-b"]
-            .iter()
-            .map(|variation| {
-                materials.add(StandardMaterial {
-                    base_color_texture: Some(asset_server.load(format!(
-                        "{base_url}/city-kit-commercial/Textures/{variation}.png"
-                    ))),
-                    ..Default::default()
-                })
-            })
-            .collect::<Vec<_>>();
-        let meshes = ["a", "b", "c", "d", "f", "g", "h"]
-            .iter()
-            .map(|t| {
-                asset_server.load(
-                    GltfAssetLabel::Primitive {
-                        mesh: 0,
-                        primitive: 0,
-                    }
-                    .from_asset(format!("{base_url}/city-kit-commercial/building-{t}.glb")),
-                )
-            })
-            .collect::<Vec<_>>();
+    let fence = load_scene(&asset_server, AssetPaths::suburban("fence.glb"));
 
-        Buildings { meshes, materials }
-    };
-    let low_density = {
-        let materials = ["colormap", "variation-a", "variation-b", "variation-c"]
-            .iter()
-            .map(|variation| {
-                materials.add(StandardMaterial {
-                    base_color_texture: Some(asset_server.load(format!(
-                        "{base_url}/city-kit-suburban/Textures/{variation}.png"
-                    ))),
-                    ..Default::default()
-                })
-            })
-            .collect::<Vec<_>>();
-        let meshes = ["b", "c", "d", "e", "f", "g", "h", "i", "k", "l", "o", "u"]
-            .iter()
-            .map(|t| {
-                asset_server.load(
-                    GltfAssetLabel::Primitive {
-                        mesh: 0,
-                        primitive: 0,
-                    }
-                    .from_asset(format!(
-                        "{base_url}/city-kit-suburban/building-type-{t}.glb"
-                    )),
-                )
-            })
-            .collect::<Vec<_>>();
+    // There's no bench in the kits so the parks use planters instead
+    let bench = load_scene(&asset_server, AssetPaths::suburban("planter.glb"));
 
-        Buildings { meshes, materials }
-    };
+    // There's no smokestack in the kits either, a brick colored cylinder stands in for it
+    let smokestack = (
+        mesh_assets.add(
+            Mesh::from(Cylinder::new(SMOKESTACK_RADIUS, SMOKESTACK_HEIGHT))
+                .translated_by(Vec3::Y * SMOKESTACK_HEIGHT / 2.0),
+        ),
+        materials.add(StandardMaterial {
+            base_color: Color::srgb_u8(150, 75, 60),
+            perceptual_roughness: 0.9,
+            ..default()
+        }),
+    );
 
-    let ground_tile = {
-        let mesh = asset_server.load(
-            GltfAssetLabel::Primitive {
-                mesh: 0,
-                primitive: 0,
-            }
-            .from_asset(format!("{base_url}/city-kit-roads/tile-low.glb")),
-        );
-        // TODO use this once https://github.com/bevyengine/bevy/pull/22943 is merged
-        // let default_material: Handle<StandardMaterial> = asset_server.load(format!(
-        //     "ground_tile/tile-low.glb#{}/std",
-        //     GltfAssetLabel::DefaultMaterial
-        // ));
-        let white_material = materials.add(StandardMaterial::from_color(WHITE));
-        let grass_material =
-            materials.add(StandardMaterial::from_color(Color::srgb_u8(97, 203, 139)));
-
-        (mesh, white_material, grass_material)
-    };
+    let siren = SirenAssets::new(&mut mesh_assets, &mut materials);
 
-    let tree_small: Handle<Scene> = asset_server.load(
-        GltfAssetLabel::Scene(0).from_asset(format!("{base_url}/city-kit-suburban/tree-small.glb")),
+    // There's no bus or bus stop in the kits, boxes stand in for them
+    let bus = (
+        mesh_assets
+            .add(Mesh::from(Cuboid::from_size(BUS_SIZE)).translated_by(Vec3::Y * BUS_SIZE.y / 2.0)),
+        materials.add(StandardMaterial::from_color(Color::srgb_u8(230, 180, 40))),
     );
-    let tree_large: Handle<Scene> = asset_server.load(
-        GltfAssetLabel::Scene(0).from_asset(format!("{base_url}/city-kit-suburban/tree-large.glb")),
+    let bus_stop = (
+        mesh_assets.add(
+            Mesh::from(Cuboid::from_size(BUS_STOP_SIZE))
+                .translated_by(Vec3::Y * BUS_STOP_SIZE.y / 2.0),
+        ),
+        materials.add(StandardMaterial {
+            base_color: Color::srgba(0.6, 0.75, 0.85, 0.7),
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        }),
     );
 
-    let path_stones_long: Handle<Scene> = asset_server.load(
-        GltfAssetLabel::Scene(0)
-            .from_asset(format!("{base_url}/city-kit-suburban/path-stones-long.glb")),
-    );
-
-    let fence: Handle<Scene> = asset_server.load(
-        GltfAssetLabel::Scene(0).from_asset(format!("{base_url}/city-kit-suburban/fence.glb")),
-    );
+    let pedestrians = {
+        // There's no character kit in the assets so pedestrians are just small colored capsules
+        let mesh = mesh_assets.add(Capsule3d::new(0.015, 0.05));
+        PEDESTRIAN_COLORS
+            .into_iter()
+            .map(|color| {
+                (
+                    mesh.clone(),
+                    materials.add(StandardMaterial::from_color(color)),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
 
     commands.insert_resource(CityAssets {
         cars,
+        emergency_vehicles,
         crossroad,
+        road_junction,
+        road_bend,
+        road_end,
+        roundabout,
         road_straight,
+        road_bridge,
         high_density,
         medium_density,
         low_density,
-        ground_tile,
+        industrial,
+        ground_tiles,
         tree_small,
         tree_large,
+        tree_textures,
+        tree_materials,
         path_stones_long,
         fence,
+        bench,
+        smokestack,
+        siren,
+        bus,
+        bus_stop,
+        pedestrians,
+    });
+}
+
+/// Loads the meshes and textures of a building set from the manifest
+fn load_building_set(
+    set: &BuildingSet,
+    asset_server: &AssetServer,
+    materials: &mut Assets<StandardMaterial>,
+    mesh_assets: &Assets<Mesh>,
+) -> Buildings {
+    let meshes = set
+        .meshes
+        .iter()
+        .map(|building| {
+            let mesh = asset_server.load(
+                GltfAssetLabel::Primitive {
+                    mesh: 0,
+                    primitive: 0,
+                }
+                .from_asset(AssetPaths::kenney(&building.path)),
+            );
+            (mesh, Vec2::from_array(building.footprint))
+        })
+        .collect();
+    let textures = set
+        .materials
+        .iter()
+        .map(|texture| asset_server.load(AssetPaths::kenney(texture)))
+        .collect::<Vec<Handle<Image>>>();
+    let building_materials = textures
+        .iter()
+        .map(|texture| StandardMaterial {
+            base_color_texture: Some(texture.clone()),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+    let tinted_materials = building_materials
+        .iter()
+        .map(|material| {
+            (0..TINT_BUCKETS)
+                .map(|bucket| {
+                    let tinted = StandardMaterial {
+                        base_color: building_tint(bucket),
+                        ..material.clone()
+                    };
+                    BuildingMaterial::new(tinted, materials)
+                })
+                .collect()
+        })
+        .collect();
+    let building_materials = building_materials
+        .into_iter()
+        .map(|material| BuildingMaterial::new(material, materials))
+        .collect();
+    // The kits don't have low poly versions matching their buildings so the far buildings are
+    // plain boxes with the main color of their kit
+    let [r, g, b] = set.lod_color;
+    let lod_material = materials.add(StandardMaterial::from_color(Color::srgb_u8(r, g, b)));
+    Buildings::new(
+        meshes,
+        building_materials,
+        textures,
+        tinted_materials,
+        lod_material,
+        mesh_assets,
+    )
+}
+
+/// Fills the low poly version of each building with a box matching its bounds once the building
+/// mesh is loaded
+pub fn build_lod_meshes(
+    mut events: MessageReader<AssetEvent<Mesh>>,
+    assets: Option<Res<CityAssets>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let Some(assets) = assets else {
+        return;
+    };
+    for event in events.read() {
+        let AssetEvent::Added { id } = event else {
+            continue;
+        };
+        for buildings in assets.building_sets() {
+            let Some(index) = buildings.meshes.iter().position(|mesh| mesh.id() == *id) else {
+                continue;
+            };
+            let Some(aabb) = meshes.get(*id).and_then(Mesh::compute_aabb) else {
+                continue;
+            };
+            let lod_mesh = Mesh::from(Cuboid::from_size(Vec3::from(aabb.half_extents) * 2.0))
+                .translated_by(aabb.center.into());
+            if let Err(err) = meshes.insert(&buildings.lod_meshes[index], lod_mesh) {
+                warn!("Failed to insert the building lod mesh: {err}");
+            }
+        }
+    }
+}
+
+/// Size of the box standing in for a scene that failed to load, about the size of the props of
+/// the kits
+const PLACEHOLDER_SIZE: f32 = 0.5;
+
+/// Logs the buildings and scenes that failed to load and replaces them with a box so the layout
+/// of the city stays intact
+#[allow(clippy::too_many_arguments)]
+pub fn replace_failed_assets(
+    mut failures: MessageReader<UntypedAssetLoadFailedEvent>,
+    assets: Option<Res<CityAssets>>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut scenes: ResMut<Assets<Scene>>,
+    mut placeholders: ResMut<PlaceholderAssets>,
+    mut placeholder: Local<Option<(Handle<Mesh>, Handle<StandardMaterial>)>>,
+) {
+    let Some(assets) = assets else {
+        return;
+    };
+    for failure in failures.read() {
+        error!(
+            "Failed to load {}, using a placeholder instead: {}",
+            failure.path, failure.error
+        );
+        // The failure is reported on the glTF file, the handles point to an asset inside of it
+        let is_failed = |id: UntypedAssetId| {
+            asset_server
+                .get_path(id)
+                .is_some_and(|path| path.without_label() == failure.path.without_label())
+        };
+
+        for buildings in assets.building_sets() {
+            for (mesh, footprint) in buildings.meshes.iter().zip(&buildings.footprints) {
+                if !is_failed(mesh.id().untyped()) {
+                    continue;
+                }
+                // The height of the building is unknown, a cube matching its footprint is
+                // close enough
+                let size = Vec3::new(footprint.x, footprint.x.max(footprint.y), footprint.y) * 2.0;
+                let placeholder_mesh =
+                    Mesh::from(Cuboid::from_size(size)).translated_by(Vec3::Y * size.y / 2.0);
+                // The lod mesh is built from this one by `build_lod_meshes`
+                if let Err(err) = meshes.insert(mesh, placeholder_mesh) {
+                    warn!("Failed to insert the placeholder building mesh: {err}");
+                }
+                placeholders.0.insert(mesh.id().untyped());
+            }
+        }
+
+        for scene in assets.scenes() {
+            if !is_failed(scene.id().untyped()) {
+                continue;
+            }
+            let (mesh, material) = placeholder
+                .get_or_insert_with(|| {
+                    let mesh = Mesh::from(Cuboid::from_length(PLACEHOLDER_SIZE))
+                        .translated_by(Vec3::Y * PLACEHOLDER_SIZE / 2.0);
+                    (
+                        meshes.add(mesh),
+                        // Magenta so the missing assets stand out
+                        materials.add(StandardMaterial::from_color(Color::srgb(1.0, 0.0, 1.0))),
+                    )
+                })
+                .clone();
+            let mut world = World::new();
+            world.spawn((Mesh3d(mesh), MeshMaterial3d(material), Transform::default()));
+            if let Err(err) = scenes.insert(scene, Scene::new(world)) {
+                warn!("Failed to insert the placeholder scene: {err}");
+            }
+            placeholders.0.insert(scene.id().untyped());
+        }
+    }
+}
+
+fn load_ground_tiles(
+    asset_server: &AssetServer,
+    materials: &mut Assets<StandardMaterial>,
+    mesh_assets: &mut Assets<Mesh>,
+) -> GroundTiles {
+    let mesh = asset_server.load(
+        GltfAssetLabel::Primitive {
+            mesh: 0,
+            primitive: 0,
+        }
+        .from_asset(AssetPaths::road("tile-low.glb")),
+    );
+    // TODO use this once https://github.com/bevyengine/bevy/pull/22943 is merged
+    // let default_material: Handle<StandardMaterial> = asset_server.load(format!(
+    //     "ground_tile/tile-low.glb#{}/std",
+    //     GltfAssetLabel::DefaultMaterial
+    // ));
+
+    // The kit textures are color palettes, the tile samples the gray of the pavement from it and
+    // the base color tints it to tell the sidewalks and the asphalt apart
+    let colormap: Handle<Image> = asset_server.load(AssetPaths::road("Textures/colormap.png"));
+    let sidewalk_material = materials.add(StandardMaterial {
+        base_color: Color::srgb_u8(235, 235, 230),
+        base_color_texture: Some(colormap.clone()),
+        perceptual_roughness: 0.9,
+        ..default()
+    });
+    let asphalt_material = materials.add(StandardMaterial {
+        base_color: Color::srgb_u8(120, 120, 125),
+        base_color_texture: Some(colormap),
+        perceptual_roughness: 0.8,
+        ..default()
     });
+    let grass_material = materials.add(StandardMaterial::from_color(Season::Summer.grass_color()));
+    let water_material = materials.add(StandardMaterial {
+        base_color: Color::srgb_u8(64, 140, 200),
+        perceptual_roughness: 0.1,
+        ..default()
+    });
+
+    // The sidewalks are as high as the curb of the road pieces. The box doesn't have the uvs
+    // of the kit so it uses a plain color instead of the colormap
+    let sidewalk = (
+        mesh_assets.add(
+            Mesh::from(Cuboid::new(1.0, SIDEWALK_HEIGHT, 1.0))
+                .translated_by(Vec3::Y * SIDEWALK_HEIGHT / 2.0),
+        ),
+        materials.add(StandardMaterial {
+            base_color: Color::srgb_u8(200, 200, 195),
+            perceptual_roughness: 0.9,
+            ..default()
+        }),
+    );
+
+    GroundTiles {
+        mesh,
+        sidewalk,
+        grass_material,
+        sidewalk_material,
+        asphalt_material,
+        water_material,
+    }
 }
@@ -0,0 +1,143 @@
+use bevy::{
+    light::{
+        Atmosphere, AtmosphereEnvironmentMapLight,
+        atmosphere::{Falloff, PhaseFunction, ScatteringMedium, ScatteringTerm},
+    },
+    pbr::AtmosphereSettings,
+    prelude::*,
+    ui::Checked,
+    ui_widgets::ValueChange,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+/// Color of the sky when the atmosphere is disabled
+const FALLBACK_SKY_COLOR: Color = Color::srgb(0.55, 0.72, 0.9);
+/// Brightness of the ambient light replacing the lighting from the atmosphere when it's
+/// disabled, without it the shadows are pitch black
+const FALLBACK_AMBIENT_BRIGHTNESS: f32 = 1500.0;
+
+/// The look of the sky, selected from the settings
+#[derive(Component, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum AtmospherePreset {
+    #[default]
+    Earthlike,
+    /// Earth with a lot more aerosols, the distance fades into a white haze
+    Hazy,
+    /// A thin atmosphere filled with dust that absorbs the blue light
+    Martian,
+}
+
+impl AtmospherePreset {
+    pub const ALL: [AtmospherePreset; 3] = [
+        AtmospherePreset::Earthlike,
+        AtmospherePreset::Hazy,
+        AtmospherePreset::Martian,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AtmospherePreset::Earthlike => "Earthlike",
+            AtmospherePreset::Hazy => "Hazy",
+            AtmospherePreset::Martian => "Martian",
+        }
+    }
+
+    /// The medium referenced by the [`Atmosphere`] of the camera
+    pub fn medium(self) -> ScatteringMedium {
+        let earthlike = ScatteringMedium::default();
+        match self {
+            AtmospherePreset::Earthlike => earthlike,
+            AtmospherePreset::Hazy => {
+                let mut medium = earthlike.with_label("hazy_atmosphere");
+                // The second term of the earthlike medium is the Mie scattering of the aerosols
+                if let Some(aerosols) = medium.terms.get_mut(1) {
+                    aerosols.scattering *= 20.0;
+                    aerosols.absorption *= 2.0;
+                }
+                medium
+            }
+            AtmospherePreset::Martian => {
+                // Keep the molecular scattering from earth but much thinner, the dust does most
+                // of the work
+                let mut gas = earthlike.terms[0].clone();
+                gas.scattering *= 0.02;
+                ScatteringMedium::new(
+                    earthlike.falloff_resolution,
+                    earthlike.phase_resolution,
+                    [
+                        gas,
+                        ScatteringTerm {
+                            absorption: Vec3::new(1.0e-6, 4.0e-6, 12.0e-6),
+                            scattering: Vec3::new(10.0e-6, 7.0e-6, 4.0e-6),
+                            falloff: Falloff::Exponential { scale: 11.0 / 60.0 },
+                            phase: PhaseFunction::Mie { asymmetry: 0.65 },
+                        },
+                    ],
+                )
+                .with_label("martian_atmosphere")
+            }
+        }
+    }
+}
+
+/// Swaps the medium of the atmosphere when a preset is picked in the settings
+pub fn select_atmosphere_preset(
+    change: On<ValueChange<Entity>>,
+    presets: Query<(Entity, &AtmospherePreset)>,
+    mut commands: Commands,
+    mut settings: ResMut<Settings>,
+    mut scattering_mediums: ResMut<Assets<ScatteringMedium>>,
+    mut atmospheres: Query<&mut Atmosphere>,
+) {
+    for (radio, preset) in &presets {
+        if radio != change.value {
+            commands.entity(radio).remove::<Checked>();
+            continue;
+        }
+        commands.entity(radio).insert(Checked);
+        settings.atmosphere_preset = *preset;
+        let medium = scattering_mediums.add(preset.medium());
+        for mut atmosphere in &mut atmospheres {
+            atmosphere.medium = medium.clone();
+        }
+    }
+}
+
+/// Adds the atmosphere to the camera, or removes it when it's disabled in the settings
+///
+/// The atmosphere is one of the most expensive parts of the frame on weaker hardware. Without
+/// it the sky is a plain color and an ambient light stands in for the light it bounces around.
+pub fn apply_atmosphere(
+    commands: &mut Commands,
+    camera: Entity,
+    settings: &Settings,
+    scattering_mediums: &mut Assets<ScatteringMedium>,
+) {
+    if settings.atmosphere_enabled {
+        commands.entity(camera).insert((
+            Atmosphere::earthlike(scattering_mediums.add(settings.atmosphere_preset.medium())),
+            AtmosphereSettings {
+                scene_units_to_m: settings.fog_density,
+                ..default()
+            },
+            // Enables the atmosphere to drive reflections and ambient lighting (IBL) for this view
+            AtmosphereEnvironmentMapLight::default(),
+        ));
+        commands.insert_resource(ClearColor(Color::BLACK));
+        commands.insert_resource(GlobalAmbientLight::NONE);
+    } else {
+        commands.entity(camera).remove::<(
+            Atmosphere,
+            AtmosphereSettings,
+            AtmosphereEnvironmentMapLight,
+        )>();
+        commands.insert_resource(ClearColor(FALLBACK_SKY_COLOR));
+        commands.insert_resource(GlobalAmbientLight {
+            color: Color::WHITE,
+            brightness: FALLBACK_AMBIENT_BRIGHTNESS,
+            affects_lightmapped_meshes: true,
+        });
+    }
+}
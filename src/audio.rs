@@ -0,0 +1,206 @@
+use std::{f32::consts::TAU, time::Duration};
+
+use bevy::{
+    audio::{AddAudioSource, AudioSinkPlayback, Decodable, Source, SpatialScale, Volume},
+    camera_controller::free_camera::FreeCamera,
+    prelude::*,
+};
+
+use crate::{settings::Settings, traffic::Car};
+
+/// Only the engines of this many cars closest to the camera are played
+const MAX_ENGINE_EMITTERS: usize = 8;
+const ENGINE_VOLUME: f32 = 0.6;
+const AMBIENT_HUM_VOLUME: f32 = 0.08;
+/// Scales the distances of the city before they attenuate the engines, the city is small so
+/// without it the cars could only be heard right next to the camera
+const AUDIO_SPATIAL_SCALE: f32 = 0.3;
+/// Distance between the ears of the camera
+const EAR_GAP: f32 = 0.3;
+const SAMPLE_RATE: u32 = 44_100;
+
+const ENGINE_SOUND: SynthSound = SynthSound {
+    frequency: 45.0,
+    noise: 0.2,
+};
+const AMBIENT_HUM_SOUND: SynthSound = SynthSound {
+    frequency: 60.0,
+    noise: 0.8,
+};
+
+/// Plays the engines of the cars closest to the camera along with the hum of the city when
+/// audio is enabled in the settings
+pub struct TrafficAudioPlugin;
+
+impl Plugin for TrafficAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<SynthSound>()
+            .add_systems(Update, update_traffic_audio);
+    }
+}
+
+/// A looping sound synthesized on the fly since there are no sounds in the kits
+#[derive(Asset, TypePath, Clone, Copy)]
+pub struct SynthSound {
+    /// Frequency of the hum, in Hz
+    pub frequency: f32,
+    /// How much low rumbling noise is mixed in, from 0 for a pure hum to 1 for only noise
+    pub noise: f32,
+}
+
+impl Decodable for SynthSound {
+    type DecoderItem = f32;
+    type Decoder = SynthDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        SynthDecoder {
+            sound: *self,
+            phase: 0.0,
+            seed: 0x2545_f491,
+            rumble: 0.0,
+        }
+    }
+}
+
+/// Endless samples of a [`SynthSound`]
+pub struct SynthDecoder {
+    sound: SynthSound,
+    /// Phase of the hum, in cycles
+    phase: f32,
+    /// State of the xorshift generating the noise
+    seed: u32,
+    /// The noise smoothed so only its low frequencies remain
+    rumble: f32,
+}
+
+impl Iterator for SynthDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.phase = (self.phase + self.sound.frequency / SAMPLE_RATE as f32).fract();
+        // A few harmonics give the hum the buzz of an engine instead of a clean tone
+        let hum = (1..=4)
+            .map(|harmonic| (self.phase * TAU * harmonic as f32).sin() / harmonic as f32)
+            .sum::<f32>()
+            * 0.5;
+
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        let white = self.seed as f32 / u32::MAX as f32 * 2.0 - 1.0;
+        self.rumble += (white - self.rumble) * 0.02;
+
+        Some(hum * (1.0 - self.sound.noise) + self.rumble * 4.0 * self.sound.noise)
+    }
+}
+
+impl Source for SynthDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Plays the engine of the car it's moved onto each frame
+#[derive(Component)]
+struct EngineEmitter;
+
+#[derive(Component)]
+struct AmbientHum;
+
+/// Filter for every entity playing a sound
+type AudioEntity = Or<(With<EngineEmitter>, With<AmbientHum>)>;
+
+/// Starts or stops the audio when it's toggled in the settings, and moves the engine emitters
+/// onto the cars closest to the camera
+///
+/// There can be thousands of cars so instead of each car playing its engine, a few emitters are
+/// moved to the closest ones. The emitters left without a car are silenced.
+fn update_traffic_audio(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut sounds: ResMut<Assets<SynthSound>>,
+    camera: Option<Single<(Entity, &GlobalTransform), With<FreeCamera>>>,
+    cars: Query<(&GlobalTransform, &InheritedVisibility), With<Car>>,
+    mut emitters: Query<(&mut Transform, Option<&mut SpatialAudioSink>), With<EngineEmitter>>,
+    audio: Query<Entity, AudioEntity>,
+) {
+    let Some(camera) = camera else {
+        return;
+    };
+    let (camera, camera_transform) = camera.into_inner();
+    if !settings.audio_enabled {
+        if !audio.is_empty() {
+            for entity in &audio {
+                commands.entity(entity).despawn();
+            }
+            commands.entity(camera).remove::<SpatialListener>();
+        }
+        return;
+    }
+    if audio.is_empty() {
+        commands
+            .entity(camera)
+            .insert(SpatialListener::new(EAR_GAP));
+        let engine = sounds.add(ENGINE_SOUND);
+        for _ in 0..MAX_ENGINE_EMITTERS {
+            commands.spawn((
+                AudioPlayer(engine.clone()),
+                PlaybackSettings::LOOP
+                    .with_spatial(true)
+                    .with_spatial_scale(SpatialScale::new(AUDIO_SPATIAL_SCALE))
+                    .with_volume(Volume::SILENT),
+                Transform::default(),
+                EngineEmitter,
+            ));
+        }
+        commands.spawn((
+            AudioPlayer(sounds.add(AMBIENT_HUM_SOUND)),
+            PlaybackSettings::LOOP.with_volume(Volume::Linear(AMBIENT_HUM_VOLUME)),
+            AmbientHum,
+        ));
+        return;
+    }
+
+    let camera_position = camera_transform.translation();
+    let mut closest = cars
+        .iter()
+        .filter(|(_, visibility)| visibility.get())
+        .map(|(transform, _)| transform.translation())
+        .collect::<Vec<_>>();
+    let by_distance = |a: &Vec3, b: &Vec3| {
+        a.distance_squared(camera_position)
+            .total_cmp(&b.distance_squared(camera_position))
+    };
+    if closest.len() > MAX_ENGINE_EMITTERS {
+        closest.select_nth_unstable_by(MAX_ENGINE_EMITTERS, by_distance);
+        closest.truncate(MAX_ENGINE_EMITTERS);
+    }
+
+    for (i, (mut transform, sink)) in emitters.iter_mut().enumerate() {
+        let volume = match closest.get(i) {
+            Some(car) => {
+                transform.translation = *car;
+                Volume::Linear(ENGINE_VOLUME)
+            }
+            None => Volume::SILENT,
+        };
+        // The sink is only added once the sound starts playing
+        if let Some(mut sink) = sink
+            && sink.volume() != volume
+        {
+            sink.set_volume(volume);
+        }
+    }
+}
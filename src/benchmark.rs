@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+use bevy::{diagnostic::FrameCount, prelude::*};
+
+use crate::{city::CityGenerated, generate_city::CityConfig, stats::SceneStats};
+
+/// Number of frames to render after the city is generated before reporting, the first frames are
+/// a lot slower than the rest
+const BENCHMARK_FRAMES: u32 = 5;
+
+/// Measures how long it takes to load the assets and spawn the city and logs it along with the
+/// number of spawned entities
+pub struct BenchmarkPlugin {
+    /// Exits the app once the results are logged
+    pub exit: bool,
+}
+
+#[derive(Resource)]
+struct Benchmark {
+    start: Instant,
+    /// Time spent loading the assets and spawning the city
+    startup: Option<Duration>,
+    /// The frame the city was generated on
+    startup_frame: u32,
+    exit: bool,
+}
+
+impl Plugin for BenchmarkPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Benchmark {
+            start: Instant::now(),
+            startup: None,
+            startup_frame: 0,
+            exit: self.exit,
+        })
+        .add_systems(PreStartup, start_benchmark)
+        .add_observer(end_startup_benchmark)
+        .add_systems(Update, report_benchmark);
+    }
+}
+
+fn start_benchmark(mut benchmark: ResMut<Benchmark>) {
+    benchmark.start = Instant::now();
+}
+
+/// The city is generated once its assets are loaded, this is triggered after the commands
+/// spawning it so it measures the loading along with `setup_city`
+fn end_startup_benchmark(
+    _: On<CityGenerated>,
+    mut benchmark: ResMut<Benchmark>,
+    frames: Res<FrameCount>,
+) {
+    if benchmark.startup.is_none() {
+        benchmark.startup = Some(benchmark.start.elapsed());
+        benchmark.startup_frame = frames.0;
+    }
+}
+
+fn report_benchmark(
+    benchmark: Res<Benchmark>,
+    frames: Res<FrameCount>,
+    config: Res<CityConfig>,
+    stats: Res<SceneStats>,
+    mut app_exit: MessageWriter<AppExit>,
+) {
+    let Some(startup) = benchmark.startup else {
+        return;
+    };
+    if frames.0 != benchmark.startup_frame + BENCHMARK_FRAMES {
+        return;
+    }
+
+    info!(
+        "Benchmark seed: {} size: {}\n\
+        Startup: {:.2?}\n\
+        First {BENCHMARK_FRAMES} frames: {:.2?}\n\
+        Cars: {} Parked Cars: {} Low Density: {} Medium Density: {} Skyscrapers: {} \
+        Road Segments: {} Trees: {} Pedestrians: {} Parks: {}",
+        config.seed,
+        config.size,
+        startup,
+        benchmark.start.elapsed() - startup,
+        stats.cars_spawned,
+        stats.parked_cars,
+        stats.low_density_buildings,
+        stats.medium_density_buildings,
+        stats.skyscrapers,
+        stats.road_segments,
+        stats.trees,
+        stats.pedestrians,
+        stats.parks,
+    );
+
+    if benchmark.exit {
+        app_exit.write(AppExit::Success);
+    }
+}
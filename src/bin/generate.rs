@@ -0,0 +1,78 @@
+use argh::FromArgs;
+use bevy::prelude::*;
+use bevy_city::{
+    city::{CityGenerated, CityPlugin},
+    digest::CityDigest,
+    generate_city::CityConfig,
+    rng::RngKind,
+    stats::SceneStats,
+};
+
+#[derive(FromArgs)]
+/// Generates a city without a window or any rendering and prints its stats as JSON
+struct Args {
+    /// seed
+    #[argh(option, default = "42")]
+    seed: u64,
+
+    /// size
+    #[argh(option, default = "30")]
+    size: u32,
+
+    /// rng used to generate the city, `small` or `chacha`
+    #[argh(option, default = "RngKind::Small")]
+    rng: RngKind,
+
+    /// print a digest of the spawn decisions instead of the stats, to compare the cities
+    /// generated on different machines
+    #[argh(switch)]
+    digest: bool,
+}
+
+fn main() {
+    let args: Args = argh::from_env();
+
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        CityPlugin {
+            config: CityConfig {
+                seed: args.seed,
+                rng_kind: args.rng,
+                size: args.size,
+                ..default()
+            },
+            headless: true,
+            ..default()
+        },
+    ));
+    if args.digest {
+        app.add_observer(print_digest);
+    } else {
+        app.add_observer(print_stats);
+    }
+    app.run();
+}
+
+/// Prints the stats once the city is generated and exits
+///
+/// The stats are recounted when [`bevy_city::city::CityRegenerated`] is triggered, which
+/// happens right before [`CityGenerated`].
+fn print_stats(_: On<CityGenerated>, stats: Res<SceneStats>, mut app_exit: MessageWriter<AppExit>) {
+    match serde_json::to_string_pretty(&*stats) {
+        Ok(json) => {
+            println!("{json}");
+            app_exit.write(AppExit::Success);
+        }
+        Err(err) => {
+            eprintln!("Failed to serialize the stats: {err}");
+            app_exit.write(AppExit::error());
+        }
+    }
+}
+
+/// Prints the digest of the city once it's generated and exits
+fn print_digest(_: On<CityGenerated>, digest: CityDigest, mut app_exit: MessageWriter<AppExit>) {
+    println!("{:016x}", digest.digest());
+    app_exit.write(AppExit::Success);
+}
@@ -0,0 +1,134 @@
+use bevy::{
+    camera_controller::free_camera::{FreeCamera, FreeCameraState},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{camera::sync_free_camera_rotation, settings::config_file_path};
+
+const BOOKMARKS_FILE_NAME: &str = "camera_bookmarks.ron";
+
+/// The key used to jump to each bookmark slot, hold shift to save to it instead
+const BOOKMARK_KEYS: [KeyCode; 10] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::Digit0,
+];
+
+/// A saved camera pose
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct CameraBookmark {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+}
+
+impl From<&Transform> for CameraBookmark {
+    fn from(transform: &Transform) -> Self {
+        Self {
+            translation: transform.translation.to_array(),
+            rotation: transform.rotation.to_array(),
+        }
+    }
+}
+
+impl CameraBookmark {
+    pub fn to_transform(self) -> Transform {
+        Transform::from_translation(Vec3::from_array(self.translation))
+            .with_rotation(Quat::from_array(self.rotation))
+    }
+}
+
+/// Camera poses saved with the number keys
+///
+/// They are stored in a RON file so they survive restarts
+#[derive(Resource, Serialize, Deserialize, Default)]
+pub struct CameraBookmarks {
+    pub slots: [Option<CameraBookmark>; 10],
+}
+
+impl CameraBookmarks {
+    /// Loads the bookmarks saved by a previous run, if any
+    pub fn load() -> Self {
+        let Some(path) = config_file_path(BOOKMARKS_FILE_NAME) else {
+            return Self::default();
+        };
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        match ron::from_str(&contents) {
+            Ok(bookmarks) => bookmarks,
+            Err(err) => {
+                warn!("Failed to parse {}: {err}", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = config_file_path(BOOKMARKS_FILE_NAME) else {
+            return;
+        };
+        let contents = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => contents,
+            Err(err) => {
+                warn!("Failed to serialize camera bookmarks: {err}");
+                return;
+            }
+        };
+        if let Err(err) = std::fs::write(&path, contents) {
+            warn!("Failed to save {}: {err}", path.display());
+        }
+    }
+}
+
+/// Marker for the text listing the filled bookmark slots
+#[derive(Component)]
+pub struct BookmarksText;
+
+/// Jumps to a bookmark with the number keys or saves the camera to it when holding shift
+pub fn handle_bookmark_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut bookmarks: ResMut<CameraBookmarks>,
+    camera: Single<(&mut Transform, &mut FreeCameraState), With<FreeCamera>>,
+) {
+    let Some(slot) = BOOKMARK_KEYS.iter().position(|key| keys.just_pressed(*key)) else {
+        return;
+    };
+    let (mut transform, mut state) = camera.into_inner();
+
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        bookmarks.slots[slot] = Some(CameraBookmark::from(&*transform));
+        bookmarks.save();
+    } else if let Some(bookmark) = bookmarks.slots[slot] {
+        *transform = bookmark.to_transform();
+        sync_free_camera_rotation(&mut state, &transform);
+    }
+}
+
+pub fn update_bookmarks_text(
+    bookmarks: Res<CameraBookmarks>,
+    mut text: Single<&mut Text, With<BookmarksText>>,
+) {
+    if !bookmarks.is_changed() {
+        return;
+    }
+    text.0 = bookmarks_label(&bookmarks);
+}
+
+pub fn bookmarks_label(bookmarks: &CameraBookmarks) -> String {
+    let slots = bookmarks
+        .slots
+        .iter()
+        .zip(["1", "2", "3", "4", "5", "6", "7", "8", "9", "0"])
+        .map(|(slot, key)| if slot.is_some() { key } else { "-" })
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("Bookmarks: {slots}")
+}
@@ -0,0 +1,169 @@
+use bevy::prelude::*;
+use rand::RngExt;
+
+use crate::{
+    assets::CityAssets,
+    generate_city::{SpawnInfo, block_offset},
+    placement::{ROAD_HALF_WIDTH, SIDEWALK_WIDTH},
+    settings::Settings,
+    sim_time::SimTime,
+    traffic::{CarLane, Road, TrafficInterpolation},
+};
+
+/// Chance for a medium density block to have a bus driving around its roads
+pub const BUS_ROUTE_CHANCE: f64 = 0.3;
+const BUS_SPEED: f32 = 1.2;
+/// How long the buses wait at each stop, in seconds
+const BUS_DWELL_TIME: f32 = 3.0;
+/// Size of the box standing in for a bus, about twice as long as a car
+pub const BUS_SIZE: Vec3 = Vec3::new(0.18, 0.16, 0.5);
+/// Size of the shelter of a bus stop
+pub const BUS_STOP_SIZE: Vec3 = Vec3::new(0.25, 0.15, 0.06);
+
+/// A bus driving its route over several roads, one lane after the other
+///
+/// The roads of a route belong to the same block so they share the same transform, the bus is a
+/// child of the first one and its position along any of them is in the same space.
+#[derive(Component)]
+pub struct Bus {
+    /// The roads driven one after the other along with the lane driven on each of them, the bus
+    /// goes back to the first one after the last one
+    pub route: Vec<(Entity, CarLane)>,
+    /// Index of the road of the route the bus is on
+    pub leg: usize,
+    /// Distance from where the bus entered the lane of its current road
+    pub distance_traveled: f32,
+    /// Time left to wait at the stop the bus is at, 0 while driving
+    pub dwell: f32,
+}
+
+/// A stop the buses driving along `lane` of `road` wait at
+#[derive(Component)]
+pub struct BusStop {
+    pub road: Entity,
+    pub lane: CarLane,
+    /// Distance along the lane of the spot the buses stop at
+    pub distance: f32,
+}
+
+/// Spawns a bus driving both lanes of each road, with a stop on the lanes along the block
+///
+/// `roads` are the straight roads of the block along with their entity.
+pub fn spawn_bus_route<R: RngExt>(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    rng: &mut R,
+    roads: &[(Entity, Road)],
+) {
+    let Some((first_road, road)) = roads.first() else {
+        return;
+    };
+    let center = block_offset(IVec2::ONE) / 2.0;
+    let mut route = Vec::new();
+    for (road_entity, road) in roads {
+        // Leave the crossroad on the forward lane and come back to it on the other one
+        let mut lanes = road.lanes;
+        lanes.sort_by(|a, b| b.dir.total_cmp(&a.dir));
+        route.extend(lanes.map(|lane| (*road_entity, lane)));
+
+        // The stop is on the sidewalk of the block, next to the lane on its side
+        let Some(lane) = lanes
+            .into_iter()
+            .find(|lane| lane.offset.dot(center - road.start.midpoint(road.end)) > 0.0)
+        else {
+            continue;
+        };
+        let distance = rng.random_range(0.3..0.7) * road.length();
+        let outward = lane.offset.normalize();
+        let curb = ROAD_HALF_WIDTH - lane.offset.length() + SIDEWALK_WIDTH / 2.0;
+        let translation = road.lane_position(lane, distance) + outward * curb;
+        commands.commands_mut().entity(*road_entity).with_child((
+            Mesh3d(assets.bus_stop.0.clone()),
+            MeshMaterial3d(assets.bus_stop.1.clone()),
+            Transform::from_translation(translation)
+                .looking_to(road.lane_direction(lane, distance), Vec3::Y),
+            BusStop {
+                road: *road_entity,
+                lane,
+                distance,
+            },
+            SpawnInfo("Bus stop"),
+            Pickable::default(),
+        ));
+    }
+
+    let lane = route[0].1;
+    commands.commands_mut().entity(*first_road).with_child((
+        Mesh3d(assets.bus.0.clone()),
+        MeshMaterial3d(assets.bus.1.clone()),
+        Transform::from_translation(road.lane_position(lane, 0.0)).with_rotation(
+            Quat::from_rotation_arc(Vec3::Z, road.lane_direction(lane, 0.0)),
+        ),
+        Bus {
+            route,
+            leg: 0,
+            distance_traveled: 0.0,
+            dwell: 0.0,
+        },
+        SpawnInfo("Bus"),
+        Pickable::default(),
+    ));
+}
+
+/// Drives the buses along their route, waiting at each stop they reach
+pub fn simulate_buses(
+    settings: Res<Settings>,
+    roads: Query<&Road>,
+    stops: Query<&BusStop>,
+    mut buses: Query<(&mut Bus, &mut Transform, Option<&mut TrafficInterpolation>)>,
+    time: Res<SimTime>,
+) {
+    if !settings.simulate_cars {
+        return;
+    }
+    for (mut bus, mut transform, interpolation) in &mut buses {
+        let (mut road_entity, mut lane) = bus.route[bus.leg];
+        let Ok(mut road) = roads.get(road_entity) else {
+            continue;
+        };
+
+        let mut teleported = false;
+        if bus.dwell > 0.0 {
+            bus.dwell = (bus.dwell - time.delta_secs()).max(0.0);
+        } else {
+            let previous = bus.distance_traveled;
+            let distance_traveled =
+                previous + BUS_SPEED * settings.car_speed_multiplier * time.delta_secs();
+            let stop = stops.iter().find(|stop| {
+                stop.road == road_entity
+                    && stop.lane == lane
+                    && previous < stop.distance
+                    && stop.distance <= distance_traveled
+            });
+            if let Some(stop) = stop {
+                bus.distance_traveled = stop.distance;
+                bus.dwell = BUS_DWELL_TIME;
+            } else if distance_traveled > road.length() {
+                // Move on to the next road of the route
+                bus.leg = (bus.leg + 1) % bus.route.len();
+                bus.distance_traveled = 0.0;
+                (road_entity, lane) = bus.route[bus.leg];
+                let Ok(next_road) = roads.get(road_entity) else {
+                    continue;
+                };
+                road = next_road;
+                teleported = true;
+            } else {
+                bus.distance_traveled = distance_traveled;
+            }
+        }
+
+        let translation = road.lane_position(lane, bus.distance_traveled);
+        match interpolation {
+            Some(mut interpolation) => interpolation.advance(translation, teleported),
+            None => transform.translation = translation,
+        }
+        transform.rotation =
+            Quat::from_rotation_arc(Vec3::Z, road.lane_direction(lane, bus.distance_traveled));
+    }
+}
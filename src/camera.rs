@@ -0,0 +1,340 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use bevy::{
+    camera_controller::free_camera::{FreeCamera, FreeCameraState},
+    input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll, MouseScrollUnit},
+    prelude::*,
+    ui_widgets::Activate,
+};
+
+use crate::{
+    city::CityGenerated,
+    generate_city::{CityBounds, CityConfig, city_bounds},
+    settings::{PointerOverSettings, Settings},
+};
+
+/// How the camera is currently controlled
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CameraMode {
+    /// Fly around using the [`FreeCamera`] controls
+    #[default]
+    Free,
+    /// Drag the mouse to orbit around the [`OrbitFocus`] and scroll to zoom
+    Orbit,
+    /// Fly along the [`CameraTour`] until it's done or Escape is pressed
+    Tour,
+}
+
+/// The point the camera orbits around in [`CameraMode::Orbit`]
+///
+/// Defaults to the crossroad of the block at the origin
+#[derive(Resource, Default)]
+pub struct OrbitFocus(pub Vec3);
+
+const ORBIT_SENSITIVITY: f32 = 0.005;
+const ZOOM_SENSITIVITY: f32 = 0.1;
+const MIN_ORBIT_DISTANCE: f32 = 1.0;
+const MAX_ORBIT_DISTANCE: f32 = 300.0;
+/// Keeps the camera from going under the ground or flipping over the focus point
+const MAX_ORBIT_ELEVATION: f32 = FRAC_PI_2 - 0.05;
+const MIN_ORBIT_ELEVATION: f32 = 0.05;
+/// Direction from the center of the city to the camera when framing the whole city
+const FRAMING_DIRECTION: Vec3 = Vec3::new(0.0, 0.6, 0.8);
+/// How much a notch of the mouse wheel changes the speed of the free camera, the speed is
+/// multiplied so it changes as fast when flying slowly through a street as when crossing the city
+const SPEED_SCROLL_SENSITIVITY: f32 = 0.15;
+/// How many pixels of a touchpad or a smooth scrolling wheel count as a notch of the mouse wheel
+const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+const MIN_SPEED_MULTIPLIER: f32 = 0.1;
+const MAX_SPEED_MULTIPLIER: f32 = 20.0;
+/// How far the camera can go past the edges of the city, relative to the radius of the city,
+/// far enough to frame the whole city from the south
+const CAMERA_BOUNDS_PADDING: f32 = 2.5;
+/// Keeps the camera above the ground plane
+const MIN_CAMERA_HEIGHT: f32 = 0.1;
+/// Time in seconds to fly the whole tour
+const TOUR_DURATION: f32 = 60.0;
+/// Number of control points of the tour, spread on a loop around the center of the city
+const TOUR_POINTS: usize = 8;
+
+/// Only lets the free camera react to inputs in [`CameraMode::Free`] and when the pointer isn't
+/// over the settings
+pub fn update_free_camera_state(
+    mode: Res<CameraMode>,
+    pointer_over_settings: Res<PointerOverSettings>,
+    camera: Single<(&mut FreeCameraState, &Transform)>,
+) {
+    let (mut state, transform) = camera.into_inner();
+    if mode.is_changed() && *mode == CameraMode::Free {
+        // Start from where the orbit left the camera
+        sync_free_camera_rotation(&mut state, transform);
+    }
+
+    let enabled = *mode == CameraMode::Free && !pointer_over_settings.0;
+    if state.enabled != enabled {
+        state.enabled = enabled;
+    }
+}
+
+/// How many notches the mouse wheel scrolled vertically this frame
+///
+/// Touchpads and smooth scrolling wheels scroll by pixels instead of lines, a single swipe would
+/// otherwise zoom or change the speed all the way at once.
+fn scroll_lines(mouse_scroll: &AccumulatedMouseScroll) -> f32 {
+    match mouse_scroll.unit {
+        MouseScrollUnit::Line => mouse_scroll.delta.y,
+        MouseScrollUnit::Pixel => mouse_scroll.delta.y / PIXELS_PER_SCROLL_LINE,
+    }
+}
+
+/// Shows how fast the free camera flies
+#[derive(Component)]
+pub struct CameraSpeedText;
+
+/// Speeds up or slows down the free camera with the mouse wheel
+///
+/// The wheel handling of the [`FreeCamera`] itself is turned off with its `scroll_factor` so
+/// this is the only thing changing the speed. Like the rest of the free camera controls it's
+/// ignored while the pointer is over the settings so scrolling them doesn't change the speed.
+pub fn adjust_free_camera_speed(
+    mode: Res<CameraMode>,
+    pointer_over_settings: Res<PointerOverSettings>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    mut state: Single<&mut FreeCameraState>,
+) {
+    let lines = scroll_lines(&mouse_scroll);
+    if *mode != CameraMode::Free || pointer_over_settings.0 || lines == 0.0 {
+        return;
+    }
+    state.speed_multiplier = (state.speed_multiplier * (lines * SPEED_SCROLL_SENSITIVITY).exp())
+        .clamp(MIN_SPEED_MULTIPLIER, MAX_SPEED_MULTIPLIER);
+}
+
+pub fn update_camera_speed_text(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    camera: Single<(&FreeCamera, &FreeCameraState)>,
+    mut text: Single<&mut Text, With<CameraSpeedText>>,
+) {
+    let (camera, state) = camera.into_inner();
+    // Holding the run key boosts the speed
+    let speed = if keyboard.pressed(camera.key_run) {
+        camera.run_speed
+    } else {
+        camera.walk_speed
+    } * state.speed_multiplier;
+    let label = format!("Camera Speed: {speed:.1} (x{:.1})", state.speed_multiplier);
+    if text.0 != label {
+        text.0 = label;
+    }
+}
+
+/// The free camera keeps its own yaw and pitch, they need to be synced when something else moves
+/// the camera or it will snap back to its previous orientation
+pub fn sync_free_camera_rotation(state: &mut FreeCameraState, transform: &Transform) {
+    let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+    state.yaw = yaw;
+    state.pitch = pitch;
+}
+
+pub fn orbit_camera(
+    mode: Res<CameraMode>,
+    focus: Res<OrbitFocus>,
+    pointer_over_settings: Res<PointerOverSettings>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    mut camera: Single<&mut Transform, With<FreeCamera>>,
+) {
+    if *mode != CameraMode::Orbit || pointer_over_settings.0 {
+        return;
+    }
+    let focus = focus.0;
+
+    if mouse_buttons.pressed(MouseButton::Left) {
+        let delta = mouse_motion.delta * ORBIT_SENSITIVITY;
+        camera.rotate_around(focus, Quat::from_rotation_y(-delta.x));
+
+        let elevation = (camera.translation - focus).normalize_or(Vec3::Y).y.asin();
+        let target_elevation =
+            (elevation + delta.y).clamp(MIN_ORBIT_ELEVATION, MAX_ORBIT_ELEVATION);
+        let right = camera.right();
+        camera.rotate_around(
+            focus,
+            Quat::from_axis_angle(*right, elevation - target_elevation),
+        );
+    }
+
+    let lines = scroll_lines(&mouse_scroll);
+    if lines != 0.0 {
+        let offset = camera.translation - focus;
+        let distance = (offset.length() * (1.0 - lines * ZOOM_SENSITIVITY))
+            .clamp(MIN_ORBIT_DISTANCE, MAX_ORBIT_DISTANCE);
+        camera.translation = focus + offset.normalize_or(Vec3::Y) * distance;
+    }
+
+    camera.look_at(focus, Vec3::Y);
+}
+
+/// Keeps the camera in a box around the [`CityBounds`] and above the ground when enabled in the
+/// settings, so it can't get lost flying into the empty space around the city
+pub fn clamp_camera(
+    settings: Res<Settings>,
+    bounds: Res<CityBounds>,
+    mut camera: Single<&mut Transform, With<FreeCamera>>,
+) {
+    if !settings.clamp_camera {
+        return;
+    }
+    let padding = Vec3::splat(bounds.radius() * CAMERA_BOUNDS_PADDING);
+    let min = (bounds.min - padding).with_y(MIN_CAMERA_HEIGHT);
+    let max = bounds.max + padding;
+    let clamped = camera.translation.clamp(min, max);
+    if camera.translation != clamped {
+        camera.translation = clamped;
+    }
+}
+
+/// Computes a camera transform that fits the whole grid in view
+///
+/// The camera looks down at the center of the city from the south, far enough for a sphere
+/// around the grid to fit in the vertical field of view.
+pub fn frame_city(config: &CityConfig, fov: f32) -> Transform {
+    let (min, max) = city_bounds(config);
+    let center = (min + max) / 2.0;
+    let radius = (max - min).length() / 2.0;
+    let distance = radius / (fov / 2.0).sin();
+    Transform::from_translation(center + FRAMING_DIRECTION.normalize() * distance)
+        .looking_at(center, Vec3::Y)
+}
+
+/// Frames the whole city once it's generated
+pub fn frame_generated_city(
+    _: On<CityGenerated>,
+    config: Res<CityConfig>,
+    focus: ResMut<OrbitFocus>,
+    camera: Single<(&mut Transform, &mut FreeCameraState, &Projection)>,
+) {
+    reframe(&config, focus, camera.into_inner());
+}
+
+/// Frames the whole city again when pressing Home
+pub fn reframe_camera(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    config: Res<CityConfig>,
+    focus: ResMut<OrbitFocus>,
+    camera: Single<(&mut Transform, &mut FreeCameraState, &Projection)>,
+) {
+    if keyboard.just_pressed(KeyCode::Home) {
+        reframe(&config, focus, camera.into_inner());
+    }
+}
+
+fn reframe(
+    config: &CityConfig,
+    mut focus: ResMut<OrbitFocus>,
+    (mut transform, mut state, projection): (Mut<Transform>, Mut<FreeCameraState>, &Projection),
+) {
+    let fov = match projection {
+        Projection::Perspective(perspective) => perspective.fov,
+        _ => PerspectiveProjection::default().fov,
+    };
+    *transform = frame_city(config, fov);
+    sync_free_camera_rotation(&mut state, &transform);
+    // Orbit around the center of the city
+    let (min, max) = city_bounds(config);
+    focus.0 = (min + max) / 2.0;
+}
+
+/// A loop over the city flown by the camera in [`CameraMode::Tour`]
+#[derive(Resource)]
+pub struct CameraTour {
+    path: CubicCurve<Vec3>,
+    /// Point of the ground the camera looks at
+    target: Vec3,
+    elapsed: f32,
+}
+
+impl CameraTour {
+    pub fn new(config: &CityConfig) -> Self {
+        let (min, max) = city_bounds(config);
+        let path = CubicCardinalSpline::new_catmull_rom(tour_points(min, max))
+            .to_curve_cyclic()
+            .expect("The tour has enough control points to build a curve");
+        Self {
+            path,
+            target: (min + max) / 2.0,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Where the camera is along the tour, `None` once the tour is done
+    fn transform(&self) -> Option<Transform> {
+        let progress = self.elapsed / TOUR_DURATION;
+        if progress >= 1.0 {
+            return None;
+        }
+        let segments = self.path.segments().len() as f32;
+        let t = progress * segments;
+        let position = self.path.position(t);
+        // Look a bit ahead on the ground so the camera turns with the path, the path is a loop
+        // so this wraps around to its start
+        let ahead = self.path.position((t + 0.5) % segments).with_y(0.0);
+        Some(
+            Transform::from_translation(position).looking_at(ahead.lerp(self.target, 0.5), Vec3::Y),
+        )
+    }
+}
+
+/// Control points of a loop around the center of the city that alternates between flying low
+/// over the streets and high above the roofs
+fn tour_points(min: Vec3, max: Vec3) -> Vec<Vec3> {
+    let center = (min + max) / 2.0;
+    let radius = (max - min) * 0.35;
+    (0..TOUR_POINTS)
+        .map(|i| {
+            let angle = TAU * i as f32 / TOUR_POINTS as f32;
+            let height = if i % 2 == 0 { 15.0 } else { 40.0 };
+            center + Vec3::new(angle.cos() * radius.x, height, angle.sin() * radius.z)
+        })
+        .collect()
+}
+
+/// Starts flying the camera along a new [`CameraTour`]
+pub fn start_camera_tour(
+    _activate: On<Activate>,
+    mut commands: Commands,
+    config: Res<CityConfig>,
+    mut mode: ResMut<CameraMode>,
+) {
+    commands.insert_resource(CameraTour::new(&config));
+    *mode = CameraMode::Tour;
+}
+
+/// Moves the camera along the [`CameraTour`], going back to the free camera when the tour is done
+/// or when pressing Escape
+pub fn fly_camera_tour(
+    mut commands: Commands,
+    time: Res<Time>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut mode: ResMut<CameraMode>,
+    tour: Option<ResMut<CameraTour>>,
+    mut camera: Single<&mut Transform, With<FreeCamera>>,
+) {
+    if *mode != CameraMode::Tour {
+        return;
+    }
+    let Some(mut tour) = tour else {
+        *mode = CameraMode::Free;
+        return;
+    };
+
+    tour.elapsed += time.delta_secs();
+    match tour.transform() {
+        Some(transform) if !keyboard.just_pressed(KeyCode::Escape) => **camera = transform,
+        _ => {
+            commands.remove_resource::<CameraTour>();
+            // The free camera syncs its rotation with where the tour left the camera
+            *mode = CameraMode::Free;
+        }
+    }
+}
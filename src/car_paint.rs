@@ -0,0 +1,188 @@
+use bevy::{platform::collections::HashMap, prelude::*, scene::SceneInstanceReady};
+use rand::RngExt;
+
+use crate::generate_city::CityConfig;
+
+/// Colors the bodies of the cars can be painted with, every color is a different material for
+/// each car model so the palette is kept small to keep the cars batched together
+///
+/// The colors are multiplied with the colormap of the kit, the paint of the models is light so
+/// the colors show through.
+const CAR_COLORS: [Color; 8] = [
+    Color::WHITE,
+    Color::srgb(0.72, 0.73, 0.76),
+    Color::srgb(0.3, 0.3, 0.32),
+    Color::srgb(0.75, 0.12, 0.1),
+    Color::srgb(0.15, 0.25, 0.6),
+    Color::srgb(0.2, 0.4, 0.28),
+    Color::srgb(0.85, 0.78, 0.6),
+    Color::srgb(0.45, 0.12, 0.15),
+];
+
+/// Name of the node holding the body of the car models, the wheels are separate nodes
+const BODY_NODE: &str = "body";
+
+/// The index of the color of [`CAR_COLORS`] the body of a car is painted with
+#[derive(Component, Clone, Copy)]
+pub struct CarPaint(pub usize);
+
+/// Picks the paint of a car when the colors of the cars are randomized
+///
+/// This doesn't draw from the rng otherwise so the rest of the city stays the same.
+pub fn random_car_paint<R: RngExt>(config: &CityConfig, rng: &mut R) -> Option<CarPaint> {
+    config
+        .randomize_car_color
+        .then(|| CarPaint(rng.random_range(0..CAR_COLORS.len())))
+}
+
+/// The painted copies of the materials of the car models
+///
+/// Every model of the kit has its own material, it's copied for each color the first time a car
+/// of that model gets it.
+#[derive(Resource, Default)]
+pub struct CarPaints {
+    painted: HashMap<(AssetId<StandardMaterial>, usize), Handle<StandardMaterial>>,
+    /// The material of the model each painted copy was made from
+    original: HashMap<AssetId<StandardMaterial>, Handle<StandardMaterial>>,
+}
+
+impl CarPaints {
+    fn get_or_paint(
+        &mut self,
+        materials: &mut Assets<StandardMaterial>,
+        material: &Handle<StandardMaterial>,
+        CarPaint(color): CarPaint,
+    ) -> Option<Handle<StandardMaterial>> {
+        // A reused car is already painted, its paint is replaced from the material of its model
+        let material = self.original(material);
+        if let Some(painted) = self.painted.get(&(material.id(), color)) {
+            return Some(painted.clone());
+        }
+        let painted = materials.add(StandardMaterial {
+            base_color: CAR_COLORS[color],
+            ..materials.get(&material)?.clone()
+        });
+        self.painted.insert((material.id(), color), painted.clone());
+        self.original.insert(painted.id(), material);
+        Some(painted)
+    }
+
+    /// The material of the model a material was painted from, the material itself when it
+    /// isn't painted
+    fn original(&self, material: &Handle<StandardMaterial>) -> Handle<StandardMaterial> {
+        self.original
+            .get(&material.id())
+            .cloned()
+            .unwrap_or_else(|| material.clone())
+    }
+}
+
+/// The entities with a material making up the body of a car, this is empty until its scene is
+/// spawned
+fn car_body_meshes(car: Entity, children: &Query<&Children>, names: &Query<&Name>) -> Vec<Entity> {
+    children
+        .iter_descendants(car)
+        .filter(|entity| {
+            names
+                .get(*entity)
+                .is_ok_and(|name| name.as_str() == BODY_NODE)
+        })
+        .flat_map(|body| children.iter_descendants(body))
+        .collect()
+}
+
+/// Paints the body of a car, this does nothing until its scene is spawned
+fn paint_car(
+    car: Entity,
+    paint: CarPaint,
+    paints: &mut CarPaints,
+    materials: &mut Assets<StandardMaterial>,
+    children: &Query<&Children>,
+    names: &Query<&Name>,
+    mesh_materials: &mut Query<&mut MeshMaterial3d<StandardMaterial>>,
+) {
+    for entity in car_body_meshes(car, children, names) {
+        let Ok(mut mesh_material) = mesh_materials.get_mut(entity) else {
+            continue;
+        };
+        if let Some(painted) = paints.get_or_paint(materials, &mesh_material.0, paint)
+            && mesh_material.0 != painted
+        {
+            mesh_material.0 = painted;
+        }
+    }
+}
+
+/// Paints the cars once their scene is spawned
+pub fn paint_car_scene(
+    ready: On<SceneInstanceReady>,
+    cars: Query<&CarPaint>,
+    mut paints: ResMut<CarPaints>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    children: Query<&Children>,
+    names: Query<&Name>,
+    mut mesh_materials: Query<&mut MeshMaterial3d<StandardMaterial>>,
+) {
+    let Ok(paint) = cars.get(ready.entity) else {
+        return;
+    };
+    paint_car(
+        ready.entity,
+        *paint,
+        &mut paints,
+        &mut materials,
+        &children,
+        &names,
+        &mut mesh_materials,
+    );
+}
+
+/// Repaints the cars reused from the pool, their scene is already spawned so they don't get a
+/// [`SceneInstanceReady`]
+pub fn repaint_cars(
+    cars: Query<(Entity, &CarPaint), Changed<CarPaint>>,
+    mut paints: ResMut<CarPaints>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    children: Query<&Children>,
+    names: Query<&Name>,
+    mut mesh_materials: Query<&mut MeshMaterial3d<StandardMaterial>>,
+) {
+    for (car, paint) in &cars {
+        paint_car(
+            car,
+            *paint,
+            &mut paints,
+            &mut materials,
+            &children,
+            &names,
+            &mut mesh_materials,
+        );
+    }
+}
+
+/// Gives back the material of their model to the cars that lost their paint, a car released to
+/// the pool loses it and can be reused while the colors of the cars aren't randomized
+pub fn unpaint_cars(
+    mut unpainted: RemovedComponents<CarPaint>,
+    cars: Query<(), Without<CarPaint>>,
+    paints: Res<CarPaints>,
+    children: Query<&Children>,
+    names: Query<&Name>,
+    mut mesh_materials: Query<&mut MeshMaterial3d<StandardMaterial>>,
+) {
+    for car in unpainted.read() {
+        // The car was painted again right after losing its paint
+        if !cars.contains(car) {
+            continue;
+        }
+        for entity in car_body_meshes(car, &children, &names) {
+            let Ok(mut mesh_material) = mesh_materials.get_mut(entity) else {
+                continue;
+            };
+            let original = paints.original(&mesh_material.0);
+            if mesh_material.0 != original {
+                mesh_material.0 = original;
+            }
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use bevy::{camera_controller::free_camera::FreeCamera, prelude::*};
+
+use crate::{
+    assets::{CityAssets, PlaceholderAssets, build_lod_meshes, load_assets, replace_failed_assets},
+    bus::simulate_buses,
+    car_paint::{CarPaints, paint_car_scene, repaint_cars, unpaint_cars},
+    debug_draw::{draw_bounding_boxes, draw_traffic_gizmos},
+    district::{spawn_district_ui, update_district_ui},
+    emergency::flash_sirens,
+    generate_city::{
+        CityBounds, CityConfig, CityRoot, apply_car_density, spawn_city,
+        spawn_pending_block_detail, sync_car_density, update_city_bounds,
+    },
+    heatmap::update_density_heatmap,
+    layout::CityLayout,
+    loading::{AssetsLoaded, spawn_loading_screen, update_loading_screen},
+    pool::{EntityPool, Pooled},
+    season::{apply_season, apply_season_to_tree, build_season_textures},
+    settings::Settings,
+    sim_time::{SimTime, toggle_pause, update_sim_frame_delta, update_sim_time},
+    stats::{SceneStats, recount_stats, spawn_stats_ui, update_stats_ui},
+    traffic::{
+        add_traffic_interpolation, interpolate_traffic, simulate_cars, simulate_pedestrians,
+    },
+    wind::animate_trees,
+};
+
+/// Loads the city assets, spawns the city and simulates the cars driving around it
+///
+/// This doesn't spawn a camera or any lights so it can be added to an app that wants to use its
+/// own camera setup.
+#[derive(Default)]
+pub struct CityPlugin {
+    pub config: CityConfig,
+    /// Spawns this layout instead of generating one from the config
+    pub layout: Option<CityLayout>,
+    /// Generates the city right away without loading any asset or spawning any UI, for apps
+    /// without rendering
+    pub headless: bool,
+}
+
+impl Plugin for CityPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(layout) = &self.layout {
+            app.insert_resource(layout.clone());
+        }
+        app.insert_resource(self.config.clone())
+            .insert_resource(CityBounds::new(&self.config))
+            .init_resource::<Settings>()
+            .init_resource::<SceneStats>()
+            .init_resource::<GenerationProgress>()
+            .init_resource::<SimTime>()
+            .init_resource::<PlaceholderAssets>()
+            .init_resource::<EntityPool>()
+            .add_observer(setup_city)
+            .add_observer(regenerate_city)
+            .add_observer(recount_stats)
+            .add_observer(log_city_generated)
+            .add_systems(PreUpdate, update_sim_frame_delta)
+            .add_systems(
+                Update,
+                (
+                    update_city_bounds,
+                    // The cars already spawned are adjusted before new blocks use the density
+                    (apply_car_density, spawn_pending_block_detail).chain(),
+                ),
+            );
+
+        if self.headless {
+            app.insert_resource(CityAssets::headless()).add_systems(
+                Startup,
+                |mut commands: Commands| {
+                    commands.trigger(AssetsLoaded);
+                },
+            );
+        } else {
+            app.add_systems(
+                Startup,
+                (
+                    load_assets,
+                    spawn_stats_ui,
+                    spawn_district_ui,
+                    spawn_loading_screen,
+                ),
+            )
+            .init_resource::<CarPaints>()
+            .add_observer(apply_season_to_tree)
+            .add_observer(paint_car_scene)
+            .add_systems(
+                Update,
+                (
+                    update_loading_screen,
+                    replace_failed_assets,
+                    build_lod_meshes,
+                    (build_season_textures, apply_season),
+                    (unpaint_cars, repaint_cars),
+                    toggle_pause,
+                    animate_trees,
+                    flash_sirens,
+                    update_stats_ui,
+                    update_district_ui,
+                    draw_traffic_gizmos,
+                    draw_bounding_boxes,
+                    update_density_heatmap,
+                ),
+            );
+        }
+
+        if self.config.deterministic_traffic {
+            app.add_systems(
+                FixedUpdate,
+                (
+                    update_sim_time,
+                    (simulate_cars, simulate_buses, simulate_pedestrians),
+                )
+                    .chain(),
+            )
+            .add_systems(Update, interpolate_traffic)
+            .add_observer(add_traffic_interpolation);
+        } else {
+            app.add_systems(
+                Update,
+                (
+                    update_sim_time,
+                    (simulate_cars, simulate_buses, simulate_pedestrians),
+                )
+                    .chain(),
+            );
+        }
+    }
+}
+
+/// Spawns the city once its assets are loaded
+#[allow(clippy::too_many_arguments)]
+fn setup_city(
+    _: On<AssetsLoaded>,
+    mut commands: Commands,
+    assets: Res<CityAssets>,
+    config: Res<CityConfig>,
+    mut pool: ResMut<EntityPool>,
+    mut progress: ResMut<GenerationProgress>,
+    layout: Option<Res<CityLayout>>,
+    camera: Option<Single<&Transform, With<FreeCamera>>>,
+) {
+    let layout = match layout {
+        Some(layout) => layout.clone(),
+        None => CityLayout::generate(&config),
+    };
+    spawn_city(
+        &mut commands,
+        &assets,
+        &config,
+        &layout,
+        &mut pool,
+        camera.map(Single::into_inner),
+    );
+    start_generation(
+        &mut commands,
+        &config,
+        &mut pool,
+        &mut progress,
+        layout.blocks.len(),
+    );
+}
+
+/// How far the generation of the city got, the details of the blocks are spawned over several
+/// frames when there's a [`CityConfig::spawn_budget`]
+#[derive(Resource, Default)]
+pub struct GenerationProgress {
+    /// Number of blocks with their details spawned, the blocks culled behind the camera count
+    /// as spawned
+    pub spawned_blocks: usize,
+    pub total_blocks: usize,
+    /// Set while some blocks are still waiting for their details
+    pub generating: bool,
+}
+
+/// Starts tracking the generation of a city that was just spawned, or finishes it right away
+/// when the whole city was spawned at once
+///
+/// This triggers [`CityRegenerated`] for the blocks that were just spawned.
+fn start_generation(
+    commands: &mut Commands,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    progress: &mut GenerationProgress,
+    block_count: usize,
+) {
+    *progress = GenerationProgress {
+        spawned_blocks: 0,
+        total_blocks: block_count,
+        generating: true,
+    };
+    if config.spawn_budget == 0 {
+        finish_generation(commands, config, pool, progress);
+    } else {
+        commands.trigger(CityRegenerated);
+    }
+}
+
+/// Triggers [`CityGenerated`] once the details of every block are spawned
+///
+/// The pool is cleared before triggering [`CityRegenerated`] so the stats don't count the pooled
+/// entities the new city didn't reuse.
+pub fn finish_generation(
+    commands: &mut Commands,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    progress: &mut GenerationProgress,
+) {
+    // Whatever wasn't reused by the new city won't be needed anymore
+    pool.clear_pool(commands);
+    progress.spawned_blocks = progress.total_blocks;
+    progress.generating = false;
+    commands.trigger(CityRegenerated);
+    commands.trigger(CityGenerated {
+        block_count: progress.total_blocks,
+        seed: config.seed,
+    });
+}
+
+/// Despawns the city and spawns a new one from the current [`CityConfig`]
+#[derive(Event)]
+pub struct RegenerateCity;
+
+/// Triggered after entities were added to or removed from the city
+///
+/// This is triggered through commands, after the commands spawning and despawning the city, so
+/// the observers see the new content of the city.
+#[derive(Event)]
+pub struct CityRegenerated;
+
+/// Triggered once the city has been generated at startup or regenerated
+///
+/// Like [`CityRegenerated`] this is triggered after the commands spawning the city.
+#[derive(Event)]
+pub struct CityGenerated {
+    pub block_count: usize,
+    pub seed: u64,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn regenerate_city(
+    _: On<RegenerateCity>,
+    mut commands: Commands,
+    city_root: Single<Entity, With<CityRoot>>,
+    assets: Res<CityAssets>,
+    settings: Res<Settings>,
+    mut config: ResMut<CityConfig>,
+    mut pool: ResMut<EntityPool>,
+    mut progress: ResMut<GenerationProgress>,
+    pooled: Query<(Entity, &Pooled), With<ChildOf>>,
+    camera: Option<Single<&Transform, With<FreeCamera>>>,
+) {
+    // Keep the buildings and cars around for the new city to reuse
+    for (entity, pooled) in &pooled {
+        pool.release(&mut commands, entity, pooled.0);
+    }
+    commands.entity(*city_root).despawn();
+
+    sync_car_density(&mut config, &settings);
+    // The loaded layout, if any, doesn't match the new config
+    commands.remove_resource::<CityLayout>();
+    let layout = CityLayout::generate(&config);
+    spawn_city(
+        &mut commands,
+        &assets,
+        &config,
+        &layout,
+        &mut pool,
+        camera.map(Single::into_inner),
+    );
+    start_generation(
+        &mut commands,
+        &config,
+        &mut pool,
+        &mut progress,
+        layout.blocks.len(),
+    );
+}
+
+fn log_city_generated(generated: On<CityGenerated>) {
+    info!(
+        "Generated {} blocks with seed {}",
+        generated.block_count, generated.seed
+    );
+}
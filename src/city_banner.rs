@@ -0,0 +1,98 @@
+use bevy::prelude::*;
+
+use crate::{
+    city::CityGenerated,
+    city_names::city_name,
+    generate_city::{CityConfig, block_offset, grid_blocks},
+    stats::{SceneStats, format_large_number},
+};
+
+/// Estimated number of residents of each kind of building
+const LOW_DENSITY_RESIDENTS: u32 = 4;
+const MEDIUM_DENSITY_RESIDENTS: u32 = 40;
+const SKYSCRAPER_RESIDENTS: u32 = 400;
+/// Estimated number of people for each car, moving or parked, on top of the residents
+const PEOPLE_PER_CAR: u32 = 2;
+/// The scale of the kit, a lane of the roads is a bit over 3 meters wide
+const METERS_PER_UNIT: f32 = 10.0;
+
+/// Shows the name of the city and a summary of its size in a banner at the top of the screen
+///
+/// The name comes from the seed so a screenshot shows which city it is, and the summary is only
+/// computed once the city is generated.
+pub struct CityBannerPlugin;
+
+impl Plugin for CityBannerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_city_banner)
+            .add_observer(update_city_banner);
+    }
+}
+
+#[derive(Component)]
+struct CityNameText;
+
+#[derive(Component)]
+struct CitySummaryText;
+
+fn spawn_city_banner(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            width: percent(100),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        Pickable::IGNORE,
+        children![
+            (
+                Text::new(""),
+                TextFont {
+                    font_size: FontSize::Px(32.0),
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                TextShadow::default(),
+                CityNameText,
+            ),
+            (
+                Text::new(""),
+                TextFont {
+                    font_size: FontSize::Px(16.0),
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                TextShadow::default(),
+                CitySummaryText,
+            ),
+        ],
+    ));
+}
+
+/// Names the city from its seed and estimates its population from the buildings and cars
+///
+/// The population is a rough guess from the number of residents of each kind of building, the
+/// industrial buildings have none.
+fn update_city_banner(
+    generated: On<CityGenerated>,
+    config: Res<CityConfig>,
+    stats: Res<SceneStats>,
+    mut name: Single<&mut Text, (With<CityNameText>, Without<CitySummaryText>)>,
+    mut summary: Single<&mut Text, With<CitySummaryText>>,
+) {
+    name.0 = city_name(generated.seed);
+
+    let population = stats.low_density_buildings * LOW_DENSITY_RESIDENTS
+        + stats.medium_density_buildings * MEDIUM_DENSITY_RESIDENTS
+        + stats.skyscrapers * SKYSCRAPER_RESIDENTS
+        + (stats.cars_spawned + stats.parked_cars) * PEOPLE_PER_CAR;
+    let blocks = grid_blocks(&config).len();
+    let block_size = block_offset(IVec2::ONE) * METERS_PER_UNIT;
+    let area = (blocks * blocks) as f32 * block_size.x * block_size.z / 1_000_000.0;
+    summary.0 = format!(
+        "Population ~{} · {blocks}x{blocks} blocks · {area:.2} km²",
+        format_large_number(population)
+    );
+}
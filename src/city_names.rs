@@ -0,0 +1,43 @@
+use rand::RngExt;
+
+use crate::rng::{RngKind, make_rng};
+
+/// Words put in front of some of the names, with the space separating them from the rest
+const PREFIXES: [&str; 8] = [
+    "New ", "Port ", "East ", "West ", "North ", "South ", "Fort ", "Lake ",
+];
+
+/// Chance for a name to start with one of the [`PREFIXES`]
+const PREFIX_CHANCE: f64 = 0.3;
+
+const ROOTS: [&str; 24] = [
+    "Ash", "Birch", "Bright", "Cedar", "Clear", "Copper", "Elm", "Fair", "Glen", "Granite",
+    "Green", "Hollow", "Iron", "Maple", "Mill", "Oak", "Pine", "Raven", "Red", "Silver", "Stone",
+    "Summer", "Willow", "Wolf",
+];
+
+const SUFFIXES: [&str; 16] = [
+    "bridge", "brook", "burg", "dale", "field", "ford", "gate", "haven", "hill", "mouth", "port",
+    "ridge", "stead", "ton", "ville", "wood",
+];
+
+/// Names the city generated from the seed by combining a root and a suffix, sometimes with a
+/// prefix in front
+///
+/// This always uses the ChaCha rng no matter the [`RngKind`] of the config, so a seed keeps its
+/// name on every platform and version of rand and a screenshot can be matched to its seed.
+pub fn city_name(seed: u64) -> String {
+    let mut rng = make_rng(seed, RngKind::ChaCha);
+    let prefix = if rng.random_bool(PREFIX_CHANCE) {
+        pick(&mut rng, &PREFIXES)
+    } else {
+        ""
+    };
+    let root = pick(&mut rng, &ROOTS);
+    let suffix = pick(&mut rng, &SUFFIXES);
+    format!("{prefix}{root}{suffix}")
+}
+
+fn pick<'a>(rng: &mut impl RngExt, words: &[&'a str]) -> &'a str {
+    words[rng.random_range(0..words.len())]
+}
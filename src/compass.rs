@@ -0,0 +1,143 @@
+use bevy::{camera_controller::free_camera::FreeCamera, prelude::*};
+
+use crate::settings::Settings;
+
+/// Size of the dial of the compass, in pixels
+const COMPASS_SIZE: f32 = 72.0;
+/// Length of the axes drawn at the origin
+const AXES_LENGTH: f32 = 3.0;
+
+/// Shows which way the camera is heading with a compass in the top left corner, and draws the
+/// axes of the world at the origin
+///
+/// North is toward -Z, the direction the camera faces when it frames the city from the south.
+pub struct CompassPlugin;
+
+impl Plugin for CompassPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_compass)
+            .add_systems(Update, (update_compass, draw_origin_axes));
+    }
+}
+
+/// The root of the compass, hidden when the compass is disabled in the settings
+#[derive(Component)]
+struct Compass;
+
+/// The dial of the compass, it's rotated so its north points to the north of the city
+#[derive(Component)]
+struct CompassDial;
+
+fn spawn_compass(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                left: Val::Px(10.0),
+                ..default()
+            },
+            Compass,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        width: Val::Px(COMPASS_SIZE),
+                        height: Val::Px(COMPASS_SIZE),
+                        border_radius: BorderRadius::MAX,
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.75)),
+                    CompassDial,
+                ))
+                .with_children(|parent| {
+                    let cardinals = [
+                        ("N", Val::Px(4.0), Val::Auto, Color::srgb(1.0, 0.3, 0.3)),
+                        ("S", Val::Auto, Val::Px(4.0), Color::WHITE),
+                    ];
+                    for (label, top, bottom, color) in cardinals {
+                        parent.spawn((
+                            Node {
+                                position_type: PositionType::Absolute,
+                                top,
+                                bottom,
+                                width: percent(100),
+                                justify_content: JustifyContent::Center,
+                                ..default()
+                            },
+                            children![(
+                                Text::new(label),
+                                TextFont {
+                                    font_size: FontSize::Px(16.0),
+                                    ..default()
+                                },
+                                TextColor(color),
+                            )],
+                        ));
+                    }
+                    let sides = [
+                        ("W", Val::Px(6.0), Val::Auto),
+                        ("E", Val::Auto, Val::Px(6.0)),
+                    ];
+                    for (label, left, right) in sides {
+                        parent.spawn((
+                            Node {
+                                position_type: PositionType::Absolute,
+                                left,
+                                right,
+                                height: percent(100),
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            children![(
+                                Text::new(label),
+                                TextFont {
+                                    font_size: FontSize::Px(16.0),
+                                    ..default()
+                                },
+                                TextColor(Color::WHITE),
+                            )],
+                        ));
+                    }
+                });
+        });
+}
+
+/// Shows or hides the compass and turns its dial with the heading of the camera
+///
+/// The heading is the forward vector of the camera projected on the ground, it's kept as is
+/// while the camera looks straight down since it has no heading then.
+fn update_compass(
+    settings: Res<Settings>,
+    camera: Option<Single<&Transform, With<FreeCamera>>>,
+    mut compass: Single<&mut Visibility, With<Compass>>,
+    mut dial: Single<&mut UiTransform, With<CompassDial>>,
+) {
+    let visibility = if settings.show_compass {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    compass.set_if_neq(visibility);
+    let Some(camera) = camera else {
+        return;
+    };
+    let Some(forward) = camera.forward().xz().try_normalize() else {
+        return;
+    };
+    // Clockwise angle from the north to the heading, the dial turns the other way so its north
+    // keeps pointing north
+    let heading = forward.x.atan2(-forward.y);
+    let rotation = Rot2::radians(-heading);
+    if dial.rotation != rotation {
+        dial.rotation = rotation;
+    }
+}
+
+/// Draws the X, Y and Z axes of the world in red, green and blue at the origin
+fn draw_origin_axes(settings: Res<Settings>, mut gizmos: Gizmos) {
+    if settings.show_origin_axes {
+        gizmos.axes(Transform::IDENTITY, AXES_LENGTH);
+    }
+}
@@ -0,0 +1,126 @@
+use std::{fmt, path::Path};
+
+use serde::{
+    Deserialize, Deserializer,
+    de::{IgnoredAny, MapAccess, Visitor},
+};
+
+use crate::{
+    generate_city::CityConfig,
+    profiles::{self, GenerationProfile},
+    settings::Settings,
+};
+
+/// The parameters of the city and the settings loaded at startup with `--config`, from a RON
+/// file or a JSON one when its extension is `.json`
+///
+/// Every field can be left out to keep its default value, the fields are the same as in the
+/// output of `--dump-config-schema`. The unknown fields of the config and of the settings are
+/// errors so typos don't go unnoticed.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub city_config: CityConfig,
+    pub settings: Settings,
+    /// Name of a [`GenerationProfile`] applied over `city_config`, like `"Balanced Town"`
+    pub profile: Option<String>,
+}
+
+impl ConfigFile {
+    /// The config and settings of the file with its profile applied
+    pub fn into_config(mut self) -> (CityConfig, Settings) {
+        if let Some(profile) = self.profile.as_deref().and_then(find_profile) {
+            profile.apply(&mut self.city_config, &mut self.settings);
+        }
+        (self.city_config, self.settings)
+    }
+}
+
+fn find_profile(name: &str) -> Option<&'static GenerationProfile> {
+    profiles::ALL
+        .into_iter()
+        .find(|profile| profile.name.eq_ignore_ascii_case(name))
+}
+
+/// The names of the settings given in a config file
+///
+/// The saved settings ignore their unknown fields so the settings file keeps loading after a
+/// field is removed, the fields of a config file are checked against these instead.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct SettingsFields {
+    settings: FieldNames,
+}
+
+impl SettingsFields {
+    fn check(&self) -> std::io::Result<()> {
+        let known = serde_json::to_value(Settings::default())?;
+        match self
+            .settings
+            .0
+            .iter()
+            .find(|field| known.get(field.as_str()).is_none())
+        {
+            Some(field) => Err(std::io::Error::other(format!(
+                "unknown field `{field}` in the settings"
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The names of the fields of a struct, a RON struct is only read as a map by `deserialize_any`
+#[derive(Default)]
+struct FieldNames(Vec<String>);
+
+impl<'de> Deserialize<'de> for FieldNames {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldNamesVisitor;
+
+        impl<'de> Visitor<'de> for FieldNamesVisitor {
+            type Value = FieldNames;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a struct")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<FieldNames, A::Error> {
+                let mut names = Vec::new();
+                while let Some((name, IgnoredAny)) = map.next_entry::<String, IgnoredAny>()? {
+                    names.push(name);
+                }
+                Ok(FieldNames(names))
+            }
+        }
+
+        deserializer.deserialize_any(FieldNamesVisitor)
+    }
+}
+
+pub fn load_config_file(path: &Path) -> std::io::Result<ConfigFile> {
+    let contents = std::fs::read_to_string(path)?;
+    let (file, fields): (ConfigFile, SettingsFields) = if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        (
+            serde_json::from_str(&contents).map_err(std::io::Error::other)?,
+            serde_json::from_str(&contents).map_err(std::io::Error::other)?,
+        )
+    } else {
+        (
+            ron::from_str(&contents).map_err(std::io::Error::other)?,
+            ron::from_str(&contents).map_err(std::io::Error::other)?,
+        )
+    };
+    fields.check()?;
+    if let Some(name) = &file.profile
+        && find_profile(name).is_none()
+    {
+        let names = profiles::ALL.map(|profile| profile.name).join(", ");
+        return Err(std::io::Error::other(format!(
+            "unknown profile `{name}`, expected one of {names}"
+        )));
+    }
+    Ok(file)
+}
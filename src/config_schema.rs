@@ -0,0 +1,82 @@
+use std::ops::RangeInclusive;
+
+use serde::Serialize;
+
+use crate::{
+    generate_city::CityConfig,
+    settings::{
+        BLOOM_INTENSITY_RANGE, CAR_DENSITY_RANGE, CAR_SPEED_RANGE, EXPOSURE_RANGE,
+        FOG_DENSITY_RANGE, LACUNARITY_RANGE, NOISE_SCALE_RANGE, OCTAVES_RANGE, PERSISTENCE_RANGE,
+        SHADOW_CASCADES_RANGE, SHADOW_DISTANCE_RANGE, Settings, TERRAIN_HEIGHT_RANGE,
+        TIME_SCALE_RANGE, WIND_STRENGTH_RANGE,
+    },
+};
+
+/// Describes the [`CityConfig`] and the [`Settings`] for the tools building their own UI around
+/// the city, written as JSON by `--dump-config-schema`
+///
+/// The defaults and the current values show the structure of each of them, the ranges are the
+/// ones of the sliders of the settings panel.
+#[derive(Serialize)]
+pub struct ConfigSchema<'a> {
+    pub city_config: SchemaSection<'a, CityConfig>,
+    pub settings: SchemaSection<'a, Settings>,
+}
+
+#[derive(Serialize)]
+pub struct SchemaSection<'a, T> {
+    pub defaults: T,
+    pub values: &'a T,
+    pub ranges: Vec<FieldRange>,
+}
+
+/// The range of a numeric field, nested fields are separated by dots like `noise.octaves`
+#[derive(Serialize)]
+pub struct FieldRange {
+    pub field: &'static str,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl FieldRange {
+    fn new(field: &'static str, range: RangeInclusive<f32>) -> Self {
+        Self {
+            field,
+            min: *range.start(),
+            max: *range.end(),
+        }
+    }
+}
+
+impl<'a> ConfigSchema<'a> {
+    pub fn new(config: &'a CityConfig, settings: &'a Settings) -> Self {
+        Self {
+            city_config: SchemaSection {
+                defaults: CityConfig::default(),
+                values: config,
+                ranges: vec![
+                    FieldRange::new("wind_strength", WIND_STRENGTH_RANGE),
+                    FieldRange::new("noise.octaves", OCTAVES_RANGE),
+                    FieldRange::new("noise.lacunarity", LACUNARITY_RANGE),
+                    FieldRange::new("noise.persistence", PERSISTENCE_RANGE),
+                    FieldRange::new("noise.scale", NOISE_SCALE_RANGE),
+                    FieldRange::new("terrain_amplitude", TERRAIN_HEIGHT_RANGE),
+                ],
+            },
+            settings: SchemaSection {
+                defaults: Settings::default(),
+                values: settings,
+                ranges: vec![
+                    FieldRange::new("shadow_distance", SHADOW_DISTANCE_RANGE),
+                    FieldRange::new("shadow_cascades", SHADOW_CASCADES_RANGE),
+                    FieldRange::new("fog_density", FOG_DENSITY_RANGE),
+                    FieldRange::new("bloom_intensity", BLOOM_INTENSITY_RANGE),
+                    FieldRange::new("exposure_ev100", EXPOSURE_RANGE),
+                    FieldRange::new("time_scale", TIME_SCALE_RANGE),
+                    FieldRange::new("car_speed_multiplier", CAR_SPEED_RANGE),
+                    FieldRange::new("car_density", CAR_DENSITY_RANGE),
+                ],
+            },
+        }
+    }
+}
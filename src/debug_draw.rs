@@ -0,0 +1,129 @@
+use bevy::{
+    camera::primitives::Aabb, camera_controller::free_camera::FreeCamera, math::bounding::Aabb3d,
+    prelude::*,
+};
+
+use crate::{
+    generate_city::{BuildingMarker, CarMarker, RoadMarker},
+    settings::Settings,
+    traffic::{Car, CarLane, Road},
+};
+
+/// Number of points the lanes are drawn with, enough for the curved roads to look smooth
+const LANE_POINTS: usize = 16;
+/// Distance along its lane from a car to the point it drives toward
+const CAR_TARGET_DISTANCE: f32 = 0.3;
+/// Height of the lines above the roads so they aren't hidden by the asphalt
+const GIZMO_HEIGHT: f32 = 0.05;
+/// Only the bounding boxes of the meshes this close to the camera are drawn, drawing every mesh
+/// of a large city would cost more than rendering it
+const BOUNDING_BOX_RADIUS: f32 = 15.0;
+
+/// Color of a lane from the direction the cars drive in at its start, the two lanes of a road
+/// get opposite hues
+fn lane_color(road: &Road, lane: CarLane) -> Color {
+    let direction = road.lane_direction(lane, 0.0);
+    Color::hsl(
+        direction
+            .z
+            .atan2(direction.x)
+            .to_degrees()
+            .rem_euclid(360.0),
+        0.9,
+        0.5,
+    )
+}
+
+/// Draws the lanes of every road with an arrow in the direction of the traffic, and a line from
+/// each car to the point of its lane it drives toward
+///
+/// The roads are invisible to the simulation otherwise, this shows cars driving against their
+/// lane or lanes that don't connect at a glance.
+pub fn draw_traffic_gizmos(
+    settings: Res<Settings>,
+    mut gizmos: Gizmos,
+    roads: Query<(&Road, &GlobalTransform, Option<&Children>)>,
+    cars: Query<&Car>,
+) {
+    if !settings.show_traffic_gizmos {
+        return;
+    }
+    for (road, transform, children) in &roads {
+        let length = road.length();
+        let point = |lane: CarLane, distance: f32| {
+            transform.transform_point(road.lane_position(lane, distance)) + Vec3::Y * GIZMO_HEIGHT
+        };
+        for lane in road.lanes {
+            let color = lane_color(road, lane);
+            let points = (0..=LANE_POINTS)
+                .map(|i| point(lane, length * i as f32 / LANE_POINTS as f32))
+                .collect::<Vec<_>>();
+            let [.., before_end, end] = points[..] else {
+                continue;
+            };
+            gizmos.linestrip(points[..LANE_POINTS].iter().copied(), color);
+            gizmos.arrow(before_end, end, color);
+        }
+
+        for car in children
+            .into_iter()
+            .flatten()
+            .filter_map(|entity| cars.get(*entity).ok())
+        {
+            let target_distance = (car.distance_traveled + CAR_TARGET_DISTANCE).min(length);
+            let target = point(car.lane, target_distance);
+            gizmos.line(point(car.lane, car.distance_traveled), target, Color::WHITE);
+            gizmos.sphere(Isometry3d::from_translation(target), 0.02, Color::WHITE);
+        }
+    }
+}
+
+/// Filter for the root of the scenes the bounding boxes are drawn for
+type BoxedCategory = Or<(With<BuildingMarker>, With<RoadMarker>, With<CarMarker>)>;
+
+/// Draws the world space bounding box of each mesh of the buildings, roads and cars around the
+/// camera, colored by what the mesh belongs to
+///
+/// This shows at a glance when the placement lets two things overlap.
+pub fn draw_bounding_boxes(
+    settings: Res<Settings>,
+    mut gizmos: Gizmos,
+    camera: Option<Single<&GlobalTransform, With<FreeCamera>>>,
+    meshes: Query<(Entity, &Aabb, &GlobalTransform, &InheritedVisibility)>,
+    parents: Query<&ChildOf>,
+    categories: Query<(Has<BuildingMarker>, Has<RoadMarker>), BoxedCategory>,
+) {
+    if !settings.show_bounding_boxes {
+        return;
+    }
+    let Some(camera) = camera else {
+        return;
+    };
+    let camera_position = camera.translation();
+    for (entity, aabb, transform, visibility) in &meshes {
+        let center = transform.transform_point(aabb.center.into());
+        if !visibility.get() || center.distance(camera_position) > BOUNDING_BOX_RADIUS {
+            continue;
+        }
+        // The markers are on the root of the scenes, the meshes are a few levels below them
+        let Some((building, road)) = parents
+            .iter_ancestors(entity)
+            .find_map(|ancestor| categories.get(ancestor).ok())
+        else {
+            continue;
+        };
+        let color = match (building, road) {
+            (true, _) => Color::srgb(1.0, 0.6, 0.1),
+            (_, true) => Color::srgb(0.3, 0.6, 1.0),
+            _ => Color::srgb(1.0, 0.2, 0.3),
+        };
+        // The extent of the rotated and scaled box along each axis of the world
+        let matrix = transform.affine().matrix3;
+        let half_size = Vec3::from(
+            matrix.x_axis.abs() * aabb.half_extents.x
+                + matrix.y_axis.abs() * aabb.half_extents.y
+                + matrix.z_axis.abs() * aabb.half_extents.z,
+        );
+        gizmos.aabb_3d(Aabb3d::new(center, half_size), Transform::IDENTITY, color);
+    }
+}
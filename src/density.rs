@@ -0,0 +1,165 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use image::{ImageResult, Rgb, RgbImage};
+use noise::{NoiseFn, OpenSimplex};
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    generate_city::{CityConfig, DensityTier, block_offset, city_bounds, grid_blocks},
+    rng::{RngKind, make_rng},
+};
+
+/// Number of pixels along each side of a block in the image written by [`dump_density`]
+const DUMP_PIXELS_PER_BLOCK: u32 = 8;
+/// Frequency of the terrain relative to the density, the hills span many blocks
+const TERRAIN_SCALE: f64 = 0.3;
+
+/// Parameters of the fractal noise used to sample the density of the city
+///
+/// Each octave adds finer details on top of the previous one. With a single octave this is the
+/// plain `OpenSimplex` noise.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NoiseConfig {
+    /// Number of layers of noise added together
+    pub octaves: u32,
+    /// How much the frequency increases with each octave
+    pub lacunarity: f64,
+    /// How much the amplitude decreases with each octave
+    pub persistence: f64,
+    /// Frequency of the first octave, lower values give bigger districts
+    pub scale: f64,
+}
+
+impl Default for NoiseConfig {
+    fn default() -> Self {
+        Self {
+            octaves: 1,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            scale: 0.025,
+        }
+    }
+}
+
+/// Samples the density of the city using fractal Brownian motion over `OpenSimplex` noise
+///
+/// A second noise channel, seeded independently, decides where the rivers flow, a third one
+/// splits the city in districts and a fourth one raises the terrain.
+pub struct DensityField {
+    noise: OpenSimplex,
+    water_noise: OpenSimplex,
+    district_noise: OpenSimplex,
+    terrain_noise: OpenSimplex,
+    config: NoiseConfig,
+}
+
+impl DensityField {
+    pub fn new(seed: u64, rng_kind: RngKind, config: &NoiseConfig) -> Self {
+        let mut rng = make_rng(seed, rng_kind);
+        Self {
+            noise: OpenSimplex::new(rng.random()),
+            water_noise: OpenSimplex::new(rng.random()),
+            district_noise: OpenSimplex::new(rng.random()),
+            terrain_noise: OpenSimplex::new(rng.random()),
+            config: config.clone(),
+        }
+    }
+
+    /// Samples the density at the given world position, in the 0..1 range
+    pub fn sample_density(&self, x: f64, z: f64) -> f64 {
+        let mut frequency = self.config.scale;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        let mut max_total = 0.0;
+        for _ in 0..self.config.octaves.max(1) {
+            total += self.noise.get([x * frequency, z * frequency, 0.0]) * amplitude;
+            max_total += amplitude;
+            amplitude *= self.config.persistence;
+            frequency *= self.config.lacunarity;
+        }
+        (total / max_total) * 0.5 + 0.5
+    }
+
+    /// Samples the density at the crossroad of the block
+    pub fn block_density(&self, block: IVec2) -> f64 {
+        let offset = block_offset(block);
+        self.sample_density(offset.x as f64, offset.z as f64)
+    }
+
+    /// Samples the water channel at the crossroad of the block, in the 0..1 range
+    ///
+    /// The noise is folded around 0 so the highest values form long thin bands that look like
+    /// rivers instead of round lakes.
+    pub fn block_water(&self, block: IVec2) -> f64 {
+        let offset = block_offset(block);
+        self.sample_water(offset.x as f64, offset.z as f64)
+    }
+
+    /// Samples the water channel at the given world position, in the 0..1 range
+    pub fn sample_water(&self, x: f64, z: f64) -> f64 {
+        let scale = self.config.scale;
+        let value = self.water_noise.get([x * scale, z * scale, 0.0]);
+        1.0 - value.abs()
+    }
+
+    /// Samples the district channel at the crossroad of the block, in the 0..1 range
+    ///
+    /// This is twice as coarse as the density so the districts span several blocks.
+    pub fn block_district(&self, block: IVec2) -> f64 {
+        let offset = block_offset(block);
+        let scale = self.config.scale * 0.5;
+        let value =
+            self.district_noise
+                .get([offset.x as f64 * scale, offset.z as f64 * scale, 0.0]);
+        value * 0.5 + 0.5
+    }
+
+    /// Samples the terrain channel at the crossroad of the block, in the -1..1 range
+    pub fn block_terrain(&self, block: IVec2) -> f64 {
+        let offset = block_offset(block);
+        let scale = self.config.scale * TERRAIN_SCALE;
+        self.terrain_noise
+            .get([offset.x as f64 * scale, offset.z as f64 * scale, 0.0])
+    }
+}
+
+/// Color of a tier in the image written by [`dump_density`]
+fn tier_color(tier: DensityTier) -> [f64; 3] {
+    match tier {
+        DensityTier::Forest => [34.0, 100.0, 34.0],
+        DensityTier::Park => [97.0, 203.0, 139.0],
+        DensityTier::Low => [230.0, 200.0, 120.0],
+        DensityTier::Medium => [230.0, 140.0, 60.0],
+        DensityTier::High => [200.0, 50.0, 50.0],
+        DensityTier::Water => [60.0, 110.0, 220.0],
+    }
+}
+
+/// Writes an image of the density of the city to `path`, the format is picked from the extension
+///
+/// Each pixel samples the same [`DensityField`] as the generation and is colored by the tier
+/// the thresholds of the config pick for it, darker where the density is lower. Unlike the
+/// layout, the density is sampled at every pixel instead of at the crossroad of each block so
+/// the gradients inside the blocks are visible.
+pub fn dump_density(config: &CityConfig, path: &Path) -> ImageResult<()> {
+    let density = DensityField::new(config.seed, config.rng_kind, &config.noise);
+    let (min, max) = city_bounds(config);
+    let size = grid_blocks(config).len() as u32 * DUMP_PIXELS_PER_BLOCK;
+    let image = RgbImage::from_fn(size, size, |x, z| {
+        let x = min.x as f64 + (x as f64 + 0.5) / size as f64 * (max.x - min.x) as f64;
+        let z = min.z as f64 + (z as f64 + 0.5) / size as f64 * (max.z - min.z) as f64;
+        let value = density.sample_density(x, z);
+        let tier =
+            if config.rivers_enabled && density.sample_water(x, z) > config.water_level as f64 {
+                DensityTier::Water
+            } else {
+                DensityTier::from_density(value, config)
+            };
+        let shade = 0.5 + 0.5 * value.clamp(0.0, 1.0);
+        Rgb(tier_color(tier).map(|channel| (channel * shade) as u8))
+    });
+    image.save(path)
+}
@@ -0,0 +1,145 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    assets::BuildingModel,
+    bus::Bus,
+    generate_city::{CityBlock, RoadMarker, SidewalkMarker, SpawnInfo, TreeMarker},
+    traffic::{Car, Pedestrian},
+};
+
+/// The transforms are rounded to a thousandth of their units before being hashed, so tiny float
+/// differences between platforms don't change the digest while any real divergence does
+const DIGEST_PRECISION: f32 = 1000.0;
+/// Offset basis and prime of the 64 bits FNV-1a hash
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Filter for the entities the spawn decisions of the city are read from
+type DigestedEntity = Or<(
+    With<SpawnInfo>,
+    With<CityBlock>,
+    With<RoadMarker>,
+    With<SidewalkMarker>,
+    With<TreeMarker>,
+    With<Pedestrian>,
+)>;
+
+/// What an entity was spawned as, from its markers
+type SpawnKind = (
+    Entity,
+    Option<&'static SpawnInfo>,
+    Option<&'static CityBlock>,
+    Option<&'static BuildingModel>,
+    Has<RoadMarker>,
+    Has<SidewalkMarker>,
+    Has<TreeMarker>,
+);
+
+/// The state of the entities moving around the city
+type Moving = (
+    Option<&'static Car>,
+    Option<&'static Pedestrian>,
+    Option<&'static Bus>,
+    Option<&'static ChildOf>,
+);
+
+/// Hashes the spawn decisions of the city into a single digest, so the cities generated from the
+/// same config on two machines can be compared
+///
+/// Every block, building, car, road, sidewalk, tree and pedestrian is hashed with what it was
+/// spawned as and its transform in the city, rounded to [`DIGEST_PRECISION`]. The buildings are
+/// also hashed with the [`BuildingModel`] picked for them, so picking another building of the
+/// same footprint changes the digest. The entities are
+/// sorted before being hashed so the order they were spawned in doesn't matter, and the hash is
+/// FNV-1a since unlike the hasher of the standard library it's the same on every platform and
+/// version of Rust.
+///
+/// The city keeps moving while it's spawned over several frames, so the cars, buses and
+/// pedestrians are hashed with where and how they were spawned instead of where they are, and
+/// the trees without the tilt of the wind.
+#[derive(SystemParam)]
+pub struct CityDigest<'w, 's> {
+    entities: Query<'w, 's, (SpawnKind, Moving), DigestedEntity>,
+    transforms: Query<'w, 's, (&'static Transform, Option<&'static ChildOf>)>,
+}
+
+impl CityDigest<'_, '_> {
+    pub fn digest(&self) -> u64 {
+        let mut records = self
+            .entities
+            .iter()
+            .map(
+                |((entity, info, block, model, road, sidewalk, tree), moving)| {
+                    let mut kind = match (info, block) {
+                        (Some(SpawnInfo(label)), _) => label.to_string(),
+                        (_, Some(block)) => format!(
+                            "Block {} {} {:?} {:?}",
+                            block.coord.x, block.coord.y, block.tier, block.district
+                        ),
+                        _ if road => "Road".to_string(),
+                        _ if sidewalk => "Sidewalk".to_string(),
+                        _ if tree => "Tree".to_string(),
+                        _ => "Pedestrian".to_string(),
+                    };
+                    if let Some(model) = model {
+                        kind += &format!(" {} {} {:?}", model.mesh, model.material, model.tint);
+                    }
+                    let mut transform = self.city_transform(entity);
+                    match moving {
+                        (Some(car), _, _, Some(road)) => {
+                            let lane = car.lane.offset.extend(car.lane.dir).to_array();
+                            kind += &format!(" {} {}", round(car.speed), rounded(lane));
+                            transform = self.city_transform(road.parent());
+                        }
+                        (_, Some(pedestrian), _, Some(path)) => {
+                            kind += &format!(" {}", round(pedestrian.dir));
+                            transform = self.city_transform(path.parent());
+                        }
+                        (_, _, Some(bus), _) => {
+                            kind += &format!(" {}", bus.route.len());
+                            transform = Transform::IDENTITY;
+                        }
+                        _ if tree => transform.rotation = Quat::IDENTITY,
+                        _ => {}
+                    }
+                    let values = [transform.translation.to_array(), transform.scale.to_array()]
+                        .into_iter()
+                        .flatten()
+                        .chain(transform.rotation.to_array());
+                    format!("{kind} {}", rounded(values))
+                },
+            )
+            .collect::<Vec<_>>();
+        records.sort_unstable();
+        records
+            .iter()
+            .flat_map(|record| record.bytes().chain([b'\n']))
+            .fold(FNV_OFFSET, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+            })
+    }
+
+    /// The transform of an entity relative to the root of the city
+    ///
+    /// This doesn't rely on the [`GlobalTransform`] since the headless app doesn't propagate the
+    /// transforms.
+    fn city_transform(&self, mut entity: Entity) -> Transform {
+        let mut transform = Transform::IDENTITY;
+        while let Ok((local, parent)) = self.transforms.get(entity) {
+            transform = *local * transform;
+            let Some(parent) = parent else {
+                break;
+            };
+            entity = parent.parent();
+        }
+        transform
+    }
+}
+
+fn round(value: f32) -> i64 {
+    (value * DIGEST_PRECISION).round() as i64
+}
+
+fn rounded(values: impl IntoIterator<Item = f32>) -> String {
+    format!("{:?}", values.into_iter().map(round).collect::<Vec<_>>())
+}
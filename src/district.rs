@@ -0,0 +1,93 @@
+use bevy::{camera_controller::free_camera::FreeCamera, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::generate_city::{CityBlock, DensityTier, block_at};
+
+/// Value of the district noise above which the commercial blocks become industrial
+const INDUSTRIAL_THRESHOLD: f64 = 0.62;
+
+/// The kind of area a block belongs to
+///
+/// The district follows the density of the block, a coarser noise then turns some of the
+/// commercial areas into industrial ones so they form large zones instead of single blocks.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum District {
+    Residential,
+    Commercial,
+    Downtown,
+    /// Warehouses instead of the commercial buildings
+    Industrial,
+    /// The forests and the parks
+    Parkland,
+    Waterfront,
+}
+
+impl District {
+    /// Picks the district of a block from its tier and the district noise, in the 0..1 range
+    pub fn new(tier: DensityTier, district_noise: f64) -> Self {
+        match tier {
+            DensityTier::Forest | DensityTier::Park => District::Parkland,
+            DensityTier::Low => District::Residential,
+            DensityTier::Medium if district_noise > INDUSTRIAL_THRESHOLD => District::Industrial,
+            DensityTier::Medium => District::Commercial,
+            DensityTier::High => District::Downtown,
+            DensityTier::Water => District::Waterfront,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            District::Residential => "Residential",
+            District::Commercial => "Commercial",
+            District::Downtown => "Downtown",
+            District::Industrial => "Industrial",
+            District::Parkland => "Parkland",
+            District::Waterfront => "Waterfront",
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct DistrictText;
+
+pub fn spawn_district_ui(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                // Below the banner with the name of the city
+                top: Val::Px(64.0),
+                width: percent(100),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .with_child((
+            Text::new(""),
+            TextFont {
+                font_size: FontSize::Px(24.0),
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            TextShadow::default(),
+            DistrictText,
+        ));
+}
+
+/// Shows the name of the district under the camera
+pub fn update_district_ui(
+    mut text: Single<&mut Text, With<DistrictText>>,
+    camera: Single<&Transform, With<FreeCamera>>,
+    blocks: Query<&CityBlock>,
+) {
+    let coord = block_at(camera.translation);
+    let label = blocks
+        .iter()
+        .find(|block| block.coord == coord)
+        .map(|block| block.district.label())
+        .unwrap_or_default();
+    if text.0 != label {
+        text.0 = label.to_owned();
+    }
+}
@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+
+use crate::{assets::CityAssets, sim_time::SimTime};
+
+/// How much faster the emergency vehicles drive than the speed picked for a car
+pub const EMERGENCY_SPEED_MULTIPLIER: f32 = 1.6;
+/// Number of times per second the lights of the sirens swap
+const SIREN_FLASH_RATE: f32 = 3.0;
+/// Where the lights sit on the roof, in the space of the car models before they are scaled down
+const SIREN_OFFSET: Vec3 = Vec3::new(0.18, 1.05, 0.0);
+
+/// Marks the ambulances, police cars and fire trucks
+///
+/// They drive among the other cars but faster, with the lights of their sirens flashing. The cars
+/// just ahead of them move over to another lane or stop to let them pass.
+#[derive(Component)]
+pub struct EmergencyVehicle;
+
+/// One of the two lights of a siren, the index of its color in [`SirenAssets::lights`]
+#[derive(Component)]
+pub struct SirenLight(pub usize);
+
+/// The mesh of the lights of the sirens along with their materials
+#[derive(Default)]
+pub struct SirenAssets {
+    pub mesh: Handle<Mesh>,
+    /// The glowing red and blue materials of the lights when they are on
+    pub lights: [Handle<StandardMaterial>; 2],
+    pub off: Handle<StandardMaterial>,
+}
+
+impl SirenAssets {
+    pub fn new(meshes: &mut Assets<Mesh>, materials: &mut Assets<StandardMaterial>) -> Self {
+        let light = |color: LinearRgba| StandardMaterial {
+            base_color: color.into(),
+            emissive: color * 20.0,
+            ..default()
+        };
+        Self {
+            mesh: meshes.add(Cuboid::new(0.3, 0.12, 0.2)),
+            lights: [
+                materials.add(light(LinearRgba::RED)),
+                materials.add(light(LinearRgba::BLUE)),
+            ],
+            off: materials.add(StandardMaterial::from_color(Color::srgb(0.2, 0.2, 0.2))),
+        }
+    }
+}
+
+/// Spawns the red and blue lights of a siren on the roof of an emergency vehicle
+pub fn spawn_siren(commands: &mut ChildSpawnerCommands, assets: &CityAssets) {
+    for (index, side) in [-1.0, 1.0].into_iter().enumerate() {
+        commands.spawn((
+            Mesh3d(assets.siren.mesh.clone()),
+            MeshMaterial3d(assets.siren.off.clone()),
+            Transform::from_translation(SIREN_OFFSET * Vec3::new(side, 1.0, 1.0)),
+            SirenLight(index),
+        ));
+    }
+}
+
+/// Turns the lights of the sirens on one after the other
+pub fn flash_sirens(
+    time: Res<SimTime>,
+    assets: Option<Res<CityAssets>>,
+    mut lights: Query<(&SirenLight, &mut MeshMaterial3d<StandardMaterial>)>,
+) {
+    let Some(assets) = assets else {
+        return;
+    };
+    let lit = (time.elapsed_secs() * SIREN_FLASH_RATE) as usize % assets.siren.lights.len();
+    for (SirenLight(index), mut material) in &mut lights {
+        let handle = if *index == lit {
+            &assets.siren.lights[*index]
+        } else {
+            &assets.siren.off
+        };
+        if material.0 != *handle {
+            material.0 = handle.clone();
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    mesh::{Indices, VertexAttributeValues},
+    platform::collections::HashMap,
+    prelude::*,
+};
+use serde_json::{Value, json};
+
+/// Directory the exported cities are written to
+const EXPORT_DIR: &str = "exports";
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_VERSION: u32 = 2;
+const GLB_JSON_CHUNK: u32 = 0x4E4F_534A;
+const GLB_BIN_CHUNK: u32 = 0x004E_4942;
+
+const GLTF_FLOAT: u32 = 5126;
+const GLTF_UNSIGNED_INT: u32 = 5125;
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// The meshes that can be exported, with their world transform
+pub type ExportedMesh<'a> = (
+    &'a Mesh3d,
+    &'a MeshMaterial3d<StandardMaterial>,
+    &'a GlobalTransform,
+    &'a InheritedVisibility,
+);
+
+/// Writes every visible mesh of the city to `exports/city_<seed>.glb`
+///
+/// The scenes have already been spawned as meshes so this also covers the cars, trees and roads.
+/// Each entity becomes a node with its world transform baked in, the nodes displaying the same
+/// mesh and material share the same glTF mesh.
+pub fn export_city<'a>(
+    seed: u64,
+    entities: impl Iterator<Item = ExportedMesh<'a>>,
+    meshes: &Assets<Mesh>,
+    materials: &Assets<StandardMaterial>,
+    images: &Assets<Image>,
+) -> std::io::Result<PathBuf> {
+    let mut builder = GlbBuilder::default();
+    for (mesh, material, transform, visibility) in entities {
+        if !visibility.get() {
+            continue;
+        }
+        let Some(mesh_index) = builder.mesh(mesh, material, meshes, materials, images) else {
+            continue;
+        };
+        builder.nodes.push(json!({
+            "mesh": mesh_index,
+            "matrix": transform.to_matrix().to_cols_array(),
+        }));
+    }
+
+    std::fs::create_dir_all(EXPORT_DIR)?;
+    let path = Path::new(EXPORT_DIR).join(format!("city_{seed}.glb"));
+    std::fs::write(&path, builder.finish()?)?;
+    Ok(path)
+}
+
+#[derive(Default)]
+struct GlbBuilder {
+    buffer: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    nodes: Vec<Value>,
+    meshes: Vec<Value>,
+    materials: Vec<Value>,
+    textures: Vec<Value>,
+    images: Vec<Value>,
+    mesh_indices: HashMap<(AssetId<Mesh>, AssetId<StandardMaterial>), Option<usize>>,
+    material_indices: HashMap<AssetId<StandardMaterial>, usize>,
+    texture_indices: HashMap<AssetId<Image>, Option<usize>>,
+}
+
+impl GlbBuilder {
+    /// Returns the index of the glTF mesh for this mesh and material, adding it if it's the
+    /// first time it's used
+    ///
+    /// Returns `None` for meshes that can't be exported, like meshes that aren't loaded yet.
+    fn mesh(
+        &mut self,
+        mesh: &Mesh3d,
+        material: &MeshMaterial3d<StandardMaterial>,
+        meshes: &Assets<Mesh>,
+        materials: &Assets<StandardMaterial>,
+        images: &Assets<Image>,
+    ) -> Option<usize> {
+        let key = (mesh.id(), material.id());
+        if let Some(index) = self.mesh_indices.get(&key) {
+            return *index;
+        }
+
+        let index = self.add_mesh(mesh, material, meshes, materials, images);
+        self.mesh_indices.insert(key, index);
+        index
+    }
+
+    fn add_mesh(
+        &mut self,
+        mesh: &Mesh3d,
+        material: &MeshMaterial3d<StandardMaterial>,
+        meshes: &Assets<Mesh>,
+        materials: &Assets<StandardMaterial>,
+        images: &Assets<Image>,
+    ) -> Option<usize> {
+        let mesh = meshes.get(mesh)?;
+        let Ok(Some(VertexAttributeValues::Float32x3(positions))) =
+            mesh.try_attribute_option(Mesh::ATTRIBUTE_POSITION)
+        else {
+            return None;
+        };
+
+        let mut attributes = serde_json::Map::new();
+        attributes.insert("POSITION".into(), self.positions(positions).into());
+        if let Ok(Some(VertexAttributeValues::Float32x3(normals))) =
+            mesh.try_attribute_option(Mesh::ATTRIBUTE_NORMAL)
+        {
+            let accessor =
+                self.accessor(float_bytes(normals.as_flattened()), "VEC3", normals.len());
+            attributes.insert("NORMAL".into(), accessor.into());
+        }
+        if let Ok(Some(VertexAttributeValues::Float32x2(uvs))) =
+            mesh.try_attribute_option(Mesh::ATTRIBUTE_UV_0)
+        {
+            let accessor = self.accessor(float_bytes(uvs.as_flattened()), "VEC2", uvs.len());
+            attributes.insert("TEXCOORD_0".into(), accessor.into());
+        }
+
+        let mut primitive = json!({ "attributes": attributes });
+        if let Ok(Some(indices)) = mesh.try_indices_option() {
+            primitive["indices"] = self.indices(indices).into();
+        }
+        if let Some(material_index) = self.material(material, materials, images) {
+            primitive["material"] = material_index.into();
+        }
+
+        self.meshes.push(json!({ "primitives": [primitive] }));
+        Some(self.meshes.len() - 1)
+    }
+
+    fn material(
+        &mut self,
+        material: &MeshMaterial3d<StandardMaterial>,
+        materials: &Assets<StandardMaterial>,
+        images: &Assets<Image>,
+    ) -> Option<usize> {
+        if let Some(index) = self.material_indices.get(&material.id()) {
+            return Some(*index);
+        }
+        let standard_material = materials.get(material)?;
+
+        let mut pbr = json!({
+            "baseColorFactor": standard_material.base_color.to_linear().to_f32_array(),
+            "metallicFactor": standard_material.metallic,
+            "roughnessFactor": standard_material.perceptual_roughness,
+        });
+        if let Some(texture) = &standard_material.base_color_texture
+            && let Some(texture_index) = self.texture(texture, images)
+        {
+            pbr["baseColorTexture"] = json!({ "index": texture_index });
+        }
+
+        self.materials.push(json!({
+            "pbrMetallicRoughness": pbr,
+            "doubleSided": standard_material.double_sided,
+        }));
+        let index = self.materials.len() - 1;
+        self.material_indices.insert(material.id(), index);
+        Some(index)
+    }
+
+    /// Embeds the image as a png, returns `None` if the image can't be converted
+    fn texture(&mut self, image: &Handle<Image>, images: &Assets<Image>) -> Option<usize> {
+        if let Some(index) = self.texture_indices.get(&image.id()) {
+            return *index;
+        }
+
+        let index = images.get(image).and_then(|source| {
+            let dynamic_image = match source.clone().try_into_dynamic() {
+                Ok(dynamic_image) => dynamic_image,
+                Err(err) => {
+                    warn!("Skipping texture that can't be exported: {err}");
+                    return None;
+                }
+            };
+            let mut png = Vec::new();
+            if let Err(err) =
+                dynamic_image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            {
+                warn!("Failed to encode texture: {err}");
+                return None;
+            }
+            let view = self.buffer_view(&png, None);
+            self.images
+                .push(json!({ "bufferView": view, "mimeType": "image/png" }));
+            self.textures
+                .push(json!({ "source": self.images.len() - 1 }));
+            Some(self.textures.len() - 1)
+        });
+        self.texture_indices.insert(image.id(), index);
+        index
+    }
+
+    /// Adds the positions along with the bounds required by the glTF spec
+    fn positions(&mut self, positions: &[[f32; 3]]) -> usize {
+        let (min, max) = positions.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), position| {
+                let position = Vec3::from_array(*position);
+                (min.min(position), max.max(position))
+            },
+        );
+        let accessor = self.accessor(
+            float_bytes(positions.as_flattened()),
+            "VEC3",
+            positions.len(),
+        );
+        self.accessors[accessor]["min"] = json!(min.to_array());
+        self.accessors[accessor]["max"] = json!(max.to_array());
+        accessor
+    }
+
+    fn indices(&mut self, indices: &Indices) -> usize {
+        let bytes = indices
+            .iter()
+            .flat_map(|index| (index as u32).to_le_bytes())
+            .collect::<Vec<_>>();
+        let view = self.buffer_view(&bytes, Some(GLTF_ELEMENT_ARRAY_BUFFER));
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": GLTF_UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn accessor(&mut self, bytes: Vec<u8>, kind: &str, count: usize) -> usize {
+        let view = self.buffer_view(&bytes, Some(GLTF_ARRAY_BUFFER));
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": GLTF_FLOAT,
+            "count": count,
+            "type": kind,
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn buffer_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        // Every view starts on a 4 bytes boundary so the accessors are correctly aligned
+        pad_to_4(&mut self.buffer, 0);
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.buffer.len(),
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = target.into();
+        }
+        self.buffer.extend_from_slice(bytes);
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+
+    fn finish(mut self) -> std::io::Result<Vec<u8>> {
+        pad_to_4(&mut self.buffer, 0);
+        let gltf = json!({
+            "asset": { "version": "2.0", "generator": "bevy_city" },
+            "scene": 0,
+            "scenes": [{ "nodes": (0..self.nodes.len()).collect::<Vec<_>>() }],
+            "nodes": self.nodes,
+            "meshes": self.meshes,
+            "materials": self.materials,
+            "textures": self.textures,
+            "images": self.images,
+            "accessors": self.accessors,
+            "bufferViews": self.buffer_views,
+            "buffers": [{ "byteLength": self.buffer.len() }],
+        });
+        let mut json = serde_json::to_vec(&gltf).map_err(std::io::Error::other)?;
+        pad_to_4(&mut json, b' ');
+
+        let total_length = 12 + 8 + json.len() + 8 + self.buffer.len();
+        let mut glb = Vec::with_capacity(total_length);
+        glb.extend_from_slice(&GLB_MAGIC.to_le_bytes());
+        glb.extend_from_slice(&GLB_VERSION.to_le_bytes());
+        glb.extend_from_slice(&(total_length as u32).to_le_bytes());
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&GLB_JSON_CHUNK.to_le_bytes());
+        glb.extend_from_slice(&json);
+        glb.extend_from_slice(&(self.buffer.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&GLB_BIN_CHUNK.to_le_bytes());
+        glb.extend_from_slice(&self.buffer);
+        Ok(glb)
+    }
+}
+
+fn float_bytes(floats: &[f32]) -> Vec<u8> {
+    floats
+        .iter()
+        .flat_map(|float| float.to_le_bytes())
+        .collect()
+}
+
+fn pad_to_4(bytes: &mut Vec<u8>, padding: u8) {
+    while !bytes.len().is_multiple_of(4) {
+        bytes.push(padding);
+    }
+}
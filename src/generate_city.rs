@@ -1,12 +1,326 @@
-use bevy::prelude::*;
-use noise::{NoiseFn, OpenSimplex};
-use rand::{rngs::SmallRng, RngExt, SeedableRng};
+use std::{
+    f32::consts::{FRAC_PI_2, PI},
+    ops::{Range, RangeInclusive},
+};
 
-use crate::{assets::CityAssets, Car, Road};
+use bevy::{
+    camera::visibility::VisibilityRange, camera_controller::free_camera::FreeCamera,
+    platform::collections::HashMap, prelude::*,
+};
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::{BuildingWithLod, CarAsset, CityAssets, GroundTiles},
+    bus::{spawn_bus_route, BUS_ROUTE_CHANCE},
+    car_paint::random_car_paint,
+    city::{finish_generation, CityRegenerated, GenerationProgress},
+    density::{DensityField, NoiseConfig},
+    district::District,
+    emergency::{spawn_siren, EmergencyVehicle, EMERGENCY_SPEED_MULTIPLIER},
+    layout::CityLayout,
+    placement::{
+        forest_transforms, high_density_transforms, industrial_transforms, low_density_transforms,
+        medium_density_transforms, park_transforms, PlacementKind, ROAD_HALF_WIDTH, SIDEWALK_WIDTH,
+    },
+    pool::{EntityPool, Pooled},
+    rng::{make_rng, CityRng, RngKind},
+    season::Season,
+    settings::Settings,
+    time_of_day::BuildingWindows,
+    traffic::{Car, CarLane, Pedestrian, Road, RoadCurve, WalkPath},
+    weather::Weather,
+};
 
 #[derive(Component)]
 pub struct CityRoot;
 
+/// A single block of the city, the content of the block is spawned as its children
+#[derive(Component)]
+pub struct CityBlock {
+    pub coord: IVec2,
+    pub tier: DensityTier,
+    pub district: District,
+    /// Number of times the block was regenerated on its own, it's mixed into the seeds of the
+    /// block so each regeneration gives a different block
+    pub reroll: u32,
+    /// Whether the roads, buildings and other details of the block have been spawned
+    pub detail_visible: bool,
+    pub neighbors: BlockNeighbors,
+}
+
+/// The tier of the block and of the 8 blocks around it, `None` for the blocks that aren't part
+/// of the city
+///
+/// The roads of a block are connected to the crossroads of its neighbors, this decides which
+/// road piece is used for each crossroad.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct BlockNeighbors([[Option<DensityTier>; 3]; 3]);
+
+impl BlockNeighbors {
+    pub fn new(block: IVec2, blocks: &HashMap<IVec2, DensityTier>) -> Self {
+        let mut neighbors = [[None; 3]; 3];
+        for (x, column) in neighbors.iter_mut().enumerate() {
+            for (z, tier) in column.iter_mut().enumerate() {
+                *tier = blocks
+                    .get(&(block + IVec2::new(x as i32 - 1, z as i32 - 1)))
+                    .copied();
+            }
+        }
+        Self(neighbors)
+    }
+
+    /// The tier of the block at the given offset from this block
+    pub fn tier(&self, offset: IVec2) -> Option<DensityTier> {
+        self.0[(offset.x + 1) as usize][(offset.y + 1) as usize]
+    }
+
+    /// Whether the block at the given offset from this block exists
+    pub fn contains(&self, offset: IVec2) -> bool {
+        self.tier(offset).is_some()
+    }
+
+    /// Whether the crossroad of the block at the given offset, between 0 and 1 on both axis, is
+    /// a roundabout
+    ///
+    /// The roundabouts are only placed downtown, where all 4 blocks around the crossroad are
+    /// high density blocks, since they take some space from each of them.
+    pub fn has_roundabout(&self, config: &CityConfig, block: IVec2, crossroad: IVec2) -> bool {
+        let downtown = [IVec2::ZERO, IVec2::X, IVec2::Y, IVec2::ONE]
+            .into_iter()
+            .all(|around| self.tier(crossroad - around) == Some(DensityTier::High));
+        let crossroad = block + crossroad;
+        downtown
+            && block_roundabout_rng(config, crossroad.x, crossroad.y).random::<f32>()
+                < ROUNDABOUT_CHANCE
+    }
+
+    /// The crossroads of the block and of its neighbors at +x, +z and +x+z that are roundabouts
+    pub fn roundabouts(&self, config: &CityConfig, block: IVec2) -> Vec<IVec2> {
+        [IVec2::ZERO, IVec2::X, IVec2::Y, IVec2::ONE]
+            .into_iter()
+            .filter(|crossroad| self.has_roundabout(config, block, *crossroad))
+            .collect()
+    }
+}
+
+/// Marks the trees spawned in the forests and along the streets
+#[derive(Component)]
+pub struct TreeMarker;
+
+/// Marks the fences spawned around the houses and along the walking paths
+#[derive(Component)]
+pub struct FenceMarker;
+
+/// Marks the low, medium and high density buildings with the tier they were spawned for
+#[derive(Component)]
+pub struct BuildingMarker {
+    pub density: DensityTier,
+}
+
+/// What an entity of the city was spawned as, like the building set of a building, shown when
+/// clicking on it
+#[derive(Component, Clone, Copy, Debug)]
+pub struct SpawnInfo(pub &'static str);
+
+/// Marks the warehouses of the industrial blocks, they are also marked as medium density
+/// buildings since they are placed like them
+#[derive(Component)]
+pub struct IndustrialMarker;
+
+/// Marks the crossroads, roads and bridges
+#[derive(Component)]
+pub struct RoadMarker;
+
+/// Marks the ground tile under each block
+#[derive(Component)]
+pub struct GroundMarker;
+
+/// Marks the low poly boxes displayed instead of the buildings far from the camera
+#[derive(Component)]
+pub struct BuildingLod;
+
+/// Marks every car, the moving ones also have a [`Car`] and the parked ones a [`ParkedCarMarker`]
+#[derive(Component)]
+pub struct CarMarker;
+
+/// Marks the cars parked along the curb
+#[derive(Component)]
+pub struct ParkedCarMarker;
+
+/// Marks the sidewalks between the roads and the inside of the blocks
+#[derive(Component)]
+pub struct SidewalkMarker;
+
+/// The densities at which a block moves up to the next [`DensityTier`]
+///
+/// Each threshold is the upper bound of its tier, the blocks above `medium` get skyscrapers.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DensityThresholds {
+    pub forest: f64,
+    pub park: f64,
+    pub low: f64,
+    pub medium: f64,
+}
+
+impl DensityThresholds {
+    /// Whether every tier covers a range of the 0..1 density, otherwise some tiers can never be
+    /// picked
+    pub fn is_ordered(&self) -> bool {
+        0.0 <= self.forest
+            && self.forest <= self.park
+            && self.park <= self.low
+            && self.low <= self.medium
+            && self.medium <= 1.0
+    }
+}
+
+impl Default for DensityThresholds {
+    fn default() -> Self {
+        Self {
+            forest: 0.45,
+            park: 0.5,
+            low: 0.6,
+            medium: 0.7,
+        }
+    }
+}
+
+/// What kind of content a block is filled with, picked from the density of the block
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum DensityTier {
+    Forest,
+    Park,
+    Low,
+    Medium,
+    High,
+    /// Flooded by a river, only the roads crossing it are spawned as bridges
+    Water,
+}
+
+impl DensityTier {
+    /// Picks the tier of a density with the thresholds of the config
+    ///
+    /// Everything classifying densities goes through this, like the layout and the density dump,
+    /// so they always agree. The water is picked from its own noise channel, not from the density.
+    pub fn from_density(density: f64, config: &CityConfig) -> Self {
+        let thresholds = &config.density_thresholds;
+        if density < thresholds.forest {
+            DensityTier::Forest
+        } else if density < thresholds.park {
+            DensityTier::Park
+        } else if density < thresholds.low {
+            DensityTier::Low
+        } else if density < thresholds.medium {
+            DensityTier::Medium
+        } else {
+            DensityTier::High
+        }
+    }
+}
+
+/// Parameters used to generate the city
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CityConfig {
+    /// Seed used for every random decision made while generating the city
+    pub seed: u64,
+    /// Algorithm of the rngs seeded from `seed`, see [`RngKind`] for which one gives the same
+    /// city across rand versions
+    pub rng_kind: RngKind,
+    /// Number of blocks on each side of the grid
+    pub size: u32,
+    /// Rotates every building to a random cardinal direction instead of facing the road
+    pub randomize_building_yaw: bool,
+    /// Range of the random vertical scale applied to each skyscraper
+    pub skyscraper_height_range: RangeInclusive<f32>,
+    /// Probability of a parking spot in front of low and medium density buildings being taken
+    pub parked_car_density: f32,
+    /// Probability of each spot of the roads having a car driving on it
+    pub car_density: f32,
+    /// Gives each building one of a few tints so the blocks using the same textures look less
+    /// monotone, at the cost of a few more materials
+    pub tint_buildings: bool,
+    /// Paints the body of each car with one of a few colors instead of the color of its model
+    pub randomize_car_color: bool,
+    /// Probability of each straight road being a one-way street, both of its lanes then go in
+    /// the same direction
+    pub one_way_probability: f32,
+    /// Makes the cars stuck behind slower ones move to another lane going the same way when
+    /// it's free, only the one-way streets have several lanes going the same way
+    pub lane_changing: bool,
+    /// Range of the random speed given to each car
+    pub car_speed_range: RangeInclusive<f32>,
+    /// Max speed of heavy vehicles like trucks and vans, `None` uses the max of `car_speed_range`
+    pub heavy_vehicle_max_speed: Option<f32>,
+    /// Only spawns the ground tiles of the blocks behind the camera at startup, the rest of the
+    /// block is spawned once it comes in front of the camera
+    pub cull_offscreen_detail: bool,
+    /// Number of blocks whose roads, buildings and other details are spawned each frame, the
+    /// city is spread over several frames so generating a large one doesn't freeze the app. 0
+    /// spawns the whole city at once
+    pub spawn_budget: usize,
+    /// Noise used to pick the density of each block
+    pub noise: NoiseConfig,
+    /// Densities of the noise at which the blocks switch from one tier to the next
+    pub density_thresholds: DensityThresholds,
+    /// Distance from the camera past which the buildings are replaced by low poly boxes
+    pub lod_distance: f32,
+    /// Moves the cars and pedestrians on the fixed timestep, interpolating their rendered
+    /// position, so the traffic is the same for a given seed no matter the frame rate
+    pub deterministic_traffic: bool,
+    /// Floods the blocks where the water noise channel is above `water_level`
+    pub rivers_enabled: bool,
+    /// Threshold of the water noise channel, in the 0..1 range, higher values give thinner rivers
+    pub water_level: f32,
+    /// Colors of the trees and the grass, it can be changed without regenerating the city
+    pub season: Season,
+    /// Lighting, fog and rain over the city, it can be changed without regenerating the city
+    pub weather: Weather,
+    /// How far the trees tilt when swaying in the wind, in radians, 0 keeps them still
+    ///
+    /// Off by default since swaying moves every visible tree on every frame.
+    pub wind_strength: f32,
+    /// Chance for each car to be an ambulance, a police car or a fire truck instead
+    pub emergency_vehicle_chance: f32,
+    /// How high the hills of the terrain raise the blocks, 0 keeps the city flat
+    pub terrain_amplitude: f32,
+}
+
+impl Default for CityConfig {
+    fn default() -> Self {
+        Self {
+            seed: 42,
+            rng_kind: RngKind::default(),
+            size: 30,
+            randomize_building_yaw: false,
+            skyscraper_height_range: 0.8..=1.6,
+            parked_car_density: 0.3,
+            car_density: 0.4,
+            tint_buildings: false,
+            randomize_car_color: false,
+            one_way_probability: 0.0,
+            lane_changing: false,
+            car_speed_range: 1.0..=2.0,
+            heavy_vehicle_max_speed: Some(1.4),
+            cull_offscreen_detail: false,
+            spawn_budget: 16,
+            noise: NoiseConfig::default(),
+            density_thresholds: DensityThresholds::default(),
+            lod_distance: 60.0,
+            deterministic_traffic: false,
+            rivers_enabled: false,
+            water_level: 0.9,
+            season: Season::default(),
+            weather: Weather::default(),
+            wind_strength: 0.0,
+            emergency_vehicle_chance: 0.02,
+            terrain_amplitude: 0.0,
+        }
+    }
+}
+
 /// Spawns a grid of city blocks
 ///
 /// For simplicity we spawn the roads and buildings in this pattern
@@ -21,331 +335,1232 @@ pub struct CityRoot;
 /// Each city block is 5.5 units x 4.0 units.
 ///
 /// Every asset gets spawned relative to the crossroad position
-pub fn spawn_city(commands: &mut Commands, assets: &CityAssets, seed: u64, size: u32) {
-    let mut rng = SmallRng::seed_from_u64(seed);
-    let noise = OpenSimplex::new(rng.random());
-    let noise_scale = 0.025;
-
+///
+/// Every random choice comes from the rng of its block so the same config always produces the
+/// same city. Only the asset handles are used, the assets don't need to be loaded, so this can
+/// also run in a headless app.
+pub fn spawn_city(
+    commands: &mut Commands,
+    assets: &CityAssets,
+    config: &CityConfig,
+    layout: &CityLayout,
+    pool: &mut EntityPool,
+    camera: Option<&Transform>,
+) {
+    let density = DensityField::new(config.seed, config.rng_kind, &config.noise);
     commands
         .spawn((CityRoot, Transform::default(), Visibility::default()))
         .with_children(|commands| {
-            let half_size = size as i32 / 2;
-            for x in -half_size..half_size {
-                for z in -half_size..half_size {
-                    // scale the position to match the city block size
-                    let x = x as f32 * 5.5;
-                    let z = z as f32 * 4.0;
-                    let offset = Vec3::new(x, 0.0, z);
-
-                    spawn_roads_and_cars(commands, assets, &mut rng, offset);
-
-                    let density = noise.get([
-                        offset.x as f64 * noise_scale,
-                        offset.z as f64 * noise_scale,
-                        0.0,
-                    ]) * 0.5
-                        + 0.5;
-
-                    let forest = 0.45;
-                    let low_density = 0.6;
-                    let medium_density = 0.7;
-
-                    let ground_tile_scale = Vec3::new(4.5, 1.0, 3.0);
-                    commands.spawn((
-                        Mesh3d(assets.ground_tile.0.clone()),
-                        if density < low_density {
-                            MeshMaterial3d(assets.ground_tile.2.clone())
-                        } else {
-                            MeshMaterial3d(assets.ground_tile.1.clone())
+            let blocks = layout
+                .blocks
+                .iter()
+                .map(|block| (IVec2::from_array(block.coord), block.tier))
+                .collect::<HashMap<_, _>>();
+            for block_layout in &layout.blocks {
+                let block = IVec2::from_array(block_layout.coord);
+                let tier = block_layout.tier;
+                let district = block_layout.district();
+                let reroll = block_layout.reroll;
+                let neighbors = BlockNeighbors::new(block, &blocks);
+                // With a spawn budget the details are all left to `spawn_pending_block_detail`
+                let detail_visible = config.spawn_budget == 0
+                    && (!config.cull_offscreen_detail
+                        || camera.is_none_or(|camera| !is_block_behind_camera(camera, block)));
+
+                commands
+                    .spawn((
+                        CityBlock {
+                            coord: block,
+                            tier,
+                            district,
+                            reroll,
+                            detail_visible,
+                            neighbors,
                         },
-                        Transform::from_translation(
-                            Vec3::new(0.5, -0.5005, 0.5) + ground_tile_scale / 2.0 + offset,
-                        )
-                        .with_scale(ground_tile_scale),
-                    ));
-
-                    if density < forest {
-                        spawn_forest(commands, assets, &mut rng, offset);
-                    } else if density < low_density {
-                        spawn_low_density(commands, assets, &mut rng, offset);
-                    } else if density < medium_density {
-                        spawn_medium_density(commands, assets, &mut rng, offset);
-                    } else {
-                        spawn_high_density(commands, assets, &mut rng, offset);
-                    }
-                }
+                        Transform::from_xyz(0.0, block_height(config, &density, block), 0.0),
+                        Visibility::default(),
+                    ))
+                    .with_children(|commands| {
+                        spawn_ground_tile(commands, assets, tier, block);
+                        if detail_visible {
+                            spawn_city_block(
+                                commands, assets, config, pool, tier, district, block, reroll,
+                                neighbors,
+                            );
+                        }
+                    });
             }
         });
 }
 
-fn spawn_roads_and_cars<R: RngExt>(
+/// The position of the crossroad of the block, every asset of the block is spawned relative to it
+pub fn block_offset(block: IVec2) -> Vec3 {
+    // scale the position to match the city block size
+    Vec3::new(block.x as f32 * 5.5, 0.0, block.y as f32 * 4.0)
+}
+
+/// The block whose crossroad is the closest one before the position on both axis
+pub fn block_at(position: Vec3) -> IVec2 {
+    IVec2::new(
+        (position.x / 5.5).floor() as i32,
+        (position.z / 4.0).floor() as i32,
+    )
+}
+
+/// The height of the ground of the block, the ground tile and everything on the block are raised
+/// by it
+pub fn block_height(config: &CityConfig, density: &DensityField, block: IVec2) -> f32 {
+    if config.terrain_amplitude == 0.0 {
+        return 0.0;
+    }
+    density.block_terrain(block) as f32 * config.terrain_amplitude
+}
+
+/// The coordinates of the blocks along each side of the grid
+///
+/// A grid smaller than two blocks still has the block at the origin, so the city is never empty
+/// and its bounds never collapse to a point.
+pub fn grid_blocks(config: &CityConfig) -> Range<i32> {
+    let half_extent = config.size as i32 / 2;
+    -half_extent..half_extent.max(1)
+}
+
+/// The corners of the area covered by the grid, from the crossroad of the first block to the far
+/// edge of the last block
+pub fn city_bounds(config: &CityConfig) -> (Vec3, Vec3) {
+    let blocks = grid_blocks(config);
+    (
+        block_offset(IVec2::splat(blocks.start)),
+        block_offset(IVec2::splat(blocks.end)),
+    )
+}
+
+/// The area covered by the grid of the [`CityConfig`], kept up to date when the config changes
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct CityBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl CityBounds {
+    pub fn new(config: &CityConfig) -> Self {
+        let (min, max) = city_bounds(config);
+        Self { min, max }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    /// Radius of the circle around the center covering the whole grid
+    pub fn radius(&self) -> f32 {
+        (self.max - self.min).length() / 2.0
+    }
+}
+
+pub fn update_city_bounds(config: Res<CityConfig>, mut bounds: ResMut<CityBounds>) {
+    if config.is_changed() {
+        *bounds = CityBounds::new(&config);
+    }
+}
+
+/// Checks if the whole block, including its tallest buildings, is behind the camera
+fn is_block_behind_camera(camera: &Transform, block: IVec2) -> bool {
+    let offset = block_offset(block);
+    let forward = camera.forward();
+    [0.0, 5.5].into_iter().all(|x| {
+        [0.0, 4.0].into_iter().all(|z| {
+            [0.0, 5.0].into_iter().all(|y| {
+                let corner = offset + Vec3::new(x, y, z);
+                (corner - camera.translation).dot(*forward) < 0.0
+            })
+        })
+    })
+}
+
+fn spawn_ground_tile(
     commands: &mut ChildSpawnerCommands,
     assets: &CityAssets,
-    rng: &mut R,
-    offset: Vec3,
+    tier: DensityTier,
+    block: IVec2,
 ) {
-    let x = offset.x;
-    let z = offset.z;
-
+    let offset = block_offset(block);
+    let ground_tile_scale = Vec3::new(4.5, 1.0, 3.0);
     commands.spawn((
-        SceneRoot(assets.crossroad.clone()),
-        Transform::from_xyz(x, 0.0, z),
+        Mesh3d(assets.ground_tiles.mesh.clone()),
+        MeshMaterial3d(ground_material_for_density(tier, &assets.ground_tiles).clone()),
+        GroundMarker,
+        // Lets the empty blocks be clicked to regenerate them
+        Pickable::default(),
+        Transform::from_translation(
+            Vec3::new(0.5, -0.5005, 0.5) + ground_tile_scale / 2.0 + offset,
+        )
+        .with_scale(ground_tile_scale),
     ));
+}
 
-    let max_car_density = 0.4;
+/// Picks the material of the ground tile of a block, the downtown blocks are paved
+pub fn ground_material_for_density(
+    density: DensityTier,
+    ground_tiles: &GroundTiles,
+) -> &Handle<StandardMaterial> {
+    match density {
+        DensityTier::Forest | DensityTier::Park | DensityTier::Low => &ground_tiles.grass_material,
+        DensityTier::Medium => &ground_tiles.sidewalk_material,
+        DensityTier::High => &ground_tiles.asphalt_material,
+        DensityTier::Water => &ground_tiles.water_material,
+    }
+}
 
-    // When spawning roads we rotate and stretch a single road asset instead of spawning multiple
-    // road segments
+/// Spawns the roads, cars, buildings and other details of a single city block
+///
+/// Every block uses its own rng derived from the global seed and the block coordinate. This way a
+/// block always looks the same no matter in which order it gets spawned or if it's spawned on its
+/// own.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_city_block(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    tier: DensityTier,
+    district: District,
+    block: IVec2,
+    reroll: u32,
+    neighbors: BlockNeighbors,
+) {
+    let mut rng = block_rng(config, block.x, block.y, reroll);
+    let offset = block_offset(block);
+    let roundabouts = neighbors.roundabouts(config, block);
 
-    // NOTE most of the magic numbers were hand tweaked for something that looks visually nice
+    spawn_crossroads(commands, assets, neighbors, &roundabouts, offset);
+    let mut roads = Vec::new();
+    if tier == DensityTier::Water {
+        spawn_bridges(commands, assets, offset);
+    } else {
+        let mut car_rng = block_car_rng(config, block.x, block.y, reroll);
+        roads = Vec::from(spawn_roads_and_cars(
+            commands,
+            assets,
+            config,
+            pool,
+            &mut car_rng,
+            &roundabouts,
+            offset,
+        ));
+        spawn_sidewalks(commands, assets, &roundabouts, offset);
+    }
 
-    // horizontal road
-    let car_count = 9;
+    match tier {
+        DensityTier::Forest => {
+            spawn_forest(commands, assets, config, pool, &mut rng, offset);
+        }
+        DensityTier::Park => {
+            spawn_park(commands, assets, config, pool, &mut rng, offset);
+        }
+        DensityTier::Low => {
+            spawn_low_density(commands, assets, config, pool, &mut rng, offset);
+        }
+        DensityTier::Medium if district == District::Industrial => {
+            spawn_industrial(commands, assets, config, pool, &mut rng, offset);
+        }
+        DensityTier::Medium => {
+            spawn_medium_density(commands, assets, config, pool, &mut rng, offset, &roads);
+        }
+        DensityTier::High => {
+            spawn_high_density(
+                commands,
+                assets,
+                config,
+                pool,
+                &mut rng,
+                &roundabouts,
+                offset,
+            );
+        }
+        DensityTier::Water => {}
+    }
+}
+
+/// Spawns the details of the blocks that were skipped when the city was spawned, at most
+/// [`CityConfig::spawn_budget`] blocks per frame starting with the closest ones to the camera
+///
+/// When the offscreen detail is culled the blocks behind the camera wait until they come in
+/// front of it. Every block draws from its own rng so the city is the same no matter how many
+/// frames it's spread over.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_pending_block_detail(
+    mut commands: Commands,
+    assets: Res<CityAssets>,
+    settings: Res<Settings>,
+    mut config: ResMut<CityConfig>,
+    mut pool: ResMut<EntityPool>,
+    mut progress: ResMut<GenerationProgress>,
+    camera: Option<Single<&Transform, With<FreeCamera>>>,
+    mut blocks: Query<(Entity, &mut CityBlock)>,
+) {
+    let camera = camera.map(Single::into_inner);
+    let culled = |block: &CityBlock| {
+        config.cull_offscreen_detail
+            && camera.is_some_and(|camera| is_block_behind_camera(camera, block.coord))
+    };
+    let mut pending = blocks
+        .iter_mut()
+        .filter(|(_, block)| !block.detail_visible && !culled(block))
+        .collect::<Vec<_>>();
+    if pending.is_empty() && !progress.generating {
+        return;
+    }
+    if let Some(camera) = camera {
+        pending.sort_by(|(_, a), (_, b)| {
+            let distance =
+                |block: &CityBlock| block_offset(block.coord).distance_squared(camera.translation);
+            distance(a).total_cmp(&distance(b))
+        });
+    }
+    let budget = match config.spawn_budget {
+        0 => pending.len(),
+        budget => budget.min(pending.len()),
+    };
+    let remaining = pending.len() - budget;
+    if budget > 0 {
+        sync_car_density(&mut config, &settings);
+    }
+
+    for (entity, mut block) in pending.into_iter().take(budget) {
+        block.detail_visible = true;
+        commands.entity(entity).with_children(|commands| {
+            spawn_city_block(
+                commands,
+                &assets,
+                &config,
+                &mut pool,
+                block.tier,
+                block.district,
+                block.coord,
+                block.reroll,
+                block.neighbors,
+            );
+        });
+    }
+
+    if progress.generating && remaining == 0 {
+        // Finishing the generation triggers `CityRegenerated` once the pool is cleared
+        finish_generation(&mut commands, &config, &mut pool, &mut progress);
+        return;
+    }
+    if progress.generating {
+        progress.spawned_blocks = progress.total_blocks.saturating_sub(remaining);
+    }
+    if budget > 0 {
+        commands.trigger(CityRegenerated);
+    }
+}
+
+/// Despawns the content of a block and spawns it again with the next seed of the block, the rest
+/// of the city is left untouched
+///
+/// Only the content of the block changes, the tier and the crossroads stay the same since the
+/// neighbors of the block depend on them.
+pub fn reroll_block(
+    commands: &mut Commands,
+    assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    entity: Entity,
+    block: &mut CityBlock,
+) {
+    block.reroll += 1;
     commands
-        .spawn((
-            Transform::from_translation(offset),
-            Visibility::default(),
-            Road {
-                start: Vec3::new(0.75, 0.0, 0.0),
-                end: Vec3::new(0.75 + (0.5 * car_count as f32), 0.0, 0.0),
-            },
-        ))
+        .entity(entity)
+        .despawn_related::<Children>()
         .with_children(|commands| {
-            commands.spawn((
-                SceneRoot(assets.road_straight.clone()),
-                Transform::from_translation(Vec3::new(2.75, 0.0, 0.0))
-                    .with_scale(Vec3::new(4.5, 1.0, 1.0)),
-            ));
-
-            for i in 0..car_count {
-                let car_pos = Vec3::new(0.0, 0.0, 0.75 + i as f32 * 0.5);
-
-                if rng.random::<f32>() < max_car_density {
-                    commands.spawn((
-                        SceneRoot(assets.get_random_car(rng)),
-                        Transform::from_translation(car_pos + Vec3::new(0.0, 0.0, -0.15))
-                            .with_scale(Vec3::splat(0.15))
-                            .with_rotation(Quat::from_axis_angle(
-                                Vec3::Y,
-                                3.0 * std::f32::consts::FRAC_PI_2,
-                            )),
-                        Car {
-                            distance_traveled: i as f32 * 0.5,
-                            dir: -1.0,
-                            offset: Vec3::new(4.25, 0.0, -0.15),
-                        },
-                    ));
-                }
-
-                if rng.random::<f32>() < max_car_density {
-                    commands.spawn((
-                        SceneRoot(assets.get_random_car(rng)),
-                        Transform::from_translation(car_pos + Vec3::new(0.0, 0.0, 0.15))
-                            .with_scale(Vec3::splat(0.15))
-                            .with_rotation(Quat::from_axis_angle(
-                                Vec3::Y,
-                                std::f32::consts::FRAC_PI_2,
-                            )),
-                        Car {
-                            distance_traveled: i as f32 * 0.5,
-                            dir: 1.0,
-                            offset: Vec3::new(-0.25, 0.0, 0.15),
-                        },
-                    ));
-                }
+            spawn_ground_tile(commands, assets, block.tier, block.coord);
+            if block.detail_visible {
+                spawn_city_block(
+                    commands,
+                    assets,
+                    config,
+                    pool,
+                    block.tier,
+                    block.district,
+                    block.coord,
+                    block.reroll,
+                    block.neighbors,
+                );
             }
         });
+    commands.trigger(CityRegenerated);
+}
+
+/// Creates the rng used to spawn the content of the block at the given block coordinate
+///
+/// `reroll` is the number of times the block was regenerated on its own, see
+/// [`CityBlock::reroll`].
+pub fn block_rng(config: &CityConfig, block_x: i32, block_z: i32, reroll: u32) -> CityRng {
+    make_rng(
+        block_seed(config.seed, block_x, block_z).wrapping_add(reroll as u64),
+        config.rng_kind,
+    )
+}
+
+/// Creates the rng deciding where the cars of the block drive
+///
+/// The cars have their own stream so the number of cars of a block doesn't change when the
+/// buildings, trees or anything else of the block draws more or less from the block rng.
+pub fn block_car_rng(config: &CityConfig, block_x: i32, block_z: i32, reroll: u32) -> CityRng {
+    let seed = block_seed(config.seed, block_x, block_z).wrapping_add(reroll as u64);
+    make_rng(splitmix64(seed ^ CAR_RNG_STREAM), config.rng_kind)
+}
+
+/// Mixed into the seed of the block to derive the seed of the car rng
+const CAR_RNG_STREAM: u64 = 0xca75;
+
+/// Creates the rng deciding which cars of the block are added or removed when the car density
+/// changes to `density`
+///
+/// The same changes of the density add and remove the same cars for a seed.
+fn block_car_density_rng(config: &CityConfig, block: &CityBlock, density: f32) -> CityRng {
+    let seed = block_seed(config.seed, block.coord.x, block.coord.y)
+        .wrapping_add(block.reroll as u64)
+        ^ ((density.to_bits() as u64) << 32);
+    make_rng(splitmix64(seed ^ CAR_DENSITY_RNG_STREAM), config.rng_kind)
+}
+
+/// Mixed into the seed of the block to derive the seed of the car density rng
+const CAR_DENSITY_RNG_STREAM: u64 = 0xde75;
+
+/// Creates the rng deciding if the crossroad of the block is a roundabout
+///
+/// The neighbors of the block need to know it too so this can't depend on anything else drawn
+/// for the block.
+fn block_roundabout_rng(config: &CityConfig, block_x: i32, block_z: i32) -> CityRng {
+    make_rng(
+        splitmix64(block_seed(config.seed, block_x, block_z) ^ ROUNDABOUT_RNG_STREAM),
+        config.rng_kind,
+    )
+}
+
+/// Mixed into the seed of the block to derive the seed of the roundabout rng
+const ROUNDABOUT_RNG_STREAM: u64 = 0x40b0;
+
+/// Probability of a downtown crossroad being a roundabout
+const ROUNDABOUT_CHANCE: f32 = 0.15;
+/// Distance from the center of a roundabout to the middle of its road
+const ROUNDABOUT_RADIUS: f32 = 0.75;
+/// Distance from the center of a roundabout to its exits, the roundabout covers 3 road tiles
+pub const ROUNDABOUT_HALF_SIZE: f32 = 1.5;
+
+/// Hashes the global seed and the block coordinate into a seed for that block
+///
+/// This uses splitmix64 instead of the std hasher because the std hasher isn't guaranteed to be
+/// stable across rust versions.
+fn block_seed(seed: u64, block_x: i32, block_z: i32) -> u64 {
+    let hash = splitmix64(seed ^ block_x as u32 as u64);
+    splitmix64(hash ^ ((block_z as u32 as u64) << 32))
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Spawns the roads of the block with their cars, returns the straight roads along with their
+/// entity
+#[allow(clippy::too_many_arguments)]
+fn spawn_roads_and_cars<R: RngExt>(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    rng: &mut R,
+    roundabouts: &[IVec2],
+    offset: Vec3,
+) -> [(Entity, Road); 2] {
+    // When spawning roads we rotate and stretch a single road asset instead of spawning multiple
+    // road segments
+
+    // NOTE most of the magic numbers were hand tweaked for something that looks visually nice
+
+    // The roads go from the edge of the crossroad of the block to the edge of the crossroad of
+    // the next block
+    let crossroad_half_size = |crossroad: IVec2| {
+        if roundabouts.contains(&crossroad) {
+            ROUNDABOUT_HALF_SIZE
+        } else {
+            0.5
+        }
+    };
+    let block_size = block_offset(IVec2::ONE);
+
+    // horizontal road, the start and end match the ends of the stretched road asset
+    let mut horizontal = Road::new(
+        Vec3::new(crossroad_half_size(IVec2::ZERO), 0.0, 0.0),
+        Vec3::new(block_size.x - crossroad_half_size(IVec2::X), 0.0, 0.0),
+        None,
+        [-1.0, 1.0],
+    );
+    horizontal.lanes = one_way_lanes(config, rng, horizontal.lanes);
+    let horizontal_entity = commands
+        .spawn((Transform::from_translation(offset), Visibility::default()))
+        .with_children(|commands| {
+            spawn_scene(
+                commands,
+                &assets.road_straight,
+                Transform::from_translation(horizontal.start.midpoint(horizontal.end))
+                    .with_scale(Vec3::new(horizontal.length(), 1.0, 1.0)),
+                RoadMarker,
+            );
+            spawn_road_cars(commands, assets, config, pool, rng, &horizontal);
+        })
+        .insert(horizontal.clone())
+        .id();
 
     // vertical road
-    let car_count = 6;
-    commands
-        .spawn((
-            Transform::from_translation(offset),
-            Visibility::default(),
-            Road {
-                start: Vec3::new(0.0, 0.0, 0.75),
-                end: Vec3::new(0.0, 0.0, 0.75 + (0.5 * car_count as f32)),
-            },
-        ))
+    let mut vertical = Road::new(
+        Vec3::new(0.0, 0.0, crossroad_half_size(IVec2::ZERO)),
+        Vec3::new(0.0, 0.0, block_size.z - crossroad_half_size(IVec2::Y)),
+        None,
+        [1.0, -1.0],
+    );
+    vertical.lanes = one_way_lanes(config, rng, vertical.lanes);
+    let vertical_entity = commands
+        .spawn((Transform::from_translation(offset), Visibility::default()))
         .with_children(|commands| {
-            commands.spawn((
-                SceneRoot(assets.road_straight.clone()),
-                Transform::from_translation(Vec3::new(0.0, 0.0, 2.0))
-                    .with_scale(Vec3::new(3.0, 1.0, 1.0))
+            spawn_scene(
+                commands,
+                &assets.road_straight,
+                Transform::from_translation(vertical.start.midpoint(vertical.end))
+                    .with_scale(Vec3::new(vertical.length(), 1.0, 1.0))
                     .with_rotation(Quat::from_axis_angle(Vec3::Y, std::f32::consts::FRAC_PI_2)),
-            ));
-
-            for i in 0..car_count {
-                let car_pos = Vec3::new(0.0, 0.0, 0.75 + i as f32 * 0.5);
-
-                if rng.random::<f32>() < max_car_density {
-                    commands.spawn((
-                        SceneRoot(assets.get_random_car(rng)),
-                        Transform::from_translation(car_pos + Vec3::new(0.15, 0.0, 0.0))
-                            .with_scale(Vec3::splat(0.15)),
-                        Car {
-                            distance_traveled: i as f32 * 0.5,
-                            dir: 1.0,
-                            offset: Vec3::new(-0.15, 0.0, -0.25),
-                        },
-                    ));
-                }
+                RoadMarker,
+            );
+            spawn_road_cars(commands, assets, config, pool, rng, &vertical);
+        })
+        .insert(vertical.clone())
+        .id();
 
-                if rng.random::<f32>() < max_car_density {
-                    commands.spawn((
-                        SceneRoot(assets.get_random_car(rng)),
-                        Transform::from_translation(car_pos + Vec3::new(-0.15, 0.0, 0.0))
-                            .with_scale(Vec3::splat(0.15))
-                            .with_rotation(Quat::from_axis_angle(Vec3::Y, std::f32::consts::PI)),
-                        Car {
-                            distance_traveled: i as f32 * 0.5,
-                            dir: -1.0,
-                            offset: Vec3::new(0.15, 0.0, 2.75),
-                        },
-                    ));
-                }
-            }
-        });
+    if roundabouts.contains(&IVec2::ZERO) {
+        // Both lanes go around counterclockwise, the inner lane is on the left of the cars
+        let mut road = Road::new(
+            Vec3::new(ROUNDABOUT_RADIUS, 0.0, 0.0),
+            Vec3::new(ROUNDABOUT_RADIUS, 0.0, 0.0),
+            Some(roundabout_curve()),
+            [1.0, 1.0],
+        );
+        road.lanes[0].offset = road.lane_offset(-1.0, 0);
+        commands
+            .spawn((Transform::from_translation(offset), Visibility::default()))
+            .with_children(|commands| {
+                spawn_road_cars(commands, assets, config, pool, rng, &road);
+            })
+            .insert(road);
+    }
+
+    [(horizontal_entity, horizontal), (vertical_entity, vertical)]
 }
 
-fn spawn_low_density<R: RngExt>(
+/// Turns the two lanes of a straight road into a one-way street with a probability of
+/// `one_way_probability`, both lanes then go in the direction of one of them picked randomly
+///
+/// The buses follow the direction of the lanes of their route so they never drive the wrong way
+/// on a one-way street. This doesn't draw from the rng when there are no one-way streets so the
+/// rest of the city stays the same.
+fn one_way_lanes<R: RngExt>(config: &CityConfig, rng: &mut R, lanes: [CarLane; 2]) -> [CarLane; 2] {
+    if config.one_way_probability <= 0.0 || rng.random::<f32>() >= config.one_way_probability {
+        return lanes;
+    }
+    let dir = lanes[rng.random_range(0..lanes.len())].dir;
+    lanes.map(|lane| CarLane { dir, ..lane })
+}
+
+/// The ring of a roundabout, made of a bezier curve for each quarter of the circle
+///
+/// It starts at +x and goes counterclockwise when seen from above so the cars keep the center of
+/// the roundabout on their left.
+fn roundabout_curve() -> RoadCurve {
+    // Distance of the control points from the ends of a quarter for it to be close to a circle
+    let handle = ROUNDABOUT_RADIUS * 0.5523;
+    RoadCurve::new((0..4).map(|quarter| {
+        let rotation = Quat::from_rotation_y(quarter as f32 * FRAC_PI_2);
+        [
+            Vec3::new(ROUNDABOUT_RADIUS, 0.0, 0.0),
+            Vec3::new(ROUNDABOUT_RADIUS, 0.0, -handle),
+            Vec3::new(handle, 0.0, -ROUNDABOUT_RADIUS),
+            Vec3::new(0.0, 0.0, -ROUNDABOUT_RADIUS),
+        ]
+        .map(|point| rotation * point)
+    }))
+}
+
+/// Spawns a sidewalk along each of the roads around the block, between the crossroads at the
+/// corners of the block
+fn spawn_sidewalks(
     commands: &mut ChildSpawnerCommands,
     assets: &CityAssets,
-    rng: &mut R,
+    roundabouts: &[IVec2],
     offset: Vec3,
 ) {
-    for x in 1..=2 {
-        let x_factor = 1.8;
-        commands.spawn((
-            assets.low_density.get_random_building(rng),
-            Transform::from_translation(Vec3::new(x as f32 * x_factor, 0.0, 1.25) + offset),
-        ));
+    let center = block_offset(IVec2::ONE) / 2.0;
+    for (from, to) in [
+        (IVec2::ZERO, IVec2::X),
+        (IVec2::Y, IVec2::ONE),
+        (IVec2::ZERO, IVec2::Y),
+        (IVec2::X, IVec2::ONE),
+    ] {
+        let start = block_offset(from);
+        let end = block_offset(to);
+        let along = (end - start).normalize();
+        let inward = (center - start).reject_from(along).normalize();
+        // The sidewalks along the z axis stop at the ones along the x axis so they don't overlap
+        let corner = if along.z != 0.0 { SIDEWALK_WIDTH } else { 0.0 };
+        let trim = |crossroad: IVec2| {
+            if roundabouts.contains(&crossroad) {
+                ROUNDABOUT_HALF_SIZE
+            } else {
+                ROAD_HALF_WIDTH + corner
+            }
+        };
+        let start = start + along * trim(from);
+        let end = end - along * trim(to);
+        let length = start.distance(end);
+        let scale = if along.x != 0.0 {
+            Vec3::new(length, 1.0, SIDEWALK_WIDTH)
+        } else {
+            Vec3::new(SIDEWALK_WIDTH, 1.0, length)
+        };
         commands.spawn((
-            assets.low_density.get_random_building(rng),
-            Transform::from_translation(Vec3::new(x as f32 * x_factor, 0.0, 2.75) + offset)
-                .with_rotation(Quat::from_axis_angle(Vec3::Y, std::f32::consts::PI)),
+            Mesh3d(assets.ground_tiles.sidewalk.0.clone()),
+            MeshMaterial3d(assets.ground_tiles.sidewalk.1.clone()),
+            Transform::from_translation(
+                start.midpoint(end) + inward * (ROAD_HALF_WIDTH + SIDEWALK_WIDTH / 2.0) + offset,
+            )
+            .with_scale(scale),
+            SidewalkMarker,
         ));
     }
-    for i in 0..=6 {
-        commands.spawn((
-            SceneRoot(assets.fence.clone()),
-            Transform::from_translation(Vec3::new(2.75, 0.0, 0.75 + i as f32 * 0.4) + offset)
-                .with_rotation(Quat::from_axis_angle(Vec3::Y, std::f32::consts::FRAC_PI_2)),
-        ));
+}
+
+/// The directions the roads can leave a crossroad in, each one is a quarter turn from the
+/// previous one
+const ROAD_DIRECTIONS: [IVec2; 4] = [IVec2::X, IVec2::NEG_Y, IVec2::NEG_X, IVec2::Y];
+
+/// Spawns the crossroad of the block, along with the dead ends of its roads leading to a missing
+/// block
+///
+/// The crossroad of a missing block is spawned by its neighbor at -x, or by the one at -z when
+/// that one is missing too, so it's only spawned once.
+fn spawn_crossroads(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    neighbors: BlockNeighbors,
+    roundabouts: &[IVec2],
+    offset: Vec3,
+) {
+    if roundabouts.contains(&IVec2::ZERO) {
+        spawn_scene(
+            commands,
+            &assets.roundabout,
+            Transform::from_translation(offset),
+            RoadMarker,
+        );
+    } else {
+        spawn_crossroad(commands, assets, neighbors, IVec2::ZERO, offset);
     }
-    for z in 0..=8 {
-        commands.spawn((
-            SceneRoot(assets.tree_small.clone()),
-            Transform::from_translation(Vec3::new(0.75, 0.0, 0.75 + z as f32 * 0.3) + offset),
-        ));
-        commands.spawn((
-            SceneRoot(assets.tree_small.clone()),
-            Transform::from_translation(Vec3::new(4.75, 0.0, 0.75 + z as f32 * 0.3) + offset),
-        ));
+    if !neighbors.contains(IVec2::X) {
+        spawn_crossroad(commands, assets, neighbors, IVec2::X, offset);
+    }
+    if !neighbors.contains(IVec2::Y) && !neighbors.contains(IVec2::new(-1, 1)) {
+        spawn_crossroad(commands, assets, neighbors, IVec2::Y, offset);
     }
 }
 
-fn spawn_medium_density<R: RngExt>(
+/// Spawns the road piece connecting the roads leaving the crossroad of the block at the given
+/// offset from the current block
+fn spawn_crossroad(
     commands: &mut ChildSpawnerCommands,
     assets: &CityAssets,
-    rng: &mut R,
+    neighbors: BlockNeighbors,
+    crossroad: IVec2,
     offset: Vec3,
 ) {
-    let x_factor = 0.9;
-    for x in 1..=5 {
-        commands.spawn((
-            assets.medium_density.get_random_building(rng),
-            Transform::from_translation(Vec3::new(x as f32 * x_factor, 0.0, 1.0) + offset),
-        ));
+    // Each block owns the roads going to +x and +z from its crossroad
+    let roads = ROAD_DIRECTIONS.map(|dir| neighbors.contains(crossroad + dir.min(IVec2::ZERO)));
+    let Some((scene, rotation)) = road_piece(assets, roads) else {
+        return;
+    };
+    spawn_scene(
+        commands,
+        scene,
+        Transform::from_translation(offset + block_offset(crossroad)).with_rotation(rotation),
+        RoadMarker,
+    );
+}
+
+/// Picks the road piece with a road leaving in each of the [`ROAD_DIRECTIONS`] set in `roads`,
+/// along with the rotation lining it up with them
+fn road_piece(assets: &CityAssets, roads: [bool; 4]) -> Option<(&Handle<Scene>, Quat)> {
+    let pieces = [
+        (&assets.crossroad, [true, true, true, true]),
+        (&assets.road_junction, [true, false, true, true]),
+        (&assets.road_straight, [true, false, true, false]),
+        (&assets.road_bend, [false, false, true, true]),
+        (&assets.road_end, [true, false, false, false]),
+    ];
+    pieces.into_iter().find_map(|(scene, piece_roads)| {
+        (0..4)
+            .find(|turns| (0..4).all(|i| piece_roads[i] == roads[(i + turns) % 4]))
+            .map(|turns| (scene, Quat::from_rotation_y(turns as f32 * FRAC_PI_2)))
+    })
+}
+
+/// Distance between the spots cars can be spawned at along a road
+pub const CAR_SPACING: f32 = 0.5;
+/// Number of parking spots in the parking lot of the industrial blocks
+const PARKING_LOT_SPOTS: usize = 13;
+/// Smallest distance between a car added to a road and the other cars of its lane
+const MIN_CAR_GAP: f32 = 0.4;
 
-        for tree_x in 0..=1 {
-            let tree_x = tree_x as f32 * 0.5;
-            if x == 5 && tree_x == 0.5 {
-                break;
+/// Fills the spots of both lanes of the road with cars, each spot being taken with a
+/// probability of `car_density`
+fn spawn_road_cars<R: RngExt>(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    rng: &mut R,
+    road: &Road,
+) {
+    for i in 0..road.spot_count() {
+        for lane in road.lanes {
+            if rng.random::<f32>() < config.car_density {
+                spawn_car(
+                    commands,
+                    assets,
+                    config,
+                    pool,
+                    rng,
+                    road,
+                    lane,
+                    i as f32 * CAR_SPACING,
+                );
             }
-            commands.spawn((
-                SceneRoot(assets.tree_large.clone()),
-                Transform::from_translation(
-                    Vec3::new(tree_x + x as f32 * x_factor, 0.0, 1.75) + offset,
-                ),
-            ));
-            commands.spawn((
-                SceneRoot(assets.tree_large.clone()),
-                Transform::from_translation(
-                    Vec3::new(tree_x + x as f32 * x_factor, 0.0, 2.25) + offset,
-                ),
-            ));
         }
+    }
+}
 
-        commands.spawn((
-            assets.medium_density.get_random_building(rng),
-            Transform::from_translation(Vec3::new(x as f32 * x_factor, 0.0, 3.0) + offset)
-                .with_rotation(Quat::from_axis_angle(Vec3::Y, std::f32::consts::PI)),
+#[allow(clippy::too_many_arguments)]
+fn spawn_car<R: RngExt>(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    rng: &mut R,
+    road: &Road,
+    lane: CarLane,
+    distance_traveled: f32,
+) {
+    // The car models face +Z
+    let transform = Transform::from_translation(road.lane_position(lane, distance_traveled))
+        .with_scale(Vec3::splat(0.15))
+        .with_rotation(Quat::from_rotation_arc(
+            Vec3::Z,
+            road.lane_direction(lane, distance_traveled),
         ));
+    // Only roll for the emergency vehicles when they are enabled so the other cars keep their
+    // rng
+    if config.emergency_vehicle_chance > 0.0
+        && rng.random::<f32>() < config.emergency_vehicle_chance
+    {
+        let vehicle = assets.get_random_emergency_vehicle(rng);
+        // They aren't pooled since the lights of their siren would stay on the pooled entity
+        spawn_scene(
+            commands,
+            &vehicle.scene,
+            transform,
+            (
+                Car {
+                    speed: car_speed(rng, config, vehicle) * EMERGENCY_SPEED_MULTIPLIER,
+                    distance_traveled,
+                    lane,
+                    lane_change: None,
+                },
+                CarMarker,
+                EmergencyVehicle,
+                SpawnInfo("Emergency vehicle"),
+            ),
+        )
+        .with_children(|commands| spawn_siren(commands, assets));
+        return;
+    }
+    let car = assets.get_random_car(rng);
+    let mut car_commands = pool.spawn_scene(commands, &car.scene, transform);
+    car_commands.insert((
+        Car {
+            speed: car_speed(rng, config, car),
+            distance_traveled,
+            lane,
+            lane_change: None,
+        },
+        CarMarker,
+        SpawnInfo("Car"),
+    ));
+    if let Some(paint) = random_car_paint(config, rng) {
+        car_commands.insert(paint);
     }
+}
 
-    for x in 0..=10 {
-        commands.spawn((
-            SceneRoot(assets.path_stones_long.clone()),
-            Transform::from_translation(Vec3::new(0.75 + (x as f32 * 0.4), 0.02, 2.0) + offset)
-                .with_scale(Vec3::new(1.0, 2.0, 1.0))
-                .with_rotation(Quat::from_axis_angle(Vec3::Y, std::f32::consts::FRAC_PI_2)),
-        ));
-        commands.spawn((
-            SceneRoot(assets.fence.clone()),
-            Transform::from_translation(Vec3::new(0.75 + (x as f32 * 0.4), 0.02, 1.85) + offset),
-        ));
-        commands.spawn((
-            SceneRoot(assets.fence.clone()),
-            Transform::from_translation(Vec3::new(0.75 + (x as f32 * 0.4), 0.02, 2.15) + offset),
-        ));
+/// Makes the blocks about to be spawned use the car density of the settings
+///
+/// The density is only copied to the config when blocks are spawned, copying it on every step
+/// of the slider would rebuild everything depending on the config each time.
+pub fn sync_car_density(config: &mut ResMut<CityConfig>, settings: &Settings) {
+    if config.car_density != settings.car_density {
+        config.car_density = settings.car_density;
     }
 }
 
-fn spawn_high_density<R: RngExt>(
+/// Adds or removes cars on the roads when the car density setting changes
+///
+/// Lowering the density removes a random share of the cars, raising it fills the free spots of
+/// the roads with the same probability so the roads look like they were spawned with the new
+/// density. The removed cars go back to the pool so raising the density again reuses them.
+///
+/// `applied` is the density the cars on the roads were spawned with, the config only catches up
+/// when blocks are spawned, see [`sync_car_density`].
+#[allow(clippy::too_many_arguments)]
+pub fn apply_car_density(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    assets: Res<CityAssets>,
+    config: Res<CityConfig>,
+    mut pool: ResMut<EntityPool>,
+    mut applied: Local<Option<f32>>,
+    blocks: Query<(&CityBlock, &Children)>,
+    roads: Query<(Entity, &Road, Option<&Children>)>,
+    cars: Query<(&Car, Option<&Pooled>)>,
+) {
+    let old_density = applied.unwrap_or(config.car_density);
+    let new_density = settings.car_density;
+    if !settings.is_changed() || old_density == new_density {
+        return;
+    }
+    *applied = Some(new_density);
+
+    for (block, block_children) in &blocks {
+        let mut rng = block_car_density_rng(&config, block, new_density);
+        for (road_entity, road, children) in roads.iter_many(block_children) {
+            let road_cars = children
+                .into_iter()
+                .flatten()
+                .filter_map(|entity| Some((*entity, cars.get(*entity).ok()?)))
+                .collect::<Vec<_>>();
+
+            if new_density < old_density {
+                let keep_probability = new_density / old_density;
+                for (entity, (_, pooled)) in &road_cars {
+                    if rng.random::<f32>() < keep_probability {
+                        continue;
+                    }
+                    // The emergency vehicles aren't pooled since they have a siren
+                    match pooled {
+                        Some(pooled) => pool.release(&mut commands, *entity, pooled.0),
+                        None => commands.entity(*entity).despawn(),
+                    }
+                }
+                continue;
+            }
+
+            let fill_probability =
+                (new_density - old_density) / (1.0 - old_density).max(f32::EPSILON);
+            let road_len = road.length();
+            commands.entity(road_entity).with_children(|commands| {
+                for lane in road.lanes {
+                    for i in 0..road.spot_count() {
+                        let distance = i as f32 * CAR_SPACING;
+                        let taken = road_cars.iter().any(|(_, (car, _))| {
+                            let gap = (car.distance_traveled - distance).abs();
+                            car.lane == lane && gap.min(road_len - gap) < MIN_CAR_GAP
+                        });
+                        if !taken && rng.random::<f32>() < fill_probability {
+                            spawn_car(
+                                commands, &assets, &config, &mut pool, &mut rng, road, lane,
+                                distance,
+                            );
+                        }
+                    }
+                }
+            });
+        }
+    }
+    commands.trigger(CityRegenerated);
+}
+
+/// Spawns the roads of a flooded block as bridges
+///
+/// The bridges use the same layout as the roads from `spawn_roads_and_cars` so they connect to
+/// the roads of the neighboring blocks. There's no [`Road`] under them so no cars drive on them.
+fn spawn_bridges(commands: &mut ChildSpawnerCommands, assets: &CityAssets, offset: Vec3) {
+    spawn_scene(
+        commands,
+        &assets.road_bridge,
+        Transform::from_translation(Vec3::new(2.75, 0.0, 0.0) + offset)
+            .with_scale(Vec3::new(4.5, 1.0, 1.0)),
+        RoadMarker,
+    );
+    spawn_scene(
+        commands,
+        &assets.road_bridge,
+        Transform::from_translation(Vec3::new(0.0, 0.0, 2.0) + offset)
+            .with_scale(Vec3::new(3.0, 1.0, 1.0))
+            .with_rotation(Quat::from_axis_angle(Vec3::Y, std::f32::consts::FRAC_PI_2)),
+        RoadMarker,
+    );
+}
+
+/// Picks a random speed for a car
+///
+/// Heavy vehicles like trucks and vans can be capped to a lower max speed than the other cars.
+fn car_speed<R: RngExt>(rng: &mut R, config: &CityConfig, car: &CarAsset) -> f32 {
+    let min = *config.car_speed_range.start();
+    let mut max = *config.car_speed_range.end();
+    if car.heavy
+        && let Some(heavy_max_speed) = config.heavy_vehicle_max_speed
+    {
+        max = max.min(heavy_max_speed).max(min);
+    }
+    rng.random_range(min..=max)
+}
+
+fn spawn_low_density<R: RngExt>(
     commands: &mut ChildSpawnerCommands,
     assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
     rng: &mut R,
     offset: Vec3,
 ) {
-    for x in 0..3 {
-        let x = x as f32;
-        commands.spawn((
-            assets.high_density.get_random_building(rng),
-            Transform::from_translation(Vec3::new(1.25 + x * 1.5, 0.0, 1.25) + offset),
-        ));
-        commands.spawn((
-            assets.high_density.get_random_building(rng),
-            Transform::from_translation(Vec3::new(1.25 + x * 1.5, 0.0, 2.75) + offset)
-                .with_rotation(Quat::from_axis_angle(Vec3::Y, std::f32::consts::PI)),
-        ));
+    let placements = low_density_transforms(offset, rng, config);
+    spawn_placements(commands, assets, config, pool, rng, placements);
+    spawn_parked_cars(commands, assets, config, pool, rng, offset);
+}
+
+fn spawn_medium_density<R: RngExt>(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    rng: &mut R,
+    offset: Vec3,
+    roads: &[(Entity, Road)],
+) {
+    let placements =
+        medium_density_transforms(offset, rng, config, assets.medium_density.footprints());
+    spawn_placements(commands, assets, config, pool, rng, placements);
+    spawn_parked_cars(commands, assets, config, pool, rng, offset);
+    spawn_pedestrians(
+        commands,
+        assets,
+        rng,
+        offset,
+        WalkPath {
+            start: Vec3::new(0.75, 0.06, 2.0),
+            end: Vec3::new(4.75, 0.06, 2.0),
+        },
+    );
+    if rng.random_bool(BUS_ROUTE_CHANCE) {
+        spawn_bus_route(commands, assets, rng, roads);
     }
 }
 
-fn spawn_forest<R: RngExt>(
+/// Spawns the warehouses of an industrial block and fills its parking lot
+fn spawn_industrial<R: RngExt>(
     commands: &mut ChildSpawnerCommands,
     assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
     rng: &mut R,
     offset: Vec3,
 ) {
-    for x in 0..=12 {
-        for z in 0..=8 {
-            let transform = Transform::from_translation(
-                Vec3::new(x as f32, 0.0, z as f32) * Vec3::new(0.325, 0.0, 0.3)
-                    + Vec3::new(0.75, 0.0, 0.85)
-                    + offset,
+    let placements = industrial_transforms(offset, rng, config, assets.industrial.footprints());
+    spawn_placements(commands, assets, config, pool, rng, placements);
+    // The cars are parked facing the warehouses
+    for i in 0..PARKING_LOT_SPOTS {
+        if rng.random::<f32>() < config.parked_car_density {
+            let mut car = pool.spawn_scene(
+                commands,
+                &assets.get_random_car(rng).scene,
+                Transform::from_translation(Vec3::new(0.9 + i as f32 * 0.3, 0.0, 3.0) + offset)
+                    .with_scale(Vec3::splat(0.15))
+                    .with_rotation(Quat::from_axis_angle(Vec3::Y, PI)),
             );
+            car.insert((CarMarker, ParkedCarMarker, SpawnInfo("Parked car")));
+            if let Some(paint) = random_car_paint(config, rng) {
+                car.insert(paint);
+            }
+        }
+    }
+}
 
-            match rng.random_range(0..3) {
-                0 => {}
-                1 => {
-                    commands.spawn((SceneRoot(assets.tree_small.clone()), transform));
-                }
-                2 => {
-                    commands.spawn((SceneRoot(assets.tree_large.clone()), transform));
+#[allow(clippy::too_many_arguments)]
+fn spawn_high_density<R: RngExt>(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    rng: &mut R,
+    roundabouts: &[IVec2],
+    offset: Vec3,
+) {
+    let placements = high_density_transforms(offset, rng, config, roundabouts);
+    spawn_placements(commands, assets, config, pool, rng, placements);
+}
+
+fn spawn_forest<R: RngExt>(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    rng: &mut R,
+    offset: Vec3,
+) {
+    let placements = forest_transforms(offset, rng);
+    spawn_placements(commands, assets, config, pool, rng, placements);
+}
+
+fn spawn_park<R: RngExt>(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    rng: &mut R,
+    offset: Vec3,
+) {
+    let placements = park_transforms(offset, rng);
+    spawn_placements(commands, assets, config, pool, rng, placements);
+    spawn_pedestrians(
+        commands,
+        assets,
+        rng,
+        offset,
+        WalkPath {
+            start: Vec3::new(0.75, 0.06, 2.0),
+            end: Vec3::new(4.75, 0.06, 2.0),
+        },
+    );
+}
+
+/// Spawns the entities computed by one of the placement functions
+///
+/// The buildings are picked randomly from the buildings of their density.
+fn spawn_placements<R: RngExt>(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    rng: &mut R,
+    placements: Vec<(PlacementKind, Transform)>,
+) {
+    for (kind, transform) in placements {
+        match kind {
+            PlacementKind::LowDensityBuilding => {
+                let building = assets
+                    .low_density
+                    .get_random_building(rng, config.tint_buildings);
+                spawn_building(
+                    commands,
+                    building,
+                    DensityTier::Low,
+                    SpawnInfo("Low density building"),
+                    config,
+                    pool,
+                    transform,
+                );
+            }
+            PlacementKind::MediumDensityBuilding(index) => {
+                let building =
+                    assets
+                        .medium_density
+                        .get_building(index, rng, config.tint_buildings);
+                spawn_building(
+                    commands,
+                    building,
+                    DensityTier::Medium,
+                    SpawnInfo("Medium density building"),
+                    config,
+                    pool,
+                    transform,
+                );
+            }
+            PlacementKind::IndustrialBuilding(index) => {
+                let building = assets
+                    .industrial
+                    .get_building(index, rng, config.tint_buildings);
+                let building = spawn_building(
+                    commands,
+                    building,
+                    DensityTier::Medium,
+                    SpawnInfo("Industrial building"),
+                    config,
+                    pool,
+                    transform,
+                );
+                commands
+                    .commands_mut()
+                    .entity(building)
+                    .insert(IndustrialMarker);
+            }
+            PlacementKind::Skyscraper => {
+                let building = assets
+                    .high_density
+                    .get_random_building(rng, config.tint_buildings);
+                spawn_building(
+                    commands,
+                    building,
+                    DensityTier::High,
+                    SpawnInfo("Skyscraper"),
+                    config,
+                    pool,
+                    transform,
+                );
+            }
+            PlacementKind::Smokestack => {
+                let (mesh, material) = &assets.smokestack;
+                commands.spawn((
+                    Mesh3d(mesh.clone()),
+                    MeshMaterial3d(material.clone()),
+                    transform,
+                ));
+            }
+            PlacementKind::SmallTree => {
+                spawn_scene(commands, &assets.tree_small, transform, TreeMarker);
+            }
+            PlacementKind::LargeTree => {
+                spawn_scene(commands, &assets.tree_large, transform, TreeMarker);
+            }
+            PlacementKind::Fence => {
+                spawn_scene(commands, &assets.fence, transform, FenceMarker);
+            }
+            PlacementKind::PathStones => {
+                spawn_scene(commands, &assets.path_stones_long, transform, ());
+            }
+            PlacementKind::Bench => {
+                spawn_scene(commands, &assets.bench, transform, ());
+            }
+        }
+    }
+}
+
+/// Spawns a scene as a child of the spawner along with a marker, or `()` for the scenes without
+/// one
+///
+/// The markers are what the visibility toggles and the stats look for, keeping them next to the
+/// scene makes them hard to forget.
+pub fn spawn_scene<'a>(
+    commands: &'a mut ChildSpawnerCommands,
+    scene: &Handle<Scene>,
+    transform: Transform,
+    marker: impl Bundle,
+) -> EntityCommands<'a> {
+    commands.spawn((SceneRoot(scene.clone()), transform, marker))
+}
+
+/// Spawns a building along with the low poly box displayed instead of it when it's far from the
+/// camera
+///
+/// The box isn't pooled, it's despawned along with its block. Returns the building.
+fn spawn_building(
+    commands: &mut ChildSpawnerCommands,
+    (building, (lod_mesh, lod_material), material, model): BuildingWithLod,
+    tier: DensityTier,
+    info: SpawnInfo,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    transform: Transform,
+) -> Entity {
+    let building = pool
+        .spawn_mesh(commands, building, transform)
+        .insert((
+            BuildingMarker { density: tier },
+            model,
+            info,
+            Pickable::default(),
+            BuildingWindows::new(material, &transform),
+            VisibilityRange::abrupt(0.0, config.lod_distance),
+        ))
+        .id();
+    commands.spawn((
+        lod_mesh,
+        lod_material,
+        transform,
+        BuildingLod,
+        VisibilityRange::abrupt(config.lod_distance, f32::MAX),
+    ));
+    building
+}
+
+/// Spawns cars parked along the curb of the roads on both sides of the block
+///
+/// The cars are parked on the edge of the road, outside of the lanes used by the moving cars.
+/// Parked cars don't have a `Car` component so they are ignored by the car simulation.
+fn spawn_parked_cars<R: RngExt>(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    config: &CityConfig,
+    pool: &mut EntityPool,
+    rng: &mut R,
+    offset: Vec3,
+) {
+    for curb_z in [0.38, 3.62] {
+        for i in 0..9 {
+            if rng.random::<f32>() < config.parked_car_density {
+                let mut car = pool.spawn_scene(
+                    commands,
+                    &assets.get_random_car(rng).scene,
+                    Transform::from_translation(
+                        Vec3::new(1.0 + i as f32 * 0.4, 0.0, curb_z) + offset,
+                    )
+                    .with_scale(Vec3::splat(0.15))
+                    .with_rotation(Quat::from_axis_angle(Vec3::Y, std::f32::consts::FRAC_PI_2)),
+                );
+                car.insert((CarMarker, ParkedCarMarker, SpawnInfo("Parked car")));
+                if let Some(paint) = random_car_paint(config, rng) {
+                    car.insert(paint);
                 }
-                _ => {}
             }
         }
     }
 }
+
+/// Spawns a few pedestrians walking back and forth along the given path
+fn spawn_pedestrians<R: RngExt>(
+    commands: &mut ChildSpawnerCommands,
+    assets: &CityAssets,
+    rng: &mut R,
+    offset: Vec3,
+    path: WalkPath,
+) {
+    let start = path.start;
+    let path_len = (path.end - path.start).length();
+    let pedestrian_count = rng.random_range(0..=3);
+    commands
+        .spawn((
+            Transform::from_translation(offset),
+            Visibility::default(),
+            path,
+        ))
+        .with_children(|commands| {
+            for _ in 0..pedestrian_count {
+                commands.spawn((
+                    assets.get_random_pedestrian(rng),
+                    Transform::from_translation(start),
+                    Pedestrian {
+                        distance_traveled: rng.random_range(0.0..path_len),
+                        dir: if rng.random_bool(0.5) { 1.0 } else { -1.0 },
+                    },
+                ));
+            }
+        });
+}
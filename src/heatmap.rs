@@ -0,0 +1,99 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    image::ImageSampler,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use crate::{
+    density::DensityField,
+    generate_city::{CityConfig, city_bounds, grid_blocks},
+    settings::Settings,
+};
+
+/// Height of the heatmap, above the roads and the ground tiles but below most of the cars
+const HEATMAP_HEIGHT: f32 = 0.05;
+const HEATMAP_ALPHA: f32 = 0.6;
+
+/// A quad covering the city colored by the density of each block
+#[derive(Component)]
+pub struct DensityHeatmap;
+
+/// Color of a density, from blue for the emptiest blocks to red for the densest ones
+fn density_color(density: f64) -> Color {
+    let hue = (1.0 - density.clamp(0.0, 1.0) as f32) * 240.0;
+    Color::hsla(hue, 0.9, 0.5, HEATMAP_ALPHA)
+}
+
+/// Spawns or despawns the heatmap when it's toggled in the settings, and rebuilds it when the
+/// config changes so it follows the noise sliders
+///
+/// Each pixel of the texture of the heatmap is the density of a block, sampled with the same
+/// [`DensityField`] as the generation.
+#[allow(clippy::too_many_arguments)]
+pub fn update_density_heatmap(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    config: Res<CityConfig>,
+    mut shown: Local<bool>,
+    heatmaps: Query<Entity, With<DensityHeatmap>>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if *shown == settings.show_density_heatmap && !(*shown && config.is_changed()) {
+        return;
+    }
+    *shown = settings.show_density_heatmap;
+    for heatmap in &heatmaps {
+        commands.entity(heatmap).despawn();
+    }
+    if !settings.show_density_heatmap {
+        return;
+    }
+
+    let density = DensityField::new(config.seed, config.rng_kind, &config.noise);
+    let blocks = grid_blocks(&config);
+    let size = blocks.len() as u32;
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    // Each block is a single sharp pixel
+    image.sampler = ImageSampler::nearest();
+    for x in 0..size {
+        for z in 0..size {
+            let block = IVec2::new(x as i32 + blocks.start, z as i32 + blocks.start);
+            let color = density_color(density.block_density(block));
+            if let Err(err) = image.set_color_at(x, z, color) {
+                warn!("Failed to draw the density heatmap: {err}");
+                return;
+            }
+        }
+    }
+
+    // The u coordinate of the plane goes along x and v along z, like the pixels of the image
+    let (min, max) = city_bounds(&config);
+    let extent = (max - min).xz().max(Vec2::ONE);
+    commands.spawn((
+        Mesh3d(meshes.add(Plane3d::new(Vec3::Y, extent / 2.0))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color_texture: Some(images.add(image)),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        })),
+        // Above the highest hills so the blocks don't hide it
+        Transform::from_translation(
+            (min + max) / 2.0 + Vec3::Y * (HEATMAP_HEIGHT + config.terrain_amplitude),
+        ),
+        DensityHeatmap,
+    ));
+}
@@ -0,0 +1,277 @@
+use bevy::{
+    camera_controller::free_camera::FreeCamera, picking::mesh_picking::MeshPickingSettings,
+    prelude::*, scene::SceneInstanceReady, window::PrimaryWindow,
+};
+
+use crate::{
+    assets::CityAssets,
+    density::DensityField,
+    generate_city::{
+        CityBlock, CityConfig, DensityTier, SpawnInfo, block_at, reroll_block, sync_car_density,
+    },
+    pool::EntityPool,
+    settings::{PointerOverSettings, Settings},
+};
+
+/// Shows what a building or car is when clicking on it, ctrl+click regenerates the clicked block
+/// and the block under the cursor is shown at the bottom of the screen
+///
+/// Only the entities with a [`SpawnInfo`] and the ground of the blocks can be picked, the rest of
+/// the city is skipped by the ray casts so hovering the city stays cheap.
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<MeshPickingPlugin>() {
+            app.add_plugins(MeshPickingPlugin);
+        }
+        app.insert_resource(MeshPickingSettings {
+            require_markers: true,
+            ..default()
+        })
+        .add_systems(Startup, (spawn_inspector_ui, spawn_hovered_block_ui))
+        .add_systems(Update, update_hovered_block_ui)
+        .add_observer(make_scene_pickable)
+        .add_observer(inspect_entity)
+        .add_observer(reroll_clicked_block);
+    }
+}
+
+/// Holding either of these while clicking regenerates the block instead of inspecting
+const CONTROL_KEYS: [KeyCode; 2] = [KeyCode::ControlLeft, KeyCode::ControlRight];
+
+#[derive(Component)]
+struct InspectorText;
+
+fn spawn_inspector_ui(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(10.0),
+                right: Val::Px(10.0),
+                padding: UiRect::all(Val::Px(10.0)),
+                display: Display::None,
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.75)),
+        ))
+        .with_child((
+            Text::new(""),
+            TextFont {
+                font_size: FontSize::Px(20.0),
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            InspectorText,
+        ));
+}
+
+#[derive(Component)]
+struct HoveredBlockText;
+
+fn spawn_hovered_block_ui(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(10.0),
+                width: percent(100),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            Pickable::IGNORE,
+        ))
+        .with_child((
+            Text::new(""),
+            TextFont {
+                font_size: FontSize::Px(18.0),
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            TextShadow::default(),
+            HoveredBlockText,
+        ));
+}
+
+/// Shows the coordinate, tier and density of the block under the cursor, without clicking
+///
+/// The ray of the cursor is cast on the ground plane instead of the meshes of the city so this
+/// works between the buildings and stays cheap. The density is sampled like the generation does
+/// and is shown with the tier it classifies as, which differs from the tier of the block for the
+/// rivers and the loaded layouts.
+fn update_hovered_block_ui(
+    config: Res<CityConfig>,
+    pointer_over_settings: Res<PointerOverSettings>,
+    mut density: Local<Option<DensityField>>,
+    window: Option<Single<&Window, With<PrimaryWindow>>>,
+    camera: Option<Single<(&Camera, &GlobalTransform), With<FreeCamera>>>,
+    blocks: Query<&CityBlock>,
+    mut text: Single<&mut Text, With<HoveredBlockText>>,
+) {
+    if config.is_changed() || density.is_none() {
+        *density = Some(DensityField::new(
+            config.seed,
+            config.rng_kind,
+            &config.noise,
+        ));
+    }
+    let hovered = window
+        .zip(camera)
+        .filter(|_| !pointer_over_settings.0)
+        .and_then(|(window, camera)| cursor_on_ground(&window, camera.0, camera.1))
+        .map(block_at)
+        .and_then(|coord| blocks.iter().find(|block| block.coord == coord));
+    let label = match (hovered, density.as_ref()) {
+        (Some(block), Some(density)) => {
+            let sampled = density.block_density(block.coord);
+            format!(
+                "Block ({}, {}): {:?}, density {sampled:.3} classified as {:?}",
+                block.coord.x,
+                block.coord.y,
+                block.tier,
+                DensityTier::from_density(sampled, &config),
+            )
+        }
+        _ => String::new(),
+    };
+    if text.0 != label {
+        text.0 = label;
+    }
+}
+
+/// Where the ray of the cursor hits the ground plane
+fn cursor_on_ground(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec3> {
+    let ray = camera
+        .viewport_to_world(camera_transform, window.cursor_position()?)
+        .ok()?;
+    let distance = ray.intersect_plane(Vec3::ZERO, InfinitePlane3d::new(Vec3::Y))?;
+    Some(ray.get_point(distance))
+}
+
+/// The meshes of a scene are its descendants, they need to be marked to be picked
+fn make_scene_pickable(
+    ready: On<SceneInstanceReady>,
+    mut commands: Commands,
+    infos: Query<(), With<SpawnInfo>>,
+    children: Query<&Children>,
+    meshes: Query<(), With<Mesh3d>>,
+) {
+    if !infos.contains(ready.entity) {
+        return;
+    }
+    for entity in children.iter_descendants(ready.entity) {
+        if meshes.contains(entity) {
+            commands.entity(entity).insert(Pickable::default());
+        }
+    }
+}
+
+/// Shows the [`SpawnInfo`], block and transform of the clicked entity
+///
+/// The click bubbles up from the picked mesh to the entity with the [`SpawnInfo`], like the
+/// root of the scene of a car.
+#[allow(clippy::too_many_arguments)]
+fn inspect_entity(
+    mut click: On<Pointer<Click>>,
+    pointer_over_settings: Res<PointerOverSettings>,
+    keys: Res<ButtonInput<KeyCode>>,
+    infos: Query<(&SpawnInfo, &GlobalTransform)>,
+    parents: Query<&ChildOf>,
+    blocks: Query<&CityBlock>,
+    text: Single<(&mut Text, &ChildOf), With<InspectorText>>,
+    mut nodes: Query<&mut Node>,
+) {
+    if pointer_over_settings.0
+        || click.button != PointerButton::Primary
+        || keys.any_pressed(CONTROL_KEYS)
+    {
+        return;
+    }
+    let Ok((info, transform)) = infos.get(click.entity) else {
+        return;
+    };
+    click.propagate(false);
+
+    let block = parents
+        .iter_ancestors(click.entity)
+        .find_map(|ancestor| blocks.get(ancestor).ok());
+    let block = match block {
+        Some(block) => format!(
+            "{} ({}, {})",
+            block.district.label(),
+            block.coord.x,
+            block.coord.y
+        ),
+        None => "None".to_owned(),
+    };
+    let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+    let (yaw, _, _) = rotation.to_euler(EulerRot::YXZ);
+    let description = format!(
+        "{}\nEntity: {}\nBlock: {block}\nTranslation: {:.2}, {:.2}, {:.2}\nYaw: {:.0}°\nScale: {:.2}",
+        info.0,
+        click.entity,
+        translation.x,
+        translation.y,
+        translation.z,
+        yaw.to_degrees(),
+        scale.y,
+    );
+    info!("Inspected {}", description.replace('\n', ", "));
+
+    let (mut text, panel) = text.into_inner();
+    text.0 = description;
+    if let Ok(mut node) = nodes.get_mut(panel.parent()) {
+        node.display = Display::Flex;
+    }
+}
+
+/// Regenerates the block under the pointer with a new seed when ctrl+clicking on it
+#[allow(clippy::too_many_arguments)]
+fn reroll_clicked_block(
+    mut click: On<Pointer<Click>>,
+    mut commands: Commands,
+    pointer_over_settings: Res<PointerOverSettings>,
+    keys: Res<ButtonInput<KeyCode>>,
+    assets: Res<CityAssets>,
+    settings: Res<Settings>,
+    mut config: ResMut<CityConfig>,
+    mut pool: ResMut<EntityPool>,
+    parents: Query<&ChildOf>,
+    mut blocks: Query<&mut CityBlock>,
+) {
+    if pointer_over_settings.0
+        || click.button != PointerButton::Primary
+        || !keys.any_pressed(CONTROL_KEYS)
+    {
+        return;
+    }
+    let Some(entity) = parents
+        .iter_ancestors(click.entity)
+        .find(|ancestor| blocks.contains(*ancestor))
+    else {
+        return;
+    };
+    // The click would reach the block again while bubbling up
+    click.propagate(false);
+    let Ok(mut block) = blocks.get_mut(entity) else {
+        return;
+    };
+    sync_car_density(&mut config, &settings);
+    reroll_block(
+        &mut commands,
+        &assets,
+        &config,
+        &mut pool,
+        entity,
+        &mut block,
+    );
+    info!(
+        "Regenerated the block ({}, {}), it was rerolled {} times",
+        block.coord.x, block.coord.y, block.reroll
+    );
+}
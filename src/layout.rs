@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    density::DensityField,
+    district::District,
+    generate_city::{CityBlock, CityConfig, DensityTier, grid_blocks},
+    rng::RngKind,
+};
+
+/// Directory the saved layouts are written to
+const LAYOUT_DIR: &str = "layouts";
+
+/// The blocks of a city and what they are filled with
+///
+/// A saved layout pins the tier, the district and the rerolls of every block, so loading it
+/// gives the same blocks even if the noise or the density thresholds change.
+///
+/// The content of the blocks isn't saved, the buildings, trees and cars are spawned again from
+/// the rng of each block, which only depends on the seed and the block coordinate. A layout only
+/// reproduces the same content with the version of the generator it was saved with: any change
+/// to the assets, the placement or the order the blocks draw from their rng moves or replaces
+/// the content of the blocks.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+pub struct CityLayout {
+    pub seed: u64,
+    /// The layouts saved before the rng could be picked were all generated with the default one
+    #[serde(default)]
+    pub rng_kind: RngKind,
+    pub blocks: Vec<BlockLayout>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct BlockLayout {
+    pub coord: [i32; 2],
+    pub tier: DensityTier,
+    /// `None` in the layouts saved before the districts, the district is then picked from the
+    /// tier alone
+    #[serde(default)]
+    pub district: Option<District>,
+    /// See [`CityBlock::reroll`]
+    #[serde(default)]
+    pub reroll: u32,
+}
+
+impl BlockLayout {
+    pub fn district(&self) -> District {
+        self.district
+            .unwrap_or_else(|| District::new(self.tier, 0.0))
+    }
+}
+
+impl CityLayout {
+    /// Samples the density noise to pick the tier of every block of the grid
+    ///
+    /// When rivers are enabled, the blocks where the water channel is above the water level are
+    /// flooded no matter their density.
+    pub fn generate(config: &CityConfig) -> Self {
+        let density = DensityField::new(config.seed, config.rng_kind, &config.noise);
+        let mut blocks = Vec::new();
+        for x in grid_blocks(config) {
+            for z in grid_blocks(config) {
+                let block = IVec2::new(x, z);
+                let tier = if config.rivers_enabled
+                    && density.block_water(block) > config.water_level as f64
+                {
+                    DensityTier::Water
+                } else {
+                    DensityTier::from_density(density.block_density(block), config)
+                };
+                blocks.push(BlockLayout {
+                    coord: block.to_array(),
+                    tier,
+                    district: Some(District::new(tier, density.block_district(block))),
+                    reroll: 0,
+                });
+            }
+        }
+        Self {
+            seed: config.seed,
+            rng_kind: config.rng_kind,
+            blocks,
+        }
+    }
+
+    /// Rebuilds the layout of the city currently spawned
+    pub fn from_blocks<'a>(
+        seed: u64,
+        rng_kind: RngKind,
+        blocks: impl Iterator<Item = &'a CityBlock>,
+    ) -> Self {
+        Self {
+            seed,
+            rng_kind,
+            blocks: blocks
+                .map(|block| BlockLayout {
+                    coord: block.coord.to_array(),
+                    tier: block.tier,
+                    district: Some(block.district),
+                    reroll: block.reroll,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Writes the layout to `layouts/city_<seed>.ron`
+pub fn save_layout(layout: &CityLayout) -> std::io::Result<PathBuf> {
+    let contents = ron::ser::to_string_pretty(layout, ron::ser::PrettyConfig::default())
+        .map_err(std::io::Error::other)?;
+    std::fs::create_dir_all(LAYOUT_DIR)?;
+    let path = Path::new(LAYOUT_DIR).join(format!("city_{}.ron", layout.seed));
+    std::fs::write(&path, contents)?;
+    Ok(path)
+}
+
+pub fn load_layout(path: &Path) -> std::io::Result<CityLayout> {
+    let contents = std::fs::read_to_string(path)?;
+    ron::from_str(&contents).map_err(std::io::Error::other)
+}
@@ -0,0 +1,41 @@
+pub mod adaptive_detail;
+pub mod assets;
+pub mod atmosphere;
+pub mod audio;
+pub mod benchmark;
+pub mod bookmarks;
+pub mod bus;
+pub mod camera;
+pub mod car_paint;
+pub mod city;
+pub mod city_banner;
+pub mod city_names;
+pub mod compass;
+pub mod config_file;
+pub mod config_schema;
+pub mod debug_draw;
+pub mod density;
+pub mod digest;
+pub mod district;
+pub mod emergency;
+pub mod export;
+pub mod generate_city;
+pub mod heatmap;
+pub mod inspector;
+pub mod layout;
+pub mod loading;
+pub mod manifest;
+pub mod offscreen_render;
+pub mod placement;
+pub mod pool;
+pub mod profiles;
+pub mod rng;
+pub mod season;
+pub mod seed_browser;
+pub mod settings;
+pub mod sim_time;
+pub mod stats;
+pub mod time_of_day;
+pub mod traffic;
+pub mod weather;
+pub mod wind;
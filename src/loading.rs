@@ -0,0 +1,119 @@
+use bevy::{
+    feathers::{
+        self,
+        theme::{ThemeBackgroundColor, ThemedText},
+    },
+    prelude::*,
+};
+
+use crate::{
+    assets::{CityAssets, PlaceholderAssets},
+    city::GenerationProgress,
+};
+
+/// Time in seconds after which the city is generated even if some assets are still loading
+const LOAD_TIMEOUT: f32 = 15.0;
+
+/// Triggered once the assets of the city are loaded, or after [`LOAD_TIMEOUT`]
+#[derive(Event)]
+pub struct AssetsLoaded;
+
+/// The root of the loading screen, it's despawned once the city is generated
+#[derive(Component)]
+pub struct LoadingScreen;
+
+#[derive(Component)]
+pub struct LoadingText;
+
+#[derive(Component)]
+pub struct LoadingBar;
+
+pub fn spawn_loading_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: percent(100),
+                height: percent(100),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            LoadingScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        row_gap: px(8),
+                        padding: UiRect::all(Val::Px(16.0)),
+                        ..default()
+                    },
+                    ThemeBackgroundColor(feathers::tokens::WINDOW_BG),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((Text::new("Loading assets…"), ThemedText, LoadingText));
+                    parent
+                        .spawn((
+                            Node {
+                                width: px(300),
+                                height: px(8),
+                                ..default()
+                            },
+                            ThemeBackgroundColor(feathers::tokens::SLIDER_BG),
+                        ))
+                        .with_child((
+                            Node {
+                                width: percent(0),
+                                height: percent(100),
+                                ..default()
+                            },
+                            ThemeBackgroundColor(feathers::tokens::SLIDER_BAR),
+                            LoadingBar,
+                        ));
+                });
+        });
+}
+
+/// Shows how many assets are loaded and starts the generation of the city once they all are,
+/// then shows how many blocks are spawned until the city is generated
+#[allow(clippy::too_many_arguments)]
+pub fn update_loading_screen(
+    mut commands: Commands,
+    screen: Single<Entity, With<LoadingScreen>>,
+    mut text: Single<&mut Text, With<LoadingText>>,
+    mut bar: Single<&mut Node, With<LoadingBar>>,
+    assets: Res<CityAssets>,
+    asset_server: Res<AssetServer>,
+    placeholders: Res<PlaceholderAssets>,
+    progress: Res<GenerationProgress>,
+    time: Res<Time>,
+    mut assets_loaded: Local<bool>,
+) {
+    if *assets_loaded {
+        let (spawned, total) = (progress.spawned_blocks, progress.total_blocks);
+        text.0 = format!("Generating the city… {spawned}/{total}");
+        bar.width = percent(100.0 * spawned as f32 / total.max(1) as f32);
+        if !progress.generating {
+            commands.entity(*screen).despawn();
+        }
+        return;
+    }
+
+    let (loaded, total) = assets.load_progress(&asset_server, &placeholders);
+    text.0 = format!("Loading assets… {loaded}/{total}");
+    bar.width = percent(100.0 * loaded as f32 / total.max(1) as f32);
+
+    if loaded < total {
+        if time.elapsed_secs() < LOAD_TIMEOUT {
+            return;
+        }
+        warn!(
+            "Only {loaded}/{total} assets loaded after {LOAD_TIMEOUT}s, generating the city anyway"
+        );
+    }
+    *assets_loaded = true;
+    commands.trigger(AssetsLoaded);
+}
@@ -1,4 +1,7 @@
-use core::f64;
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use argh::FromArgs;
 use bevy::{
@@ -9,131 +12,324 @@ use bevy::{
     dev_tools::fps_overlay::{FpsOverlayConfig, FpsOverlayPlugin, FrameTimeGraphConfig},
     diagnostic::FrameCount,
     feathers::{FeathersPlugins, dark_theme::create_dark_theme, theme::UiTheme},
-    light::{
-        Atmosphere, AtmosphereEnvironmentMapLight, VolumetricFog, VolumetricLight,
-        atmosphere::ScatteringMedium,
-    },
+    light::atmosphere::ScatteringMedium,
     pbr::{
-        AtmosphereSettings, ContactShadows,
+        ContactShadows, ScreenSpaceReflections,
         wireframe::{WireframeConfig, WireframePlugin},
     },
     post_process::bloom::Bloom,
     prelude::*,
     render::{
         RenderPlugin,
+        diagnostic::RenderDiagnosticsPlugin,
         settings::{WgpuFeatures, WgpuSettings},
+        view::screenshot::{Screenshot, ScreenshotCaptured, save_to_disk},
     },
     window::{PresentMode, WindowResolution},
     winit::WinitSettings,
 };
 
-use noise::{NoiseFn, OpenSimplex};
-use rand::{RngExt, SeedableRng, rngs::SmallRng};
-
-use crate::{
-    assets::{CityAssets, load_assets},
-    generate_city::spawn_city,
-    settings::{Settings, setup_settings_ui},
+use bevy_city::{
+    adaptive_detail::AdaptiveDetailPlugin,
+    atmosphere::apply_atmosphere,
+    audio::TrafficAudioPlugin,
+    benchmark::BenchmarkPlugin,
+    bookmarks::{CameraBookmarks, handle_bookmark_keys, update_bookmarks_text},
+    camera::{
+        CameraMode, OrbitFocus, adjust_free_camera_speed, clamp_camera, fly_camera_tour,
+        frame_generated_city, orbit_camera, reframe_camera, update_camera_speed_text,
+        update_free_camera_state,
+    },
+    city::CityPlugin,
+    city_banner::CityBannerPlugin,
+    compass::CompassPlugin,
+    config_file::load_config_file,
+    config_schema::ConfigSchema,
+    density::dump_density,
+    generate_city::CityConfig,
+    inspector::InspectorPlugin,
+    layout::load_layout,
+    offscreen_render::{OffscreenRenderPlugin, RenderSize},
+    rng::RngKind,
+    seed_browser::SeedBrowserPlugin,
+    settings::{Settings, SettingsUiPlugin, cascade_shadow_config},
+    time_of_day::TimeOfDayPlugin,
+    weather::WeatherPlugin,
 };
 
-mod assets;
-mod generate_city;
-mod settings;
-
-#[derive(Resource, Default)]
-struct SceneStats {
-    cars_spawned: u32,
-    low_density_buildings: u32,
-    medium_density_buildings: u32,
-    skyscrapers: u32,
-    road_segments: u32,
-    trees: u32,
-}
-
-#[derive(Component)]
-struct StatsText;
-
-#[derive(FromArgs, Resource, Clone)]
+#[derive(FromArgs)]
 /// Config
 pub struct Args {
-    /// seed
-    #[argh(option, default = "42")]
-    seed: u64,
+    /// seed, 42 by default
+    #[argh(option)]
+    seed: Option<u64>,
+
+    /// rng used to generate the city, `small` or `chacha`, only `chacha` gives the same city
+    /// for a seed across versions of rand, `small` by default
+    #[argh(option)]
+    rng: Option<RngKind>,
+
+    /// size, 30 by default
+    #[argh(option)]
+    size: Option<u32>,
+
+    /// log how long it takes to spawn the city
+    #[argh(switch)]
+    benchmark: bool,
+
+    /// exit once the benchmark is done
+    #[argh(switch)]
+    benchmark_exit: bool,
+
+    /// flood some of the blocks with rivers
+    #[argh(switch)]
+    rivers: bool,
+
+    /// run the cars and pedestrians on a fixed timestep so they always move the same way
+    #[argh(switch)]
+    deterministic_traffic: bool,
+
+    /// give the buildings a few different tints
+    #[argh(switch)]
+    tint_buildings: bool,
+
+    /// paint the cars with a few different colors
+    #[argh(switch)]
+    randomize_car_color: bool,
 
-    /// size
-    #[argh(option, default = "30")]
-    size: u32,
+    /// probability of each straight road being a one-way street, 0 by default
+    #[argh(option)]
+    one_way_probability: Option<f32>,
+
+    /// let the cars stuck behind slower ones change lanes on the one-way streets
+    #[argh(switch)]
+    lane_changing: bool,
+
+    /// load the parameters of the city, the settings and a profile from this RON or JSON file,
+    /// the other options shaping the city are applied over it
+    #[argh(option)]
+    config: Option<PathBuf>,
+
+    /// spawn a layout saved with the "Save Layout" button instead of generating a new one, the
+    /// blocks keep their tier but their content is only the same with the version that saved it
+    #[argh(option)]
+    layout: Option<PathBuf>,
+
+    /// write an image of the density of the city to this path before generating it
+    #[argh(option)]
+    dump_density: Option<PathBuf>,
+
+    /// exit once the density image is written instead of generating the city
+    #[argh(switch)]
+    dump_density_exit: bool,
+
+    /// print the parameters of the city and the settings with their defaults, current values
+    /// and ranges as JSON, and exit
+    #[argh(switch)]
+    dump_config_schema: bool,
+
+    /// render the city to an image of this size instead of the window, like `3840x2160`, save it
+    /// to `--out` and exit
+    #[argh(option)]
+    render: Option<RenderSize>,
+
+    /// where the image rendered with `--render` is saved
+    #[argh(option, default = "PathBuf::from(\"render.png\")")]
+    out: PathBuf,
+}
+
+/// Applies the options shaping the city over the config, the options left out keep the value of
+/// the config
+fn apply_args(args: &Args, config: &mut CityConfig) {
+    if let Some(seed) = args.seed {
+        config.seed = seed;
+    }
+    if let Some(rng) = args.rng {
+        config.rng_kind = rng;
+    }
+    if let Some(size) = args.size {
+        config.size = size;
+    }
+    if let Some(probability) = args.one_way_probability {
+        config.one_way_probability = probability;
+    }
+    config.rivers_enabled |= args.rivers;
+    config.deterministic_traffic |= args.deterministic_traffic;
+    config.tint_buildings |= args.tint_buildings;
+    config.randomize_car_color |= args.randomize_car_color;
+    config.lane_changing |= args.lane_changing;
 }
 
 fn main() {
     let args: Args = argh::from_env();
 
-    App::new()
-        .add_plugins((
-            DefaultPlugins.set(WindowPlugin {
-                primary_window: Some(Window {
-                    title: "bevy_city".into(),
-                    resolution: WindowResolution::new(1920, 1080).with_scale_factor_override(1.0),
-                    present_mode: PresentMode::AutoNoVsync,
-                    visible: false,
-                    ..default()
-                }),
+    let layout = args.layout.as_ref().map(|path| match load_layout(path) {
+        Ok(layout) => layout,
+        Err(err) => {
+            eprintln!("Failed to load the layout {}: {err}", path.display());
+            std::process::exit(1);
+        }
+    });
+
+    let (mut config, settings) = match &args.config {
+        Some(path) => match load_config_file(path) {
+            Ok(file) => {
+                let (config, settings) = file.into_config();
+                (config, Some(settings))
+            }
+            Err(err) => {
+                eprintln!("Failed to load the config {}: {err}", path.display());
+                std::process::exit(1);
+            }
+        },
+        None => (CityConfig::default(), None),
+    };
+    apply_args(&args, &mut config);
+    // The content of the blocks depends on the seed and the rng so they need to match the layout
+    if let Some(layout) = &layout {
+        config.seed = layout.seed;
+        config.rng_kind = layout.rng_kind;
+    }
+
+    if args.dump_config_schema {
+        let settings = settings.unwrap_or_else(Settings::load);
+        match serde_json::to_string_pretty(&ConfigSchema::new(&config, &settings)) {
+            Ok(json) => println!("{json}"),
+            Err(err) => {
+                eprintln!("Failed to serialize the config schema: {err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(path) = &args.dump_density {
+        if let Err(err) = dump_density(&config, path) {
+            eprintln!("Failed to write the density to {}: {err}", path.display());
+            std::process::exit(1);
+        }
+        println!("Density written to {}", path.display());
+        if args.dump_density_exit {
+            return;
+        }
+    }
+
+    let mut app = App::new();
+    app.add_plugins((
+        DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                title: "bevy_city".into(),
+                resolution: WindowResolution::new(1920, 1080).with_scale_factor_override(1.0),
+                present_mode: PresentMode::AutoNoVsync,
+                visible: false,
                 ..default()
             }),
-            // .set(RenderPlugin {
-            //     render_creation: WgpuSettings {
-            //         features: WgpuFeatures::POLYGON_MODE_LINE,
-            //         ..default()
-            //     }
-            //     .into(),
-            //     ..default()
-            // }),
-            FreeCameraPlugin,
-            FeathersPlugins,
-            WireframePlugin::default(),
-            // FpsOverlayPlugin {
-            //     config: FpsOverlayConfig {
-            //         text_config: TextFont {
-            //             font_size: FontSize::Px(32.0),
-            //             ..default()
-            //         },
-            //         // We can also change color of the overlay
-            //         text_color: WHITE.into(),
-            //         refresh_interval: core::time::Duration::from_millis(100),
-            //         enabled: true,
-            //         frame_time_graph_config: FrameTimeGraphConfig {
-            //             enabled: true,
-            //             // The minimum acceptable fps
-            //             min_fps: 30.0,
-            //             // The target fps
-            //             target_fps: 144.0,
-            //         },
-            //     },
-            // },
-        ))
-        .insert_resource(args.clone())
-        .init_resource::<Settings>()
-        .insert_resource(UiTheme(create_dark_theme()))
-        .insert_resource(WinitSettings::continuous())
-        .insert_resource(ClearColor(Color::BLACK))
-        .insert_resource(GlobalAmbientLight::NONE)
-        .insert_resource(WireframeConfig {
-            global: false,
-            default_color: WHITE.into(),
-        })
-        .init_resource::<SceneStats>()
-        .add_systems(
-            Startup,
+            ..default()
+        }),
+        // .set(RenderPlugin {
+        //     render_creation: WgpuSettings {
+        //         features: WgpuFeatures::POLYGON_MODE_LINE,
+        //         ..default()
+        //     }
+        //     .into(),
+        //     ..default()
+        // }),
+        FreeCameraPlugin,
+        FeathersPlugins,
+        WireframePlugin::default(),
+        // Records the pipeline statistics used to show the triangle count in the stats
+        RenderDiagnosticsPlugin,
+        CityPlugin {
+            config,
+            layout,
+            headless: false,
+        },
+        // FpsOverlayPlugin {
+        //     config: FpsOverlayConfig {
+        //         text_config: TextFont {
+        //             font_size: FontSize::Px(32.0),
+        //             ..default()
+        //         },
+        //         // We can also change color of the overlay
+        //         text_color: WHITE.into(),
+        //         refresh_interval: core::time::Duration::from_millis(100),
+        //         enabled: true,
+        //         frame_time_graph_config: FrameTimeGraphConfig {
+        //             enabled: true,
+        //             // The minimum acceptable fps
+        //             min_fps: 30.0,
+        //             // The target fps
+        //             target_fps: 144.0,
+        //         },
+        //     },
+        // },
+    ))
+    .insert_resource(UiTheme(create_dark_theme()))
+    .insert_resource(WinitSettings::continuous())
+    .insert_resource(ClearColor(Color::BLACK))
+    .insert_resource(GlobalAmbientLight::NONE)
+    .insert_resource(WireframeConfig {
+        global: false,
+        default_color: WHITE.into(),
+    })
+    .init_resource::<CameraMode>()
+    .init_resource::<OrbitFocus>()
+    .insert_resource(CameraBookmarks::load())
+    .add_plugins((
+        SettingsUiPlugin,
+        AdaptiveDetailPlugin,
+        TimeOfDayPlugin,
+        InspectorPlugin,
+        TrafficAudioPlugin,
+        WeatherPlugin,
+        CompassPlugin,
+        SeedBrowserPlugin,
+        CityBannerPlugin,
+    ))
+    .add_systems(Startup, setup)
+    .add_observer(frame_generated_city)
+    .add_systems(
+        Update,
+        (
+            take_screenshot,
             (
-                setup,
-                setup_settings_ui,
-                load_assets,
-                setup_city.after(load_assets),
-            ),
-        )
-        .add_systems(Startup, (spawn_stats_ui))
-        .add_systems(Update, (make_visible, simulate_cars, update_stats_ui))
-        .run();
+                reframe_camera,
+                fly_camera_tour,
+                update_free_camera_state,
+                adjust_free_camera_speed,
+                orbit_camera,
+                clamp_camera,
+            )
+                .chain(),
+            update_camera_speed_text,
+            (handle_bookmark_keys, update_bookmarks_text),
+        ),
+    );
+    // Replaces the saved settings loaded by the settings plugin
+    if let Some(settings) = settings {
+        app.insert_resource(settings);
+    }
+
+    // The window stays hidden while rendering to an image
+    match args.render {
+        Some(size) => {
+            app.add_plugins(OffscreenRenderPlugin {
+                size,
+                path: args.out,
+            });
+        }
+        None => {
+            app.add_systems(Update, make_visible);
+        }
+    }
+
+    if args.benchmark {
+        app.add_plugins(BenchmarkPlugin {
+            exit: args.benchmark_exit,
+        });
+    }
+
+    app.run();
 }
 
 fn make_visible(mut window: Single<&mut Window>, frames: Res<FrameCount>) {
@@ -146,180 +342,102 @@ fn make_visible(mut window: Single<&mut Window>, frames: Res<FrameCount>) {
     }
 }
 
-fn setup(mut commands: Commands, mut scattering_mediums: ResMut<Assets<ScatteringMedium>>) {
-    commands.spawn((
-        Camera3d::default(),
-        Hdr,
-        Transform::from_xyz(15.0, 10.0, 20.0).looking_at(Vec3::ZERO, Vec3::Y),
-        FreeCamera::default(),
-        Atmosphere::earthlike(scattering_mediums.add(ScatteringMedium::default())),
-        AtmosphereSettings::default(),
-        // The directional light illuminance used in this scene is
-        // quite bright, so raising the exposure compensation helps
-        // bring the scene to a nicer brightness range.
-        Exposure { ev100: 13.0 },
-        // Bloom gives the sun a much more natural look.
-        Bloom::NATURAL,
-        // Enables the atmosphere to drive reflections and ambient lighting (IBL) for this view
-        AtmosphereEnvironmentMapLight::default(),
-        Msaa::Off,
-        TemporalAntiAliasing::default(),
-        ContactShadows::default(),
-    ));
+/// Filter for the root of every UI tree
+type RootNode = (With<Node>, Without<ChildOf>);
 
-    commands.spawn((
-        DirectionalLight {
-            shadow_maps_enabled: Settings::default().shadow_maps_enabled,
-            contact_shadows_enabled: Settings::default().contact_shadows_enabled,
-            illuminance: light_consts::lux::RAW_SUNLIGHT,
-            ..default()
-        },
-        Transform::from_xyz(1.0, 0.15, 1.0).looking_at(Vec3::ZERO, Vec3::Y),
-    ));
-}
+/// Saves a screenshot of the current frame when pressing F12
+///
+/// Holding shift while pressing F12 hides the UI in the screenshot.
+fn take_screenshot(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    config: Res<CityConfig>,
+    mut ui_roots: Query<(Entity, &mut Visibility), RootNode>,
+) {
+    if !keyboard.just_pressed(KeyCode::F12) {
+        return;
+    }
 
-fn spawn_stats_ui(mut commands: Commands) {
-    commands
-        .spawn((
-            Node {
-                position_type: PositionType::Absolute,
-                bottom: Val::Px(10.0),
-                left: Val::Px(10.0),
-                padding: UiRect::all(Val::Px(10.0)),
-                ..default()
-            },
-            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.75)),
-        ))
-        .with_children(|parent| {
-            parent.spawn((
-                Text::new(""),
-                TextFont {
-                    font_size: FontSize::Px(20.0),
-                    ..default()
-                },
-                TextColor(Color::WHITE),
-                StatsText,
-            ));
-        });
-}
+    if let Err(err) = std::fs::create_dir_all("screenshots") {
+        error!("Failed to create the screenshots directory: {err}");
+        return;
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = format!("screenshots/city_{}_{timestamp}.png", config.seed);
 
-fn format_large_number(value: u32) -> String {
-    let mut s = String::new();
-    for (i, char) in value.to_string().chars().rev().enumerate() {
-        if i % 3 == 0 && i != 0 {
-            s.insert(0, ',');
+    let mut hidden_ui = vec![];
+    if keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        for (entity, mut visibility) in &mut ui_roots {
+            if *visibility != Visibility::Hidden {
+                *visibility = Visibility::Hidden;
+                hidden_ui.push(entity);
+            }
         }
-        s.insert(0, char);
     }
-    s
-}
 
-fn update_stats_ui(
-    mut stats_text: Single<&mut Text, With<StatsText>>,
-    stats: Res<SceneStats>,
-    entities: Query<Entity>,
-) {
-    let total_entities = entities.iter().count();
-    stats_text.0 = format!(
-        "Cars: {}\nLow Density: {}\nMedium Density: {}\nSkyscrapers: {}\nRoad Segments: {}\nTrees: {}\nTotal spawned mesh: {}\nTotal Entities: {}",
-        format_large_number(stats.cars_spawned),
-        format_large_number(stats.low_density_buildings),
-        format_large_number(stats.medium_density_buildings),
-        format_large_number(stats.skyscrapers),
-        format_large_number(stats.road_segments),
-        format_large_number(stats.trees),
-        format_large_number(
-            stats.cars_spawned
-                + stats.low_density_buildings
-                + stats.medium_density_buildings
-                + stats.skyscrapers
-                + stats.road_segments
-                + stats.trees
-        ),
-        format_large_number(total_entities as u32)
-    );
+    commands
+        .spawn(Screenshot::primary_window())
+        .observe(save_to_disk(path))
+        .observe(
+            move |_: On<ScreenshotCaptured>, mut visibilities: Query<&mut Visibility>| {
+                for entity in &hidden_ui {
+                    if let Ok(mut visibility) = visibilities.get_mut(*entity) {
+                        *visibility = Visibility::Inherited;
+                    }
+                }
+            },
+        );
 }
 
-fn setup_camera(mut commands: Commands, mut scattering_mediums: ResMut<Assets<ScatteringMedium>>) {
-    // camera
-    commands.spawn((
+fn setup(
+    mut commands: Commands,
+    mut scattering_mediums: ResMut<Assets<ScatteringMedium>>,
+    settings: Res<Settings>,
+    mut wireframe_config: ResMut<WireframeConfig>,
+) {
+    let mut camera = commands.spawn((
         Camera3d::default(),
         Hdr,
         Transform::from_xyz(15.0, 10.0, 20.0).looking_at(Vec3::ZERO, Vec3::Y),
-        FreeCamera::default(),
-        Atmosphere::earthlike(scattering_mediums.add(ScatteringMedium::default())),
-        AtmosphereSettings::default(),
+        // The speed is changed with the mouse wheel by `adjust_free_camera_speed` instead
+        FreeCamera {
+            scroll_factor: 0.0,
+            ..default()
+        },
         // The directional light illuminance used in this scene is
         // quite bright, so raising the exposure compensation helps
         // bring the scene to a nicer brightness range.
-        Exposure { ev100: 13.0 },
+        Exposure {
+            ev100: settings.exposure_ev100,
+        },
         // Bloom gives the sun a much more natural look.
-        Bloom::NATURAL,
-        // Enables the atmosphere to drive reflections and ambient lighting (IBL) for this view
-        AtmosphereEnvironmentMapLight::default(),
-        VolumetricFog {
-            ambient_intensity: 0.0,
-            ..default()
+        Bloom {
+            intensity: settings.bloom_intensity,
+            ..Bloom::NATURAL
         },
         Msaa::Off,
         TemporalAntiAliasing::default(),
-        // ScreenSpaceReflections::default(),
+        ContactShadows::default(),
     ));
+    if settings.screen_space_reflections_enabled {
+        camera.insert(ScreenSpaceReflections::default());
+    }
+    let camera = camera.id();
+    apply_atmosphere(&mut commands, camera, &settings, &mut scattering_mediums);
 
     commands.spawn((
         DirectionalLight {
-            shadow_maps_enabled: false,
+            shadow_maps_enabled: settings.shadow_maps_enabled,
+            contact_shadows_enabled: settings.contact_shadows_enabled,
             illuminance: light_consts::lux::RAW_SUNLIGHT,
             ..default()
         },
+        cascade_shadow_config(&settings),
         Transform::from_xyz(1.0, 0.15, 1.0).looking_at(Vec3::ZERO, Vec3::Y),
-        VolumetricLight,
     ));
-}
 
-fn setup_city(mut commands: Commands, assets: Res<CityAssets>, args: Res<Args>) {
-    spawn_city(&mut commands, &assets, args.seed, args.size);
-}
-
-#[derive(Component)]
-struct Road {
-    start: Vec3,
-    end: Vec3,
-}
-
-#[derive(Component)]
-struct Car {
-    offset: Vec3,
-    distance_traveled: f32,
-    dir: f32,
-}
-
-fn simulate_cars(
-    settings: Res<Settings>,
-    roads: Query<(&Road, &Transform, &Children), Without<Car>>,
-    mut cars: Query<(&mut Car, &mut Transform), Without<Road>>,
-    time: Res<Time>,
-) {
-    if !settings.simulate_cars {
-        return;
-    }
-    let speed = 1.5;
-
-    for (road, _, children) in &roads {
-        for child in children {
-            let Ok((mut car, mut car_transform)) = cars.get_mut(*child) else {
-                continue;
-            };
-
-            car.distance_traveled += speed * time.delta_secs();
-            let road_len = (road.end - road.start).length();
-            if car.distance_traveled > road_len {
-                car.distance_traveled = 0.0;
-            }
-            let direction = (road.end - road.start).normalize() * car.dir;
-
-            let progress = car.distance_traveled / road_len;
-            car_transform.translation = (road.start + car.offset) + direction * road_len * progress;
-        }
-    }
+    wireframe_config.global = settings.wireframe_enabled;
 }
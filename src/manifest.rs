@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+/// Where the manifest is read from, relative to the working directory
+const MANIFEST_PATH: &str = "assets/buildings.ron";
+
+/// Describes the buildings used for each density, see `assets/buildings.ron`
+///
+/// Adding a building pack only requires adding its meshes and textures to the manifest.
+#[derive(Deserialize, Debug)]
+pub struct BuildingManifest {
+    pub high_density: BuildingSet,
+    pub medium_density: BuildingSet,
+    pub low_density: BuildingSet,
+    /// The warehouses replacing the medium density buildings in the industrial districts
+    pub industrial: BuildingSet,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BuildingSet {
+    /// Color of the low poly boxes displayed instead of the buildings far from the camera
+    pub lod_color: [u8; 3],
+    /// Textures the buildings can be painted with, any of them can be used with any mesh
+    pub materials: Vec<String>,
+    pub meshes: Vec<BuildingMesh>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BuildingMesh {
+    /// Path of the glTF file, relative to the kenney directory
+    pub path: String,
+    /// Half size of the mesh on the x and z axis
+    pub footprint: [f32; 2],
+}
+
+impl BuildingManifest {
+    /// Reads the manifest, falling back to the one built into the binary if it can't be read
+    pub fn load() -> Self {
+        let contents = match std::fs::read_to_string(MANIFEST_PATH) {
+            Ok(contents) => contents,
+            Err(err) => {
+                warn!("Failed to read {MANIFEST_PATH}, using the builtin one: {err}");
+                return Self::builtin();
+            }
+        };
+        let manifest: Self = match ron::from_str(&contents) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                warn!("Failed to parse {MANIFEST_PATH}, using the builtin one: {err}");
+                return Self::builtin();
+            }
+        };
+        if let Err(err) = manifest.validate() {
+            warn!("Invalid {MANIFEST_PATH}, using the builtin one: {err}");
+            return Self::builtin();
+        }
+        manifest
+    }
+
+    /// Checks that every set has something to pick from, the buildings are picked with a random
+    /// index in the meshes and materials of their set
+    fn validate(&self) -> Result<(), String> {
+        let sets = [
+            ("high_density", &self.high_density),
+            ("medium_density", &self.medium_density),
+            ("low_density", &self.low_density),
+            ("industrial", &self.industrial),
+        ];
+        for (name, set) in sets {
+            if set.meshes.is_empty() {
+                return Err(format!("`{name}` has no meshes"));
+            }
+            if set.materials.is_empty() {
+                return Err(format!("`{name}` has no materials"));
+            }
+        }
+        Ok(())
+    }
+
+    fn builtin() -> Self {
+        ron::from_str(include_str!("../assets/buildings.ron"))
+            .expect("The builtin building manifest should be valid")
+    }
+}
@@ -0,0 +1,130 @@
+use std::{path::PathBuf, str::FromStr};
+
+use bevy::{
+    camera::RenderTarget,
+    diagnostic::FrameCount,
+    prelude::*,
+    render::{
+        render_resource::{TextureFormat, TextureUsages},
+        view::screenshot::{Screenshot, ScreenshotCaptured, save_to_disk},
+    },
+};
+
+use crate::city::CityGenerated;
+
+/// Number of frames rendered after the city is generated before capturing it, the scenes of the
+/// city are spawned over the next frames and the temporal anti-aliasing needs a few frames to
+/// converge
+pub const SETTLE_FRAMES: u32 = 30;
+
+/// Size of the image rendered with `--render`, parsed from `<width>x<height>`
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSize(pub UVec2);
+
+impl FromStr for RenderSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid size `{s}`, expected `<width>x<height>` like `3840x2160`");
+        let (width, height) = s.split_once('x').ok_or_else(error)?;
+        let size = UVec2::new(
+            width.parse().map_err(|_| error())?,
+            height.parse().map_err(|_| error())?,
+        );
+        if size.min_element() == 0 {
+            return Err(error());
+        }
+        Ok(Self(size))
+    }
+}
+
+/// Renders the city to an image of any size instead of the window, saves it once the city is
+/// generated and exits
+///
+/// The size is only limited by the largest texture the GPU supports, usually 8192 or 16384
+/// pixels on each side.
+pub struct OffscreenRenderPlugin {
+    pub size: RenderSize,
+    /// Where the image is saved, the format is picked from the extension
+    pub path: PathBuf,
+}
+
+#[derive(Resource)]
+struct OffscreenRender {
+    size: RenderSize,
+    path: PathBuf,
+    image: Handle<Image>,
+    /// The frame the city was generated on
+    generated_frame: Option<u32>,
+}
+
+impl Plugin for OffscreenRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(OffscreenRender {
+            size: self.size,
+            path: self.path.clone(),
+            image: Handle::default(),
+            generated_frame: None,
+        })
+        .add_systems(PostStartup, target_offscreen_image)
+        .add_observer(wait_for_city)
+        .add_systems(Update, capture_offscreen_image);
+    }
+}
+
+/// Creates an image the cameras can render to instead of the window, it can be saved with a
+/// [`Screenshot`] or shown in the UI
+pub fn new_render_target(images: &mut Assets<Image>, size: UVec2) -> Handle<Image> {
+    let mut image = Image::new_target_texture(size.x, size.y, TextureFormat::bevy_default(), None);
+    image.texture_descriptor.usage |= TextureUsages::COPY_SRC;
+    images.add(image)
+}
+
+/// Points the cameras at the image instead of the window, the UI is only drawn on the window so
+/// it's left out of the image
+fn target_offscreen_image(
+    mut commands: Commands,
+    mut render: ResMut<OffscreenRender>,
+    mut images: ResMut<Assets<Image>>,
+    cameras: Query<Entity, With<Camera3d>>,
+) {
+    let RenderSize(size) = render.size;
+    render.image = new_render_target(&mut images, size);
+    for camera in &cameras {
+        commands
+            .entity(camera)
+            .insert(RenderTarget::Image(render.image.clone().into()));
+    }
+}
+
+fn wait_for_city(
+    _: On<CityGenerated>,
+    mut render: ResMut<OffscreenRender>,
+    frames: Res<FrameCount>,
+) {
+    if render.generated_frame.is_none() {
+        render.generated_frame = Some(frames.0);
+    }
+}
+
+/// Captures the image once the city had time to settle and exits once it's saved
+fn capture_offscreen_image(
+    mut commands: Commands,
+    render: Res<OffscreenRender>,
+    frames: Res<FrameCount>,
+) {
+    let Some(generated_frame) = render.generated_frame else {
+        return;
+    };
+    if frames.0 != generated_frame + SETTLE_FRAMES {
+        return;
+    }
+    commands
+        .spawn(Screenshot::image(render.image.clone()))
+        .observe(save_to_disk(render.path.clone()))
+        .observe(
+            |_: On<ScreenshotCaptured>, mut app_exit: MessageWriter<AppExit>| {
+                app_exit.write(AppExit::Success);
+            },
+        );
+}
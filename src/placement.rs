@@ -0,0 +1,518 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use bevy::prelude::*;
+use rand::RngExt;
+
+use crate::generate_city::{CityConfig, ROUNDABOUT_HALF_SIZE, block_offset};
+
+/// What should be spawned at a [`Transform`] returned by one of the placement functions
+///
+/// The placement functions only compute where everything in a block goes without spawning
+/// anything so the layout can be reused outside of the spawning code.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlacementKind {
+    LowDensityBuilding,
+    /// Holds the index of the mesh in the medium density buildings, the mesh is picked when
+    /// placing them so they can be packed next to each other
+    MediumDensityBuilding(usize),
+    /// Holds the index of the mesh in the industrial buildings, they are placed like the medium
+    /// density buildings
+    IndustrialBuilding(usize),
+    Skyscraper,
+    Smokestack,
+    SmallTree,
+    LargeTree,
+    Fence,
+    PathStones,
+    Bench,
+}
+
+impl PlacementKind {
+    /// Half size on the x and z axis of the largest mesh that can be spawned for this kind, `None`
+    /// for everything that isn't a building or a smokestack
+    ///
+    /// The mesh is only picked when spawning so this covers every building of the kind.
+    pub fn footprint(self) -> Option<Vec2> {
+        match self {
+            PlacementKind::LowDensityBuilding => Some(Vec2::new(0.91, 0.7)),
+            PlacementKind::MediumDensityBuilding(_) => Some(Vec2::new(0.49, 0.55)),
+            PlacementKind::IndustrialBuilding(_) => Some(Vec2::new(1.05, 0.51)),
+            PlacementKind::Skyscraper => Some(Vec2::new(0.69, 0.7)),
+            PlacementKind::Smokestack => Some(Vec2::splat(SMOKESTACK_RADIUS)),
+            _ => None,
+        }
+    }
+
+    pub fn is_tree(self) -> bool {
+        matches!(self, PlacementKind::SmallTree | PlacementKind::LargeTree)
+    }
+}
+
+/// Half width of the roads
+pub const ROAD_HALF_WIDTH: f32 = 0.5;
+/// Width of the sidewalks running along the roads on the inside of the blocks
+pub const SIDEWALK_WIDTH: f32 = 0.1;
+/// Space along the x axis between the sidewalks on each side of a block
+const BLOCK_INTERIOR_MIN_X: f32 = ROAD_HALF_WIDTH + SIDEWALK_WIDTH;
+const BLOCK_INTERIOR_MAX_X: f32 = 5.0 - SIDEWALK_WIDTH;
+/// Space left between buildings packed next to each other
+const BUILDING_GAP: f32 = 0.05;
+/// Chance for an industrial block to have a smokestack
+const SMOKESTACK_CHANCE: f64 = 0.4;
+/// Radius of the smokestacks of the industrial blocks
+pub const SMOKESTACK_RADIUS: f32 = 0.09;
+/// Height of the smokestacks of the industrial blocks
+pub const SMOKESTACK_HEIGHT: f32 = 1.8;
+/// How many times a building can be pushed out of an occupied area before giving up on it
+const MAX_PLACEMENT_SHIFTS: usize = 4;
+/// Half size of the area around the trunk of a tree that has to stay clear, the branches can
+/// hang over the sidewalks and the roofs
+const TREE_TRUNK_HALF_SIZE: f32 = 0.08;
+
+/// The areas of a block that are already occupied, relative to the crossroad of the block
+///
+/// This is used to keep the buildings off the roads around the block, the buildings that
+/// overlap an occupied area are pushed out of it or skipped if they can't fit. The trees are
+/// kept off the roads and the buildings, they are skipped when they overlap either.
+pub struct BlockOccupancy {
+    offset: Vec3,
+    occupied: Vec<Rect>,
+}
+
+impl BlockOccupancy {
+    /// Reserves the roads and sidewalks on the 4 sides of the block, including the roads spawned
+    /// by the neighboring blocks
+    pub fn with_roads(offset: Vec3) -> Self {
+        let block_size = block_offset(IVec2::ONE).xz();
+        let edge = ROAD_HALF_WIDTH + SIDEWALK_WIDTH;
+        let min = Vec2::splat(-ROAD_HALF_WIDTH);
+        let max = block_size + ROAD_HALF_WIDTH;
+        let mut occupancy = Self {
+            offset,
+            occupied: Vec::new(),
+        };
+        occupancy.reserve(Rect::from_corners(min, Vec2::new(max.x, edge)));
+        occupancy.reserve(Rect::from_corners(
+            Vec2::new(min.x, block_size.y - edge),
+            max,
+        ));
+        occupancy.reserve(Rect::from_corners(min, Vec2::new(edge, max.y)));
+        occupancy.reserve(Rect::from_corners(
+            Vec2::new(block_size.x - edge, min.y),
+            max,
+        ));
+        occupancy
+    }
+
+    pub fn reserve(&mut self, area: Rect) {
+        self.occupied.push(area);
+    }
+
+    /// The area covered by a mesh with the given footprint, relative to the crossroad of the
+    /// block
+    ///
+    /// `half_size` is the footprint of the mesh before it gets rotated and scaled.
+    fn area(&self, transform: &Transform, half_size: Vec2) -> Rect {
+        Rect::from_center_half_size(
+            transform.translation.xz() - self.offset.xz(),
+            rotated_half_size(transform.rotation, half_size * transform.scale.xz()),
+        )
+    }
+
+    fn is_free(&self, area: Rect) -> bool {
+        self.occupied
+            .iter()
+            .all(|occupied| occupied.intersect(area).is_empty())
+    }
+
+    /// Moves the building out of the occupied areas, returns `None` if it doesn't fit
+    ///
+    /// `half_size` is the footprint of the mesh before it gets rotated and scaled.
+    pub fn place(&self, mut transform: Transform, half_size: Vec2) -> Option<Transform> {
+        let half_size = rotated_half_size(transform.rotation, half_size * transform.scale.xz());
+
+        let mut center = transform.translation.xz() - self.offset.xz();
+        for _ in 0..MAX_PLACEMENT_SHIFTS {
+            let footprint = Rect::from_center_half_size(center, half_size);
+            let Some((area, overlap)) = self
+                .occupied
+                .iter()
+                .map(|area| (area, area.intersect(footprint)))
+                .find(|(_, overlap)| !overlap.is_empty())
+            else {
+                transform.translation.x = center.x + self.offset.x;
+                transform.translation.z = center.y + self.offset.z;
+                return Some(transform);
+            };
+
+            // Push the building out on the axis where it overlaps the least
+            let overlap_size = overlap.size();
+            let away = footprint.center() - area.center();
+            if overlap_size.x < overlap_size.y {
+                center.x += overlap_size.x.copysign(away.x);
+            } else {
+                center.y += overlap_size.y.copysign(away.y);
+            }
+        }
+        None
+    }
+
+    /// Places every building of the block and skips the trees that overlap the roads or the
+    /// buildings, the other placements are kept as is
+    ///
+    /// The buildings are placed with the footprint of their kind but the trees are only kept off
+    /// the footprint returned by `mesh_footprint`, which can be the footprint of the actual mesh
+    /// when it's already picked.
+    pub fn place_all(
+        mut self,
+        placements: Vec<(PlacementKind, Transform)>,
+        mesh_footprint: impl Fn(PlacementKind) -> Option<Vec2>,
+    ) -> Vec<(PlacementKind, Transform)> {
+        let placements = placements
+            .into_iter()
+            .filter_map(|(kind, transform)| match kind.footprint() {
+                Some(half_size) => Some((kind, self.place(transform, half_size)?)),
+                None => Some((kind, transform)),
+            })
+            .collect::<Vec<_>>();
+
+        // The buildings are only reserved once they are all placed so they don't push each
+        // other around
+        for (kind, transform) in &placements {
+            if let Some(half_size) = mesh_footprint(*kind) {
+                self.reserve(self.area(transform, half_size));
+            }
+        }
+        placements
+            .into_iter()
+            .filter(|(kind, transform)| {
+                !kind.is_tree()
+                    || self.is_free(self.area(transform, Vec2::splat(TREE_TRUNK_HALF_SIZE)))
+            })
+            .collect()
+    }
+}
+
+/// Houses with a fence between them and rows of trees on each side
+pub fn low_density_transforms<R: RngExt>(
+    offset: Vec3,
+    rng: &mut R,
+    config: &CityConfig,
+) -> Vec<(PlacementKind, Transform)> {
+    let mut placements = Vec::new();
+    let x_factor = 1.8;
+    for x in 1..=2 {
+        let x = x as f32 * x_factor;
+        placements.push((
+            PlacementKind::LowDensityBuilding,
+            Transform::from_translation(Vec3::new(x, 0.0, 1.25) + offset)
+                .with_rotation(building_rotation(rng, config, 0.0)),
+        ));
+        placements.push((
+            PlacementKind::LowDensityBuilding,
+            Transform::from_translation(Vec3::new(x, 0.0, 2.75) + offset)
+                .with_rotation(building_rotation(rng, config, PI)),
+        ));
+    }
+    for i in 0..=6 {
+        placements.push((
+            PlacementKind::Fence,
+            Transform::from_translation(Vec3::new(2.75, 0.0, 0.75 + i as f32 * 0.4) + offset)
+                .with_rotation(Quat::from_axis_angle(Vec3::Y, FRAC_PI_2)),
+        ));
+    }
+    for z in 0..=8 {
+        let z = 0.75 + z as f32 * 0.3;
+        placements.push((
+            PlacementKind::SmallTree,
+            Transform::from_translation(Vec3::new(0.75, 0.0, z) + offset),
+        ));
+        placements.push((
+            PlacementKind::SmallTree,
+            Transform::from_translation(Vec3::new(4.75, 0.0, z) + offset),
+        ));
+    }
+    BlockOccupancy::with_roads(offset).place_all(placements, PlacementKind::footprint)
+}
+
+/// Two rows of apartments separated by a fenced walking path lined with trees
+///
+/// The apartments are packed next to each other using the footprint of their mesh, `footprints`
+/// being the half size of each medium density building mesh.
+pub fn medium_density_transforms<R: RngExt>(
+    offset: Vec3,
+    rng: &mut R,
+    config: &CityConfig,
+    footprints: &[Vec2],
+) -> Vec<(PlacementKind, Transform)> {
+    let mut placements = Vec::new();
+    for (z, road_yaw) in [(1.0, 0.0), (3.0, PI)] {
+        pack_buildings_along_road(
+            &mut placements,
+            rng,
+            config,
+            footprints,
+            PlacementKind::MediumDensityBuilding,
+            offset,
+            z,
+            road_yaw,
+        );
+    }
+
+    for x in 0..=8 {
+        let x = 0.9 + x as f32 * 0.45;
+        for z in [1.75, 2.25] {
+            placements.push((
+                PlacementKind::LargeTree,
+                Transform::from_translation(Vec3::new(x, 0.0, z) + offset),
+            ));
+        }
+    }
+
+    for x in 0..=10 {
+        let x = 0.75 + x as f32 * 0.4;
+        placements.push((
+            PlacementKind::PathStones,
+            Transform::from_translation(Vec3::new(x, 0.02, 2.0) + offset)
+                .with_scale(Vec3::new(1.0, 2.0, 1.0))
+                .with_rotation(Quat::from_axis_angle(Vec3::Y, FRAC_PI_2)),
+        ));
+        placements.push((
+            PlacementKind::Fence,
+            Transform::from_translation(Vec3::new(x, 0.02, 1.85) + offset),
+        ));
+        placements.push((
+            PlacementKind::Fence,
+            Transform::from_translation(Vec3::new(x, 0.02, 2.15) + offset),
+        ));
+    }
+    BlockOccupancy::with_roads(offset).place_all(placements, |kind| match kind {
+        PlacementKind::MediumDensityBuilding(index) => Some(footprints[index]),
+        _ => kind.footprint(),
+    })
+}
+
+/// A row of warehouses along the road with a parking lot behind them, some of the blocks also
+/// get a smokestack between the two
+///
+/// The warehouses are packed like the medium density buildings using the footprint of their
+/// mesh. The cars of the parking lot are spawned with the block.
+pub fn industrial_transforms<R: RngExt>(
+    offset: Vec3,
+    rng: &mut R,
+    config: &CityConfig,
+    footprints: &[Vec2],
+) -> Vec<(PlacementKind, Transform)> {
+    let mut placements = Vec::new();
+    pack_buildings_along_road(
+        &mut placements,
+        rng,
+        config,
+        footprints,
+        PlacementKind::IndustrialBuilding,
+        offset,
+        1.25,
+        0.0,
+    );
+    // The warehouses are too different in size to be placed with the footprint of the largest
+    // one like the other buildings, it would push the small ones into their neighbors
+    let occupancy = BlockOccupancy::with_roads(offset);
+    let mut placements = placements
+        .into_iter()
+        .filter_map(|(kind, transform)| match kind {
+            PlacementKind::IndustrialBuilding(index) => {
+                Some((kind, occupancy.place(transform, footprints[index])?))
+            }
+            _ => Some((kind, transform)),
+        })
+        .collect::<Vec<_>>();
+    if rng.random_bool(SMOKESTACK_CHANCE) {
+        let x = rng.random_range(BLOCK_INTERIOR_MIN_X + 0.2..BLOCK_INTERIOR_MAX_X - 0.2);
+        placements.push((
+            PlacementKind::Smokestack,
+            Transform::from_translation(Vec3::new(x, 0.0, 2.1) + offset),
+        ));
+    }
+    placements
+}
+
+/// Packs buildings next to each other from the start of the block interior until no building
+/// fits anymore
+///
+/// `kind` makes the placement from the index of the mesh picked in `footprints`, `z` is the
+/// position of the row relative to the crossroad.
+#[allow(clippy::too_many_arguments)]
+fn pack_buildings_along_road<R: RngExt>(
+    placements: &mut Vec<(PlacementKind, Transform)>,
+    rng: &mut R,
+    config: &CityConfig,
+    footprints: &[Vec2],
+    kind: fn(usize) -> PlacementKind,
+    offset: Vec3,
+    z: f32,
+    road_yaw: f32,
+) {
+    let mut x = BLOCK_INTERIOR_MIN_X + BUILDING_GAP;
+    loop {
+        let rotation = building_rotation(rng, config, road_yaw);
+        let remaining = BLOCK_INTERIOR_MAX_X - x;
+        let width = |index: usize| rotated_half_size(rotation, footprints[index]).x * 2.0;
+        // Re-roll to one of the buildings that still fit when the picked one is too wide
+        let mut index = rng.random_range(0..footprints.len());
+        if width(index) > remaining {
+            let fitting = (0..footprints.len())
+                .filter(|index| width(*index) <= remaining)
+                .collect::<Vec<_>>();
+            if fitting.is_empty() {
+                break;
+            }
+            index = fitting[rng.random_range(0..fitting.len())];
+        }
+
+        let half_width = width(index) / 2.0;
+        placements.push((
+            kind(index),
+            Transform::from_translation(Vec3::new(x + half_width, 0.0, z) + offset)
+                .with_rotation(rotation),
+        ));
+        x += half_width * 2.0 + BUILDING_GAP;
+    }
+}
+
+/// Two rows of three skyscrapers
+///
+/// The skyscrapers are kept off the roundabouts at the given crossroads, see
+/// [`BlockNeighbors::roundabouts`](crate::generate_city::BlockNeighbors::roundabouts).
+pub fn high_density_transforms<R: RngExt>(
+    offset: Vec3,
+    rng: &mut R,
+    config: &CityConfig,
+    roundabouts: &[IVec2],
+) -> Vec<(PlacementKind, Transform)> {
+    let mut placements = Vec::new();
+    for x in 0..3 {
+        let x = 1.25 + x as f32 * 1.5;
+        placements.push((
+            PlacementKind::Skyscraper,
+            Transform::from_translation(Vec3::new(x, 0.0, 1.25) + offset)
+                .with_rotation(building_rotation(rng, config, 0.0))
+                .with_scale(skyscraper_scale(rng, config)),
+        ));
+        placements.push((
+            PlacementKind::Skyscraper,
+            Transform::from_translation(Vec3::new(x, 0.0, 2.75) + offset)
+                .with_rotation(building_rotation(rng, config, PI))
+                .with_scale(skyscraper_scale(rng, config)),
+        ));
+    }
+    let mut occupancy = BlockOccupancy::with_roads(offset);
+    for crossroad in roundabouts {
+        occupancy.reserve(Rect::from_center_half_size(
+            block_offset(*crossroad).xz(),
+            Vec2::splat(ROUNDABOUT_HALF_SIZE),
+        ));
+    }
+    occupancy.place_all(placements, PlacementKind::footprint)
+}
+
+/// A grid of trees with some gaps in it
+pub fn forest_transforms<R: RngExt>(offset: Vec3, rng: &mut R) -> Vec<(PlacementKind, Transform)> {
+    let mut placements = Vec::new();
+    for x in 0..=12 {
+        for z in 0..=8 {
+            let transform = Transform::from_translation(
+                Vec3::new(x as f32, 0.0, z as f32) * Vec3::new(0.325, 0.0, 0.3)
+                    + Vec3::new(0.75, 0.0, 0.85)
+                    + offset,
+            );
+
+            match rng.random_range(0..3) {
+                1 => placements.push((PlacementKind::SmallTree, transform)),
+                2 => placements.push((PlacementKind::LargeTree, transform)),
+                _ => {}
+            }
+        }
+    }
+    placements
+}
+
+/// Two paths crossing in the middle of the block with benches along them and trees around the
+/// edges
+pub fn park_transforms<R: RngExt>(offset: Vec3, rng: &mut R) -> Vec<(PlacementKind, Transform)> {
+    let mut placements = Vec::new();
+    for x in 0..=10 {
+        placements.push((
+            PlacementKind::PathStones,
+            Transform::from_translation(Vec3::new(0.75 + x as f32 * 0.4, 0.02, 2.0) + offset)
+                .with_scale(Vec3::new(1.0, 2.0, 1.0))
+                .with_rotation(Quat::from_axis_angle(Vec3::Y, FRAC_PI_2)),
+        ));
+    }
+    for z in 0..=6 {
+        placements.push((
+            PlacementKind::PathStones,
+            Transform::from_translation(Vec3::new(2.75, 0.02, 0.8 + z as f32 * 0.4) + offset)
+                .with_scale(Vec3::new(1.0, 2.0, 1.0)),
+        ));
+    }
+    for x in [1.75, 3.75] {
+        placements.push((
+            PlacementKind::Bench,
+            Transform::from_translation(Vec3::new(x, 0.0, 1.75) + offset),
+        ));
+        placements.push((
+            PlacementKind::Bench,
+            Transform::from_translation(Vec3::new(x, 0.0, 2.25) + offset)
+                .with_rotation(Quat::from_axis_angle(Vec3::Y, PI)),
+        ));
+    }
+    for x in 0..=12 {
+        let x = 0.8 + x as f32 * 0.325;
+        // Leave the entrance of the path clear
+        if (x - 2.75).abs() < 0.3 {
+            continue;
+        }
+        for z in [0.85, 3.15] {
+            let transform = Transform::from_translation(Vec3::new(x, 0.0, z) + offset);
+            match rng.random_range(0..4) {
+                1 => placements.push((PlacementKind::SmallTree, transform)),
+                2 | 3 => placements.push((PlacementKind::LargeTree, transform)),
+                _ => {}
+            }
+        }
+    }
+    placements
+}
+
+/// Half size on the x and z axis of the bounds of a footprint once rotated
+fn rotated_half_size(rotation: Quat, half_size: Vec2) -> Vec2 {
+    let x_axis = rotation * Vec3::X;
+    let z_axis = rotation * Vec3::Z;
+    Vec2::new(
+        x_axis.x.abs() * half_size.x + z_axis.x.abs() * half_size.y,
+        x_axis.z.abs() * half_size.x + z_axis.z.abs() * half_size.y,
+    )
+}
+
+/// Picks the rotation of a building that faces the road with the given yaw
+///
+/// The rng is always sampled, even when the yaw isn't randomized, so toggling
+/// `randomize_building_yaw` doesn't change any of the other random choices made for the block.
+fn building_rotation<R: RngExt>(rng: &mut R, config: &CityConfig, road_yaw: f32) -> Quat {
+    let random_yaw = rng.random_range(0..4) as f32 * FRAC_PI_2;
+    let yaw = if config.randomize_building_yaw {
+        random_yaw
+    } else {
+        road_yaw
+    };
+    Quat::from_axis_angle(Vec3::Y, yaw)
+}
+
+/// Picks a random vertical scale for a skyscraper
+///
+/// The building meshes have their origin at their base so only scaling the y axis keeps them
+/// planted on the ground tile without having to move them.
+fn skyscraper_scale<R: RngExt>(rng: &mut R, config: &CityConfig) -> Vec3 {
+    let height = rng.random_range(config.skyscraper_height_range.clone());
+    Vec3::new(1.0, height, 1.0)
+}
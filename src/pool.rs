@@ -0,0 +1,102 @@
+use bevy::{platform::collections::HashMap, prelude::*};
+
+use crate::{
+    car_paint::CarPaint,
+    generate_city::{CarMarker, ParkedCarMarker, spawn_scene},
+    traffic::{Car, TrafficInterpolation},
+};
+
+/// Identifies which asset a pooled entity displays
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PoolKey {
+    Scene(AssetId<Scene>),
+    Mesh(AssetId<Mesh>, AssetId<StandardMaterial>),
+}
+
+/// Marks an entity that can be returned to the [`EntityPool`] instead of being despawned
+#[derive(Component)]
+pub struct Pooled(pub PoolKey);
+
+/// Entities of a previous city kept around to be reused when the city is regenerated
+///
+/// Spawning and despawning hundreds of thousands of scenes is expensive so instead of despawning
+/// the buildings and cars of the old city they are hidden and reused by the new city. Reused
+/// entities keep the asset they were displaying, only their `Transform` and parent get reset.
+#[derive(Resource, Default)]
+pub struct EntityPool {
+    free: HashMap<PoolKey, Vec<Entity>>,
+}
+
+impl EntityPool {
+    /// Spawns a scene as a child of the spawner, reusing a pooled entity with the same scene if
+    /// there's one available
+    pub fn spawn_scene<'a>(
+        &mut self,
+        commands: &'a mut ChildSpawnerCommands,
+        scene: &Handle<Scene>,
+        transform: Transform,
+    ) -> EntityCommands<'a> {
+        let key = PoolKey::Scene(scene.id());
+        match self.take(key) {
+            Some(entity) => Self::reuse(commands, entity, transform),
+            None => spawn_scene(commands, scene, transform, Pooled(key)),
+        }
+    }
+
+    /// Spawns a mesh as a child of the spawner, reusing a pooled entity with the same mesh and
+    /// material if there's one available
+    pub fn spawn_mesh<'a>(
+        &mut self,
+        commands: &'a mut ChildSpawnerCommands,
+        (mesh, material): (Mesh3d, MeshMaterial3d<StandardMaterial>),
+        transform: Transform,
+    ) -> EntityCommands<'a> {
+        let key = PoolKey::Mesh(mesh.id(), material.id());
+        match self.take(key) {
+            Some(entity) => Self::reuse(commands, entity, transform),
+            None => commands.spawn((mesh, material, transform, Pooled(key))),
+        }
+    }
+
+    /// Hides the entity and detaches it from its parent so it survives the city being despawned
+    ///
+    /// The components of a moving car are removed, a car reused as a parked car would otherwise
+    /// keep being moved by the traffic, and so is its paint so a car reused while the colors
+    /// aren't randomized gets back the color of its model.
+    pub fn release(&mut self, commands: &mut Commands, entity: Entity, key: PoolKey) {
+        commands
+            .entity(entity)
+            .remove::<(
+                ChildOf,
+                Car,
+                CarMarker,
+                ParkedCarMarker,
+                TrafficInterpolation,
+                CarPaint,
+            )>()
+            .insert(Visibility::Hidden);
+        self.free.entry(key).or_default().push(entity);
+    }
+
+    /// Despawns every entity currently waiting in the pool
+    pub fn clear_pool(&mut self, commands: &mut Commands) {
+        for entity in self.free.drain().flat_map(|(_, entities)| entities) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    fn take(&mut self, key: PoolKey) -> Option<Entity> {
+        self.free.get_mut(&key)?.pop()
+    }
+
+    fn reuse<'a>(
+        commands: &'a mut ChildSpawnerCommands,
+        entity: Entity,
+        transform: Transform,
+    ) -> EntityCommands<'a> {
+        let parent = commands.target_entity();
+        let mut entity_commands = commands.commands_mut().entity(entity);
+        entity_commands.insert((transform, Visibility::Inherited, ChildOf(parent)));
+        entity_commands
+    }
+}
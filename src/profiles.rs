@@ -0,0 +1,179 @@
+use bevy::{prelude::*, ui::Checked, ui_widgets::ValueChange};
+
+use crate::{
+    city::RegenerateCity,
+    density::NoiseConfig,
+    generate_city::{CityConfig, DensityThresholds},
+    settings::Settings,
+};
+
+/// A preset of the parameters shaping the city, picked from the settings
+///
+/// New profiles only need a constant here and an entry in [`ALL`].
+#[derive(Debug)]
+pub struct GenerationProfile {
+    pub name: &'static str,
+    pub density_thresholds: DensityThresholds,
+    pub noise: NoiseConfig,
+    /// Number of blocks on each side of the grid
+    pub size: u32,
+    pub car_density: f32,
+}
+
+/// The city the generator was tuned for, these are the defaults of [`CityConfig`]
+pub const DENSE_METROPOLIS: GenerationProfile = GenerationProfile {
+    name: "Dense Metropolis",
+    density_thresholds: DensityThresholds {
+        forest: 0.45,
+        park: 0.5,
+        low: 0.6,
+        medium: 0.7,
+    },
+    noise: NoiseConfig {
+        octaves: 1,
+        lacunarity: 2.0,
+        persistence: 0.5,
+        scale: 0.025,
+    },
+    size: 30,
+    car_density: 0.4,
+};
+
+/// Mostly houses and small apartment buildings around a few towers
+pub const BALANCED_TOWN: GenerationProfile = GenerationProfile {
+    name: "Balanced Town",
+    density_thresholds: DensityThresholds {
+        forest: 0.35,
+        park: 0.42,
+        low: 0.62,
+        medium: 0.8,
+    },
+    noise: NoiseConfig {
+        octaves: 2,
+        lacunarity: 2.0,
+        persistence: 0.5,
+        scale: 0.03,
+    },
+    size: 24,
+    car_density: 0.25,
+};
+
+/// Houses spread between forests and parks with almost no towers
+pub const SPARSE_SUBURBS: GenerationProfile = GenerationProfile {
+    name: "Sparse Suburbs",
+    density_thresholds: DensityThresholds {
+        forest: 0.32,
+        park: 0.4,
+        low: 0.8,
+        medium: 0.92,
+    },
+    noise: NoiseConfig {
+        octaves: 2,
+        lacunarity: 2.0,
+        persistence: 0.4,
+        scale: 0.04,
+    },
+    size: 20,
+    car_density: 0.15,
+};
+
+/// A huge grid covered by skyscrapers with a few green spaces left
+pub const MEGACITY: GenerationProfile = GenerationProfile {
+    name: "Megacity",
+    density_thresholds: DensityThresholds {
+        forest: 0.28,
+        park: 0.32,
+        low: 0.38,
+        medium: 0.52,
+    },
+    // Bigger districts so the skyscrapers form large downtowns
+    noise: NoiseConfig {
+        octaves: 3,
+        lacunarity: 2.0,
+        persistence: 0.5,
+        scale: 0.015,
+    },
+    size: 40,
+    car_density: 0.6,
+};
+
+pub const ALL: [&GenerationProfile; 4] = [
+    &DENSE_METROPOLIS,
+    &BALANCED_TOWN,
+    &SPARSE_SUBURBS,
+    &MEGACITY,
+];
+
+impl GenerationProfile {
+    /// Copies the parameters of the profile to the config, the city needs to be regenerated to
+    /// use them
+    pub fn apply(&self, config: &mut CityConfig, settings: &mut Settings) {
+        config.density_thresholds = self.density_thresholds;
+        config.noise = self.noise.clone();
+        config.size = self.size;
+        config.car_density = self.car_density;
+        // Also update the live car density so it doesn't add or remove cars on its own
+        settings.car_density = self.car_density;
+    }
+
+    /// Whether the config uses the parameters of this profile
+    pub fn matches(&self, config: &CityConfig) -> bool {
+        config.density_thresholds == self.density_thresholds
+            && config.noise == self.noise
+            && config.size == self.size
+    }
+}
+
+/// The radio button of a profile in the settings
+#[derive(Component)]
+pub struct ProfileRadio(pub &'static GenerationProfile);
+
+/// Applies the profile picked in the settings and regenerates the city with it
+pub fn select_profile(
+    change: On<ValueChange<Entity>>,
+    profiles: Query<(Entity, &ProfileRadio)>,
+    mut commands: Commands,
+    mut config: ResMut<CityConfig>,
+    mut settings: ResMut<Settings>,
+) {
+    for (radio, profile) in &profiles {
+        if radio != change.value {
+            commands.entity(radio).remove::<Checked>();
+            continue;
+        }
+        commands.entity(radio).insert(Checked);
+        profile.0.apply(&mut config, &mut settings);
+        commands.trigger(RegenerateCity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_have_ordered_thresholds() {
+        for profile in ALL {
+            let DensityThresholds {
+                forest,
+                park,
+                low,
+                medium,
+            } = profile.density_thresholds;
+            assert!(forest < park && park < low && low < medium, "{profile:?}");
+            assert!(
+                [forest, park, low, medium]
+                    .iter()
+                    .all(|threshold| (0.0..=1.0).contains(threshold)),
+                "{profile:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn dense_metropolis_is_the_default_config() {
+        let config = CityConfig::default();
+        assert!(DENSE_METROPOLIS.matches(&config));
+        assert_eq!(DENSE_METROPOLIS.car_density, config.car_density);
+    }
+}
@@ -0,0 +1,75 @@
+use std::{convert::Infallible, str::FromStr};
+
+use rand::{
+    Rng, SeedableRng, TryRng,
+    rngs::{ChaCha8Rng, SmallRng},
+};
+use serde::{Deserialize, Serialize};
+
+/// The algorithm behind every rng used to generate the city
+///
+/// A seed only reproduces the same city when it's used with the same kind of rng.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum RngKind {
+    /// The fastest, but rand doesn't guarantee its output across versions or platforms, so a
+    /// seed can give a different city after updating rand
+    #[default]
+    Small,
+    /// ChaCha8, a bit slower but its output is portable and stable across rand versions, use
+    /// it when a seed needs to give the same city everywhere, like when sharing it
+    ChaCha,
+}
+
+impl FromStr for RngKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "small" => Ok(RngKind::Small),
+            "chacha" => Ok(RngKind::ChaCha),
+            _ => Err(format!("unknown rng `{s}`, expected `small` or `chacha`")),
+        }
+    }
+}
+
+/// An rng of the [`RngKind`] picked in the config
+///
+/// This is an enum instead of a `Box<dyn Rng>` so the spawn functions stay generic over a
+/// concrete rng without any dynamic dispatch.
+pub enum CityRng {
+    Small(SmallRng),
+    ChaCha(Box<ChaCha8Rng>),
+}
+
+pub fn make_rng(seed: u64, kind: RngKind) -> CityRng {
+    match kind {
+        RngKind::Small => CityRng::Small(SmallRng::seed_from_u64(seed)),
+        RngKind::ChaCha => CityRng::ChaCha(Box::new(ChaCha8Rng::seed_from_u64(seed))),
+    }
+}
+
+impl TryRng for CityRng {
+    type Error = Infallible;
+
+    fn try_next_u32(&mut self) -> Result<u32, Infallible> {
+        Ok(match self {
+            CityRng::Small(rng) => rng.next_u32(),
+            CityRng::ChaCha(rng) => rng.next_u32(),
+        })
+    }
+
+    fn try_next_u64(&mut self) -> Result<u64, Infallible> {
+        Ok(match self {
+            CityRng::Small(rng) => rng.next_u64(),
+            CityRng::ChaCha(rng) => rng.next_u64(),
+        })
+    }
+
+    fn try_fill_bytes(&mut self, dst: &mut [u8]) -> Result<(), Infallible> {
+        match self {
+            CityRng::Small(rng) => rng.fill_bytes(dst),
+            CityRng::ChaCha(rng) => rng.fill_bytes(dst),
+        }
+        Ok(())
+    }
+}
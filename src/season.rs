@@ -0,0 +1,190 @@
+use bevy::{prelude::*, scene::SceneInstanceReady, ui::Checked, ui_widgets::ValueChange};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::CityAssets,
+    generate_city::{CityConfig, TreeMarker},
+};
+
+/// The season the city is in, it recolors the leaves of the trees and the grass
+#[derive(Component, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Season {
+    #[default]
+    Summer,
+    Autumn,
+    /// The trees lost their leaves and the grass is dry
+    LateAutumn,
+    /// Snow covers the trees and the grass
+    Winter,
+}
+
+impl Season {
+    pub const ALL: [Season; 4] = [
+        Season::Summer,
+        Season::Autumn,
+        Season::LateAutumn,
+        Season::Winter,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Season::Summer => "Summer",
+            Season::Autumn => "Autumn",
+            Season::LateAutumn => "Late Autumn",
+            Season::Winter => "Winter",
+        }
+    }
+
+    /// Position of the season in [`Season::ALL`]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn grass_color(self) -> Color {
+        match self {
+            Season::Summer => Color::srgb_u8(97, 203, 139),
+            Season::Autumn => Color::srgb_u8(150, 180, 100),
+            Season::LateAutumn => Color::srgb_u8(160, 150, 100),
+            Season::Winter => Color::srgb_u8(235, 240, 245),
+        }
+    }
+
+    /// Recolors a pixel of the colormap of the trees
+    ///
+    /// The leaves are the green parts of the colormap, the rest like the trunk keeps its color.
+    /// The green channel is kept as the brightness so the shading painted in the colormap stays.
+    fn tree_color(self, color: Srgba) -> Srgba {
+        if color.green <= color.red {
+            return color;
+        }
+        let shade = color.green;
+        match self {
+            Season::Summer => color,
+            Season::Autumn => Srgba::new(shade * 1.2, shade * 0.6, shade * 0.2, color.alpha),
+            // The branches are painted like the trunk
+            Season::LateAutumn => Srgba::new(shade * 0.8, shade * 0.6, shade * 0.5, color.alpha),
+            Season::Winter => {
+                let snow = 0.75 + 0.25 * shade;
+                Srgba::new(snow, snow, snow * 1.05, color.alpha)
+            }
+        }
+    }
+}
+
+/// Fills the colormap of the trees of each season once the colormap of the kit is loaded
+///
+/// The summer trees use the colormap as is.
+pub fn build_season_textures(
+    mut events: MessageReader<AssetEvent<Image>>,
+    assets: Option<Res<CityAssets>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(assets) = assets else {
+        return;
+    };
+    for event in events.read() {
+        let AssetEvent::Added { id } = event else {
+            continue;
+        };
+        if *id != assets.tree_textures[Season::Summer.index()].id() {
+            continue;
+        }
+        let Some(colormap) = images.get(*id).cloned() else {
+            continue;
+        };
+        let size = colormap.size();
+        for season in Season::ALL {
+            if season == Season::Summer {
+                continue;
+            }
+            let mut texture = colormap.clone();
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    let Ok(color) = colormap.get_color_at(x, y) else {
+                        continue;
+                    };
+                    let color = season.tree_color(color.to_srgba());
+                    if let Err(err) = texture.set_color_at(x, y, color.into()) {
+                        warn!("Failed to recolor the trees for {}: {err}", season.label());
+                        break;
+                    }
+                }
+            }
+            if let Err(err) = images.insert(&assets.tree_textures[season.index()], texture) {
+                warn!(
+                    "Failed to insert the {} tree texture: {err}",
+                    season.label()
+                );
+            }
+        }
+    }
+}
+
+/// Gives the trees the material of the current season once their scene is spawned
+pub fn apply_season_to_tree(
+    ready: On<SceneInstanceReady>,
+    trees: Query<(), With<TreeMarker>>,
+    config: Res<CityConfig>,
+    assets: Res<CityAssets>,
+    children: Query<&Children>,
+    mut mesh_materials: Query<&mut MeshMaterial3d<StandardMaterial>>,
+) {
+    if !trees.contains(ready.entity) {
+        return;
+    }
+    let material = &assets.tree_materials[config.season.index()];
+    for entity in children.iter_descendants(ready.entity) {
+        if let Ok(mut mesh_material) = mesh_materials.get_mut(entity) {
+            mesh_material.0 = material.clone();
+        }
+    }
+}
+
+/// Swaps the material of the trees and recolors the grass when the season changes
+pub fn apply_season(
+    config: Res<CityConfig>,
+    assets: Option<Res<CityAssets>>,
+    mut applied: Local<Option<Season>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    trees: Query<Entity, With<TreeMarker>>,
+    children: Query<&Children>,
+    mut mesh_materials: Query<&mut MeshMaterial3d<StandardMaterial>>,
+) {
+    let Some(assets) = assets else {
+        return;
+    };
+    if *applied == Some(config.season) {
+        return;
+    }
+    *applied = Some(config.season);
+
+    let grass = StandardMaterial::from_color(config.season.grass_color());
+    if let Err(err) = materials.insert(&assets.ground_tiles.grass_material, grass) {
+        warn!("Failed to recolor the grass: {err}");
+    }
+    let material = &assets.tree_materials[config.season.index()];
+    for tree in &trees {
+        for entity in children.iter_descendants(tree) {
+            if let Ok(mut mesh_material) = mesh_materials.get_mut(entity) {
+                mesh_material.0 = material.clone();
+            }
+        }
+    }
+}
+
+/// Changes the season when one is picked in the settings
+pub fn select_season(
+    change: On<ValueChange<Entity>>,
+    seasons: Query<(Entity, &Season)>,
+    mut commands: Commands,
+    mut config: ResMut<CityConfig>,
+) {
+    for (radio, season) in &seasons {
+        if radio != change.value {
+            commands.entity(radio).remove::<Checked>();
+            continue;
+        }
+        commands.entity(radio).insert(Checked);
+        config.season = *season;
+    }
+}
@@ -0,0 +1,296 @@
+use bevy::{
+    camera::{Exposure, Hdr, RenderTarget},
+    diagnostic::FrameCount,
+    feathers::{
+        controls::{ButtonProps, button},
+        theme::ThemedText,
+    },
+    prelude::*,
+    ui_widgets::{Activate, observe},
+};
+use rand::RngExt;
+
+use crate::{
+    city::{CityGenerated, RegenerateCity},
+    generate_city::{CityBounds, CityConfig},
+    offscreen_render::{SETTLE_FRAMES, new_render_target},
+    settings::Settings,
+};
+
+/// Number of seeds previewed at once
+const THUMBNAIL_COUNT: usize = 6;
+/// Number of columns of the grid of thumbnails
+const THUMBNAIL_COLUMNS: u16 = 3;
+/// Size of the thumbnails, in pixels
+const THUMBNAIL_SIZE: UVec2 = UVec2::new(320, 180);
+/// Size of the cities generated for the thumbnails, smaller than the default city so they are
+/// quick to generate
+const THUMBNAIL_CITY_SIZE: u32 = 12;
+
+/// Previews a few random seeds side by side, clicking one of them generates the full city with
+/// that seed
+///
+/// The thumbnails are rendered one after the other by regenerating a small city with each seed
+/// and rendering it to an image, the same way `--render` does.
+pub struct SeedBrowserPlugin;
+
+impl Plugin for SeedBrowserPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(open_seed_browser)
+            .add_observer(wait_for_thumbnail)
+            .add_systems(Update, render_thumbnails);
+    }
+}
+
+/// Opens the seed browser with new random seeds
+#[derive(Event)]
+pub struct BrowseSeeds;
+
+#[derive(Resource)]
+struct SeedBrowser {
+    seeds: Vec<u64>,
+    thumbnails: Vec<Handle<Image>>,
+    /// Index of the seed whose thumbnail is being rendered, the number of seeds once they are
+    /// all rendered
+    current: usize,
+    /// The frame the city of the current seed was generated on
+    generated_frame: Option<u32>,
+    /// The seed and size of the city before browsing, the size is restored when picking a seed
+    /// and both are restored when closing the browser
+    seed: u64,
+    size: u32,
+}
+
+/// The overlay showing the thumbnails
+#[derive(Component)]
+struct SeedBrowserUi;
+
+/// The camera rendering the city to the thumbnail of the current seed
+#[derive(Component)]
+struct ThumbnailCamera;
+
+/// A thumbnail of the browser, the index of its seed
+#[derive(Component)]
+struct SeedThumbnail(usize);
+
+fn open_seed_browser(
+    _: On<BrowseSeeds>,
+    mut commands: Commands,
+    browser: Option<Res<SeedBrowser>>,
+    settings: Res<Settings>,
+    mut config: ResMut<CityConfig>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if browser.is_some() {
+        return;
+    }
+    let mut rng = rand::rng();
+    let seeds = (0..THUMBNAIL_COUNT)
+        .map(|_| rng.random::<u64>())
+        .collect::<Vec<_>>();
+    let thumbnails = seeds
+        .iter()
+        .map(|_| new_render_target(&mut images, THUMBNAIL_SIZE))
+        .collect::<Vec<_>>();
+
+    commands.spawn((
+        Camera3d::default(),
+        Hdr,
+        // Rendered before the main camera, the order only matters between cameras sharing a
+        // target
+        Camera {
+            order: -1,
+            ..default()
+        },
+        RenderTarget::Image(thumbnails[0].clone().into()),
+        Exposure {
+            ev100: settings.exposure_ev100,
+        },
+        ThumbnailCamera,
+    ));
+    spawn_seed_browser_ui(&mut commands, &seeds, &thumbnails);
+
+    commands.insert_resource(SeedBrowser {
+        seeds: seeds.clone(),
+        thumbnails,
+        current: 0,
+        generated_frame: None,
+        seed: config.seed,
+        size: config.size,
+    });
+    config.seed = seeds[0];
+    config.size = THUMBNAIL_CITY_SIZE;
+    commands.trigger(RegenerateCity);
+}
+
+fn spawn_seed_browser_ui(commands: &mut Commands, seeds: &[u64], thumbnails: &[Handle<Image>]) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: percent(100),
+                height: percent(100),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(10.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.85)),
+            SeedBrowserUi,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    display: Display::Grid,
+                    grid_template_columns: RepeatedGridTrack::auto(THUMBNAIL_COLUMNS),
+                    row_gap: Val::Px(10.0),
+                    column_gap: Val::Px(10.0),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (index, (seed, thumbnail)) in seeds.iter().zip(thumbnails).enumerate() {
+                        parent
+                            .spawn((
+                                Node {
+                                    flex_direction: FlexDirection::Column,
+                                    row_gap: Val::Px(4.0),
+                                    ..default()
+                                },
+                                SeedThumbnail(index),
+                                children![
+                                    (
+                                        ImageNode::new(thumbnail.clone()),
+                                        Node {
+                                            width: Val::Px(THUMBNAIL_SIZE.x as f32),
+                                            height: Val::Px(THUMBNAIL_SIZE.y as f32),
+                                            ..default()
+                                        },
+                                        Pickable::IGNORE,
+                                    ),
+                                    (
+                                        Text::new(format!("Seed {seed}")),
+                                        TextFont {
+                                            font_size: FontSize::Px(14.0),
+                                            ..default()
+                                        },
+                                        TextColor(Color::WHITE),
+                                        Pickable::IGNORE,
+                                    ),
+                                ],
+                            ))
+                            .observe(pick_seed);
+                    }
+                });
+            parent.spawn((
+                button(
+                    ButtonProps::default(),
+                    (),
+                    Spawn((Text::new("Close"), ThemedText)),
+                ),
+                observe(
+                    |_activate: On<Activate>,
+                     mut commands: Commands,
+                     browser: Res<SeedBrowser>,
+                     mut config: ResMut<CityConfig>,
+                     entities: Query<Entity, BrowserEntity>| {
+                        config.seed = browser.seed;
+                        close_seed_browser(&mut commands, &browser, &mut config, &entities);
+                    },
+                ),
+            ));
+        });
+}
+
+/// Filter for the entities spawned by the seed browser
+type BrowserEntity = Or<(With<SeedBrowserUi>, With<ThumbnailCamera>)>;
+
+/// Generates the full city with the seed of the clicked thumbnail
+fn pick_seed(
+    click: On<Pointer<Click>>,
+    mut commands: Commands,
+    browser: Res<SeedBrowser>,
+    mut config: ResMut<CityConfig>,
+    thumbnails: Query<&SeedThumbnail>,
+    entities: Query<Entity, BrowserEntity>,
+) {
+    let Ok(SeedThumbnail(index)) = thumbnails.get(click.entity) else {
+        return;
+    };
+    config.seed = browser.seeds[*index];
+    info!("Picked the seed {}", config.seed);
+    close_seed_browser(&mut commands, &browser, &mut config, &entities);
+}
+
+/// Closes the browser and regenerates the city at its size from before browsing
+fn close_seed_browser(
+    commands: &mut Commands,
+    browser: &SeedBrowser,
+    config: &mut CityConfig,
+    entities: &Query<Entity, BrowserEntity>,
+) {
+    for entity in entities {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<SeedBrowser>();
+    config.size = browser.size;
+    commands.trigger(RegenerateCity);
+}
+
+fn wait_for_thumbnail(
+    _: On<CityGenerated>,
+    browser: Option<ResMut<SeedBrowser>>,
+    frames: Res<FrameCount>,
+) {
+    if let Some(mut browser) = browser
+        && browser.generated_frame.is_none()
+    {
+        browser.generated_frame = Some(frames.0);
+    }
+}
+
+/// The thumbnail camera along with the image it renders to
+type ThumbnailCameraData = (
+    &'static mut Camera,
+    &'static mut RenderTarget,
+    &'static mut Transform,
+);
+
+/// Frames the city with the thumbnail camera and moves on to the next seed once the city of the
+/// current one had time to settle
+///
+/// The camera stops rendering to a thumbnail once it moves on to the next one, the image keeps
+/// the last frame rendered to it.
+fn render_thumbnails(
+    mut commands: Commands,
+    browser: Option<ResMut<SeedBrowser>>,
+    mut config: ResMut<CityConfig>,
+    bounds: Res<CityBounds>,
+    frames: Res<FrameCount>,
+    camera: Option<Single<ThumbnailCameraData, With<ThumbnailCamera>>>,
+) {
+    let (Some(mut browser), Some(camera)) = (browser, camera) else {
+        return;
+    };
+    let (mut camera, mut target, mut transform) = camera.into_inner();
+    let center = bounds.center();
+    *transform = Transform::from_translation(center + Vec3::new(0.0, 0.8, 1.0) * bounds.radius())
+        .looking_at(center, Vec3::Y);
+
+    let Some(generated_frame) = browser.generated_frame else {
+        return;
+    };
+    if frames.0 < generated_frame + SETTLE_FRAMES {
+        return;
+    }
+    browser.current += 1;
+    browser.generated_frame = None;
+    let Some(seed) = browser.seeds.get(browser.current).copied() else {
+        // Every thumbnail is rendered, the last city stays until a seed is picked
+        camera.is_active = false;
+        return;
+    };
+    *target = RenderTarget::Image(browser.thumbnails[browser.current].clone().into());
+    config.seed = seed;
+    commands.trigger(RegenerateCity);
+}
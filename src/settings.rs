@@ -1,148 +1,1263 @@
+use std::{ops::RangeInclusive, path::PathBuf};
+
 use bevy::{
-    camera_controller::free_camera::FreeCameraState,
+    camera::Exposure,
+    camera_controller::free_camera::FreeCamera,
+    core_pipeline::prepass::DeferredPrepass,
+    ecs::system::IntoObserverSystem,
     feathers::{
         self,
-        controls::{button, checkbox, ButtonProps},
+        controls::{button, checkbox, radio, slider, ButtonProps, SliderProps},
         theme::{ThemeBackgroundColor, ThemedText},
     },
-    pbr::wireframe::WireframeConfig,
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    light::{atmosphere::ScatteringMedium, CascadeShadowConfig, CascadeShadowConfigBuilder},
+    pbr::{
+        wireframe::{Wireframe, WireframeConfig},
+        AtmosphereSettings, ScreenSpaceReflections,
+    },
+    post_process::bloom::Bloom,
     prelude::*,
     ui::Checked,
-    ui_widgets::{checkbox_self_update, observe, Activate, ValueChange},
+    ui_widgets::{
+        checkbox_self_update, observe, slider_self_update, Activate, CoreSliderDragState,
+        RadioGroup, SetChecked, SliderPrecision, ValueChange,
+    },
 };
 use rand::RngExt;
+use serde::{Deserialize, Serialize};
+
+use crate::adaptive_detail::{DetailReduction, HIDE_DISTANT_BUILDINGS_LEVEL, HIDE_PROPS_LEVEL};
+use crate::atmosphere::{apply_atmosphere, select_atmosphere_preset, AtmospherePreset};
+use crate::bookmarks::BookmarksText;
+use crate::bus::Bus;
+use crate::camera::{start_camera_tour, CameraMode, CameraSpeedText};
+use crate::city::RegenerateCity;
+use crate::export::{export_city, ExportedMesh};
+use crate::generate_city::{
+    BuildingLod, BuildingMarker, CityBlock, CityConfig, FenceMarker, ParkedCarMarker, TreeMarker,
+};
+use crate::layout::{save_layout, CityLayout};
+use crate::profiles::{self, select_profile, ProfileRadio};
+use crate::season::{select_season, Season};
+use crate::seed_browser::BrowseSeeds;
+use crate::time_of_day::TimeOfDay;
+use crate::traffic::Car;
+use crate::weather::{select_weather, weather_fog_density, Weather};
+
+/// Where the settings are saved so they survive restarts
+const SETTINGS_FILE_NAME: &str = "settings.ron";
 
-use crate::assets::CityAssets;
-use crate::generate_city::{spawn_city, CityRoot};
+// Ranges of the sliders, they are also written to the schema of the config
+pub const SHADOW_DISTANCE_RANGE: RangeInclusive<f32> = 20.0..=500.0;
+pub const SHADOW_CASCADES_RANGE: RangeInclusive<f32> = 1.0..=4.0;
+pub const FOG_DENSITY_RANGE: RangeInclusive<f32> = 1.0..=50.0;
+pub const BLOOM_INTENSITY_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+pub const EXPOSURE_RANGE: RangeInclusive<f32> = 8.0..=16.0;
+pub const TIME_SCALE_RANGE: RangeInclusive<f32> = 0.0..=4.0;
+pub const CAR_SPEED_RANGE: RangeInclusive<f32> = 0.0..=3.0;
+pub const CAR_DENSITY_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+pub const WIND_STRENGTH_RANGE: RangeInclusive<f32> = 0.0..=0.1;
+pub const OCTAVES_RANGE: RangeInclusive<f32> = 1.0..=8.0;
+pub const LACUNARITY_RANGE: RangeInclusive<f32> = 1.0..=4.0;
+pub const PERSISTENCE_RANGE: RangeInclusive<f32> = 0.1..=1.0;
+pub const NOISE_SCALE_RANGE: RangeInclusive<f32> = 0.005..=0.1;
+pub const TERRAIN_HEIGHT_RANGE: RangeInclusive<f32> = 0.0..=2.0;
 
-#[derive(Resource)]
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     pub simulate_cars: bool,
+    pub simulate_pedestrians: bool,
     pub shadow_maps_enabled: bool,
     pub contact_shadows_enabled: bool,
     pub wireframe_enabled: bool,
+    /// Only draws the wireframe of the buildings, the rest of the city stays shaded
+    pub building_wireframe_enabled: bool,
+    pub show_cars: bool,
+    pub show_parked_cars: bool,
+    pub show_trees: bool,
+    pub show_fences: bool,
+    /// Chance for each spot on the roads to have a car, changing it adds or removes cars live
+    pub car_density: f32,
+    /// Multiplies the speed of every driving car
+    pub car_speed_multiplier: f32,
+    /// Speeds up or slows down the simulation of the traffic and the trees, 0 stops it
+    pub time_scale: f32,
+    /// Only the header of the settings panel is shown when collapsed
+    pub panel_collapsed: bool,
+    /// Hides the trees, fences and then the distant buildings when the frame rate drops
+    pub adaptive_detail: bool,
+    /// Distance from the camera up to which the sun casts shadows
+    pub shadow_distance: f32,
+    /// Number of shadow maps the shadow distance is split in, more cascades give sharper shadows
+    /// close to the camera
+    pub shadow_cascades: usize,
+    /// The atmosphere and its lighting are expensive, without them the sky is a plain color
+    pub atmosphere_enabled: bool,
+    pub atmosphere_preset: AtmospherePreset,
+    /// How many meters of atmosphere each unit of the scene stands for, higher values make the
+    /// distant parts of the city fade into the sky
+    pub fog_density: f32,
+    pub bloom_intensity: f32,
+    /// Screen space reflections need the deferred renderer which is expensive on some hardware
+    pub screen_space_reflections_enabled: bool,
+    pub exposure_ev100: f32,
+    /// Draws the lanes of the roads and where each car is heading, for debugging the traffic
+    pub show_traffic_gizmos: bool,
+    /// Draws the bounding boxes of the buildings, roads and cars around the camera, for
+    /// debugging the placement
+    pub show_bounding_boxes: bool,
+    /// Covers the city with the density of each block, for tuning the density thresholds
+    pub show_density_heatmap: bool,
+    /// Plays the engines of the closest cars and the hum of the city
+    pub audio_enabled: bool,
+    /// Keeps the camera in a box around the city instead of letting it fly away from it
+    pub clamp_camera: bool,
+    /// Shows a compass turning with the heading of the camera
+    pub show_compass: bool,
+    /// Draws the axes of the world at the origin
+    pub show_origin_axes: bool,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
             simulate_cars: true,
+            simulate_pedestrians: true,
             shadow_maps_enabled: true,
             contact_shadows_enabled: true,
             wireframe_enabled: false,
+            building_wireframe_enabled: false,
+            show_cars: true,
+            show_parked_cars: true,
+            show_trees: true,
+            show_fences: true,
+            car_density: 0.4,
+            car_speed_multiplier: 1.0,
+            time_scale: 1.0,
+            panel_collapsed: false,
+            adaptive_detail: false,
+            shadow_distance: 150.0,
+            shadow_cascades: 4,
+            atmosphere_enabled: true,
+            atmosphere_preset: AtmospherePreset::default(),
+            fog_density: 1.0,
+            bloom_intensity: Bloom::NATURAL.intensity,
+            screen_space_reflections_enabled: false,
+            exposure_ev100: 13.0,
+            show_traffic_gizmos: false,
+            show_bounding_boxes: false,
+            show_density_heatmap: false,
+            audio_enabled: false,
+            clamp_camera: true,
+            show_compass: true,
+            show_origin_axes: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Loads the settings saved by a previous run
+    ///
+    /// Falls back to the default settings if the file is missing or can't be parsed
+    pub fn load() -> Self {
+        let Some(path) = config_file_path(SETTINGS_FILE_NAME) else {
+            return Self::default();
+        };
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        match ron::from_str(&contents) {
+            Ok(settings) => settings,
+            Err(err) => {
+                warn!("Failed to parse {}: {err}", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = config_file_path(SETTINGS_FILE_NAME) else {
+            return;
+        };
+        let contents = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => contents,
+            Err(err) => {
+                warn!("Failed to serialize settings: {err}");
+                return;
+            }
+        };
+        if let Err(err) = std::fs::write(&path, contents) {
+            warn!("Failed to save {}: {err}", path.display());
+        }
+    }
+}
+
+/// The shadow cascades of the sun matching the [`Settings`]
+pub fn cascade_shadow_config(settings: &Settings) -> CascadeShadowConfig {
+    CascadeShadowConfigBuilder {
+        num_cascades: settings.shadow_cascades.clamp(1, 4),
+        maximum_distance: settings.shadow_distance,
+        ..default()
+    }
+    .build()
+}
+
+/// Config files are stored next to the executable
+pub fn config_file_path(file_name: &str) -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.with_file_name(file_name))
+}
+
+/// Spawns the settings panel and saves the settings whenever they change
+pub struct SettingsUiPlugin;
+
+impl Plugin for SettingsUiPlugin {
+    fn build(&self, app: &mut App) {
+        // Load the settings before the UI is spawned so the checkboxes start in the right state
+        app.insert_resource(Settings::load())
+            .init_resource::<PointerOverSettings>()
+            .add_systems(Startup, setup_settings_ui)
+            .add_systems(
+                Update,
+                (
+                    (handle_settings_hotkeys, sync_settings_checkboxes).chain(),
+                    update_settings_panel_collapsed,
+                    scroll_settings_panel,
+                    save_settings,
+                    update_category_visibility::<Car>,
+                    update_category_visibility::<Bus>,
+                    update_category_visibility::<ParkedCarMarker>,
+                    update_category_visibility::<TreeMarker>,
+                    update_category_visibility::<FenceMarker>,
+                    update_category_visibility::<BuildingLod>,
+                    update_building_wireframes,
+                ),
+            );
+    }
+}
+
+fn save_settings(settings: Res<Settings>) {
+    if settings.is_changed() && !settings.is_added() {
+        settings.save();
+    }
+}
+
+/// A category of entities that can be hidden from the settings
+trait VisibilityCategory: Component {
+    fn visible(settings: &Settings) -> bool;
+
+    /// The [`DetailReduction`] level from which the category is hidden to save frame time
+    const HIDDEN_FROM_LEVEL: u32 = u32::MAX;
+}
+
+impl VisibilityCategory for Car {
+    fn visible(settings: &Settings) -> bool {
+        settings.show_cars
+    }
+}
+
+impl VisibilityCategory for Bus {
+    fn visible(settings: &Settings) -> bool {
+        settings.show_cars
+    }
+}
+
+impl VisibilityCategory for ParkedCarMarker {
+    fn visible(settings: &Settings) -> bool {
+        settings.show_parked_cars
+    }
+}
+
+impl VisibilityCategory for TreeMarker {
+    const HIDDEN_FROM_LEVEL: u32 = HIDE_PROPS_LEVEL;
+
+    fn visible(settings: &Settings) -> bool {
+        settings.show_trees
+    }
+}
+
+impl VisibilityCategory for FenceMarker {
+    const HIDDEN_FROM_LEVEL: u32 = HIDE_PROPS_LEVEL;
+
+    fn visible(settings: &Settings) -> bool {
+        settings.show_fences
+    }
+}
+
+impl VisibilityCategory for BuildingLod {
+    const HIDDEN_FROM_LEVEL: u32 = HIDE_DISTANT_BUILDINGS_LEVEL;
+
+    fn visible(_settings: &Settings) -> bool {
+        true
+    }
+}
+
+/// Shows or hides every entity of the category when its setting or the detail level changes
+///
+/// Entities spawned after the setting was changed, like when regenerating the city, are also
+/// hidden if needed.
+fn update_category_visibility<T: VisibilityCategory>(
+    settings: Res<Settings>,
+    reduction: Res<DetailReduction>,
+    mut entities: Query<(Ref<T>, &mut Visibility)>,
+) {
+    let visibility = if T::visible(&settings) && reduction.level < T::HIDDEN_FROM_LEVEL {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    for (marker, mut entity_visibility) in &mut entities {
+        if settings.is_changed() || reduction.is_changed() || marker.is_added() {
+            entity_visibility.set_if_neq(visibility);
+        }
+    }
+}
+
+/// Adds or removes the wireframe of every building when the setting changes
+fn update_building_wireframes(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    buildings: Query<(Entity, Ref<BuildingMarker>, Has<Wireframe>)>,
+) {
+    for (entity, marker, has_wireframe) in &buildings {
+        if !settings.is_changed() && !marker.is_added() {
+            continue;
+        }
+        if settings.building_wireframe_enabled && !has_wireframe {
+            commands.entity(entity).insert(Wireframe);
+        } else if !settings.building_wireframe_enabled && has_wireframe {
+            commands.entity(entity).remove::<Wireframe>();
+        }
+    }
+}
+
+/// Whether the pointer is over the settings panel, the camera ignores inputs while it is
+#[derive(Resource, Default)]
+pub struct PointerOverSettings(pub bool);
+
+/// The [`Settings`] field a checkbox is bound to
+#[derive(Component, Clone, Copy)]
+pub enum SettingsCheckbox {
+    SimulateCars,
+    SimulatePedestrians,
+    ShadowMaps,
+    ContactShadows,
+    Wireframe,
+    BuildingWireframe,
+    ShowCars,
+    ShowParkedCars,
+    ShowTrees,
+    ShowFences,
+    AdaptiveDetail,
+    ScreenSpaceReflections,
+    Atmosphere,
+    TrafficGizmos,
+    BoundingBoxes,
+    DensityHeatmap,
+    Audio,
+    ClampCamera,
+    Compass,
+    OriginAxes,
+}
+
+impl SettingsCheckbox {
+    pub fn value(self, settings: &Settings) -> bool {
+        match self {
+            SettingsCheckbox::SimulateCars => settings.simulate_cars,
+            SettingsCheckbox::SimulatePedestrians => settings.simulate_pedestrians,
+            SettingsCheckbox::ShadowMaps => settings.shadow_maps_enabled,
+            SettingsCheckbox::ContactShadows => settings.contact_shadows_enabled,
+            SettingsCheckbox::Wireframe => settings.wireframe_enabled,
+            SettingsCheckbox::BuildingWireframe => settings.building_wireframe_enabled,
+            SettingsCheckbox::ShowCars => settings.show_cars,
+            SettingsCheckbox::ShowParkedCars => settings.show_parked_cars,
+            SettingsCheckbox::ShowTrees => settings.show_trees,
+            SettingsCheckbox::ShowFences => settings.show_fences,
+            SettingsCheckbox::AdaptiveDetail => settings.adaptive_detail,
+            SettingsCheckbox::ScreenSpaceReflections => settings.screen_space_reflections_enabled,
+            SettingsCheckbox::Atmosphere => settings.atmosphere_enabled,
+            SettingsCheckbox::TrafficGizmos => settings.show_traffic_gizmos,
+            SettingsCheckbox::BoundingBoxes => settings.show_bounding_boxes,
+            SettingsCheckbox::DensityHeatmap => settings.show_density_heatmap,
+            SettingsCheckbox::Audio => settings.audio_enabled,
+            SettingsCheckbox::ClampCamera => settings.clamp_camera,
+            SettingsCheckbox::Compass => settings.show_compass,
+            SettingsCheckbox::OriginAxes => settings.show_origin_axes,
+        }
+    }
+
+    pub fn set(self, settings: &mut Settings, value: bool) {
+        let field = match self {
+            SettingsCheckbox::SimulateCars => &mut settings.simulate_cars,
+            SettingsCheckbox::SimulatePedestrians => &mut settings.simulate_pedestrians,
+            SettingsCheckbox::ShadowMaps => &mut settings.shadow_maps_enabled,
+            SettingsCheckbox::ContactShadows => &mut settings.contact_shadows_enabled,
+            SettingsCheckbox::Wireframe => &mut settings.wireframe_enabled,
+            SettingsCheckbox::BuildingWireframe => &mut settings.building_wireframe_enabled,
+            SettingsCheckbox::ShowCars => &mut settings.show_cars,
+            SettingsCheckbox::ShowParkedCars => &mut settings.show_parked_cars,
+            SettingsCheckbox::ShowTrees => &mut settings.show_trees,
+            SettingsCheckbox::ShowFences => &mut settings.show_fences,
+            SettingsCheckbox::AdaptiveDetail => &mut settings.adaptive_detail,
+            SettingsCheckbox::ScreenSpaceReflections => {
+                &mut settings.screen_space_reflections_enabled
+            }
+            SettingsCheckbox::Atmosphere => &mut settings.atmosphere_enabled,
+            SettingsCheckbox::TrafficGizmos => &mut settings.show_traffic_gizmos,
+            SettingsCheckbox::BoundingBoxes => &mut settings.show_bounding_boxes,
+            SettingsCheckbox::DensityHeatmap => &mut settings.show_density_heatmap,
+            SettingsCheckbox::Audio => &mut settings.audio_enabled,
+            SettingsCheckbox::ClampCamera => &mut settings.clamp_camera,
+            SettingsCheckbox::Compass => &mut settings.show_compass,
+            SettingsCheckbox::OriginAxes => &mut settings.show_origin_axes,
+        };
+        *field = value;
+    }
+}
+
+/// The key toggling each setting, the keys used by the free camera should be avoided
+const SETTINGS_HOTKEYS: [(KeyCode, SettingsCheckbox); 6] = [
+    (KeyCode::KeyC, SettingsCheckbox::SimulateCars),
+    (KeyCode::KeyP, SettingsCheckbox::SimulatePedestrians),
+    (KeyCode::KeyL, SettingsCheckbox::ShadowMaps),
+    (KeyCode::KeyK, SettingsCheckbox::ContactShadows),
+    (KeyCode::KeyZ, SettingsCheckbox::Wireframe),
+    (KeyCode::KeyB, SettingsCheckbox::BuildingWireframe),
+];
+
+/// Toggles the settings bound to a key in [`SETTINGS_HOTKEYS`]
+///
+/// Only the [`Settings`] are changed, [`sync_settings_checkboxes`] then updates the checkboxes
+/// which applies the change to the scene like clicking them would.
+fn handle_settings_hotkeys(keyboard: Res<ButtonInput<KeyCode>>, mut settings: ResMut<Settings>) {
+    for (key, setting) in SETTINGS_HOTKEYS {
+        if keyboard.just_pressed(key) {
+            let value = !setting.value(&settings);
+            setting.set(&mut settings, value);
+        }
+    }
+}
+
+/// Spawns a checkbox bound to a [`Settings`] field, `on_change` is called when it's toggled
+fn settings_checkbox<M>(
+    parent: &mut ChildSpawnerCommands,
+    settings: &Settings,
+    setting: SettingsCheckbox,
+    label: &str,
+    on_change: impl IntoObserverSystem<ValueChange<bool>, (), M>,
+) {
+    let mut checkbox = parent.spawn((
+        checkbox(setting, Spawn((Text::new(label), ThemedText))),
+        observe(checkbox_self_update),
+    ));
+    checkbox.observe(on_change);
+    if setting.value(settings) {
+        checkbox.insert(Checked);
+    }
+}
+
+/// Updates the checkboxes when the [`Settings`] are changed by something other than the UI
+///
+/// This goes through [`SetChecked`] so the checkbox observers also apply the change to the scene
+fn sync_settings_checkboxes(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    checkboxes: Query<(Entity, &SettingsCheckbox, Has<Checked>)>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (entity, setting, checked) in &checkboxes {
+        let value = setting.value(&settings);
+        if value != checked {
+            commands.trigger(SetChecked {
+                entity,
+                checked: value,
+            });
         }
     }
 }
 
-pub fn setup_settings_ui(mut commands: Commands) {
-    commands.spawn((
-        Node {
-            position_type: PositionType::Absolute,
-            top: Val::Px(10.0),
-            right: Val::Px(10.0),
-            padding: UiRect::all(Val::Px(8.0)),
-            ..default()
+/// Spawns a label and a slider under it, `on_change` is called when the slider is moved
+///
+/// The slider disables the camera while it's hovered so dragging it doesn't move the camera.
+fn labeled_slider<'a, M>(
+    parent: &'a mut ChildSpawnerCommands,
+    label: &str,
+    range: RangeInclusive<f32>,
+    initial: f32,
+    precision: i32,
+    on_change: impl IntoObserverSystem<ValueChange<f32>, (), M>,
+) -> EntityCommands<'a> {
+    parent.spawn((Text::new(label), ThemedText));
+    let mut slider = parent.spawn((
+        slider(
+            SliderProps {
+                value: initial,
+                min: *range.start(),
+                max: *range.end(),
+            },
+            SliderPrecision(precision),
+        ),
+        observe(slider_self_update),
+        observe(
+            |_: On<Pointer<Over>>, mut pointer_over_settings: ResMut<PointerOverSettings>| {
+                pointer_over_settings.0 = true;
+            },
+        ),
+    ));
+    slider.observe(on_change);
+    slider
+}
+
+/// Spawns a slider controlling one of the parameters of the density noise
+///
+/// The city is regenerated once the slider is released instead of on every step of the drag.
+fn noise_slider<M>(
+    parent: &mut ChildSpawnerCommands,
+    label: &str,
+    range: RangeInclusive<f32>,
+    initial: f32,
+    precision: i32,
+    on_change: impl IntoObserverSystem<ValueChange<f32>, (), M>,
+) {
+    labeled_slider(parent, label, range, initial, precision, on_change).observe(
+        |_: On<Pointer<DragEnd>>, mut commands: Commands| {
+            commands.trigger(RegenerateCity);
         },
-        ThemeBackgroundColor(feathers::tokens::WINDOW_BG),
+    );
+}
+
+/// Regenerates the city right away for changes that don't come from dragging the slider, like
+/// using the keyboard
+fn regenerate_unless_dragging(
+    commands: &mut Commands,
+    slider: Entity,
+    drag_states: &Query<&CoreSliderDragState>,
+) {
+    if drag_states.get(slider).is_ok_and(|state| !state.dragging) {
+        commands.trigger(RegenerateCity);
+    }
+}
+
+pub fn setup_settings_ui(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    config: Res<CityConfig>,
+    time_of_day: Res<TimeOfDay>,
+) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                right: Val::Px(10.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            ThemeBackgroundColor(feathers::tokens::WINDOW_BG),
+            observe(
+                |_: On<Pointer<Over>>, mut pointer_over_settings: ResMut<PointerOverSettings>| {
+                    pointer_over_settings.0 = true;
+                },
+            ),
+            observe(
+                |_: On<Pointer<Out>>, mut pointer_over_settings: ResMut<PointerOverSettings>| {
+                    pointer_over_settings.0 = false;
+                },
+            ),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Stretch,
+                    justify_content: JustifyContent::Start,
+                    row_gap: px(8),
+                    max_height: Val::Vh(90.0),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        button(
+                            ButtonProps::default(),
+                            (),
+                            Spawn((Text::new("Settings"), ThemedText)),
+                        ),
+                        observe(|_activate: On<Activate>, mut settings: ResMut<Settings>| {
+                            settings.panel_collapsed = !settings.panel_collapsed;
+                        }),
+                    ));
+                    parent
+                        .spawn((
+                            Node {
+                                display: panel_display(&settings),
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Stretch,
+                                row_gap: px(8),
+                                overflow: Overflow::scroll_y(),
+                                ..default()
+                            },
+                            SettingsPanelContent,
+                        ))
+                        .with_children(|parent| {
+                            spawn_settings_controls(parent, &settings, &config, &time_of_day)
+                        });
+                });
+        });
+}
+
+/// Spawns the checkboxes, sliders and buttons of the settings panel
+fn spawn_settings_controls(
+    parent: &mut ChildSpawnerCommands,
+    settings: &Settings,
+    config: &CityConfig,
+    time_of_day: &TimeOfDay,
+) {
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::SimulateCars,
+        "Simulate Cars",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.simulate_cars = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::SimulatePedestrians,
+        "Simulate Pedestrians",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.simulate_pedestrians = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::ShadowMaps,
+        "Shadow maps enabled",
+        |change: On<ValueChange<bool>>,
+         mut settings: ResMut<Settings>,
+         mut directional_lights: Query<&mut DirectionalLight>| {
+            settings.shadow_maps_enabled = change.value;
+            for mut light in &mut directional_lights {
+                light.shadow_maps_enabled = change.value;
+            }
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::ContactShadows,
+        "Contact shadows enabled",
+        |change: On<ValueChange<bool>>,
+         mut settings: ResMut<Settings>,
+         mut directional_lights: Query<&mut DirectionalLight>| {
+            settings.contact_shadows_enabled = change.value;
+            for mut light in &mut directional_lights {
+                light.contact_shadows_enabled = change.value;
+            }
+        },
+    );
+    // Past a few hundred meters the shadows are too small to be noticed
+    labeled_slider(
+        parent,
+        "Shadow Distance",
+        SHADOW_DISTANCE_RANGE,
+        settings.shadow_distance,
+        0,
+        |change: On<ValueChange<f32>>,
+         mut settings: ResMut<Settings>,
+         mut cascade_configs: Query<&mut CascadeShadowConfig, With<DirectionalLight>>| {
+            settings.shadow_distance = change.value;
+            for mut cascade_config in &mut cascade_configs {
+                *cascade_config = cascade_shadow_config(&settings);
+            }
+        },
+    );
+    // Bevy supports at most 4 cascades per light
+    labeled_slider(
+        parent,
+        "Shadow Cascades",
+        SHADOW_CASCADES_RANGE,
+        settings.shadow_cascades as f32,
+        0,
+        |change: On<ValueChange<f32>>,
+         mut settings: ResMut<Settings>,
+         mut cascade_configs: Query<&mut CascadeShadowConfig, With<DirectionalLight>>| {
+            settings.shadow_cascades = change.value as usize;
+            for mut cascade_config in &mut cascade_configs {
+                *cascade_config = cascade_shadow_config(&settings);
+            }
+        },
+    );
+    labeled_slider(
+        parent,
+        "Time of Day",
+        0.0..=24.0,
+        time_of_day.hours,
+        1,
+        |change: On<ValueChange<f32>>, mut time_of_day: ResMut<TimeOfDay>| {
+            time_of_day.hours = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::Atmosphere,
+        "Atmosphere",
+        |change: On<ValueChange<bool>>,
+         mut commands: Commands,
+         mut settings: ResMut<Settings>,
+         mut scattering_mediums: ResMut<Assets<ScatteringMedium>>,
+         cameras: Query<Entity, With<FreeCamera>>| {
+            settings.atmosphere_enabled = change.value;
+            for camera in &cameras {
+                apply_atmosphere(&mut commands, camera, &settings, &mut scattering_mediums);
+            }
+        },
+    );
+    parent.spawn((Text::new("Atmosphere"), ThemedText));
+    parent
+        .spawn((
+            Node {
+                display: Display::Flex,
+                flex_direction: FlexDirection::Column,
+                row_gap: px(4),
+                ..default()
+            },
+            RadioGroup,
+            observe(select_atmosphere_preset),
+        ))
+        .with_children(|parent| {
+            for preset in AtmospherePreset::ALL {
+                let mut radio = parent.spawn((
+                    radio((), Spawn((Text::new(preset.label()), ThemedText))),
+                    preset,
+                ));
+                if preset == settings.atmosphere_preset {
+                    radio.insert(Checked);
+                }
+            }
+        });
+    parent.spawn((Text::new("Season"), ThemedText));
+    parent
+        .spawn((
+            Node {
+                display: Display::Flex,
+                flex_direction: FlexDirection::Column,
+                row_gap: px(4),
+                ..default()
+            },
+            RadioGroup,
+            observe(select_season),
+        ))
+        .with_children(|parent| {
+            for season in Season::ALL {
+                let mut radio = parent.spawn((
+                    radio((), Spawn((Text::new(season.label()), ThemedText))),
+                    season,
+                ));
+                if season == config.season {
+                    radio.insert(Checked);
+                }
+            }
+        });
+    parent.spawn((Text::new("Weather"), ThemedText));
+    parent
+        .spawn((
+            Node {
+                display: Display::Flex,
+                flex_direction: FlexDirection::Column,
+                row_gap: px(4),
+                ..default()
+            },
+            RadioGroup,
+            observe(select_weather),
+        ))
+        .with_children(|parent| {
+            for weather in Weather::ALL {
+                let mut radio = parent.spawn((
+                    radio((), Spawn((Text::new(weather.label()), ThemedText))),
+                    weather,
+                ));
+                if weather == config.weather {
+                    radio.insert(Checked);
+                }
+            }
+        });
+    labeled_slider(
+        parent,
+        "Fog Density",
+        FOG_DENSITY_RANGE,
+        settings.fog_density,
+        0,
+        |change: On<ValueChange<f32>>,
+         mut settings: ResMut<Settings>,
+         config: Res<CityConfig>,
+         mut atmosphere_settings: Query<&mut AtmosphereSettings>| {
+            settings.fog_density = change.value;
+            for mut atmosphere_settings in &mut atmosphere_settings {
+                atmosphere_settings.scene_units_to_m =
+                    weather_fog_density(&settings, config.weather);
+            }
+        },
+    );
+    // Past 1 the bloom washes out the whole image
+    labeled_slider(
+        parent,
+        "Bloom Intensity",
+        BLOOM_INTENSITY_RANGE,
+        settings.bloom_intensity,
+        2,
+        |change: On<ValueChange<f32>>,
+         mut settings: ResMut<Settings>,
+         mut blooms: Query<&mut Bloom>| {
+            settings.bloom_intensity = change.value;
+            for mut bloom in &mut blooms {
+                bloom.intensity = change.value;
+            }
+        },
+    );
+    // The directional light is raw sunlight so the exposure needs to stay high
+    labeled_slider(
+        parent,
+        "Exposure (EV100)",
+        EXPOSURE_RANGE,
+        settings.exposure_ev100,
+        1,
+        |change: On<ValueChange<f32>>,
+         mut settings: ResMut<Settings>,
+         mut exposures: Query<&mut Exposure>| {
+            settings.exposure_ev100 = change.value;
+            for mut exposure in &mut exposures {
+                exposure.ev100 = change.value;
+            }
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::ScreenSpaceReflections,
+        "Screen Space Reflections",
+        |change: On<ValueChange<bool>>,
+         mut commands: Commands,
+         mut settings: ResMut<Settings>,
+         cameras: Query<Entity, With<FreeCamera>>| {
+            settings.screen_space_reflections_enabled = change.value;
+            for camera in &cameras {
+                if change.value {
+                    commands
+                        .entity(camera)
+                        .insert(ScreenSpaceReflections::default());
+                } else {
+                    // The depth prepass required by the reflections is kept since TAA needs it
+                    commands
+                        .entity(camera)
+                        .remove::<(ScreenSpaceReflections, DeferredPrepass)>();
+                }
+            }
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::Wireframe,
+        "Wireframe Enabled",
+        |change: On<ValueChange<bool>>,
+         mut settings: ResMut<Settings>,
+         mut wireframe_config: ResMut<WireframeConfig>| {
+            settings.wireframe_enabled = change.value;
+            wireframe_config.global = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::BuildingWireframe,
+        "Building Wireframes",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.building_wireframe_enabled = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::ShowCars,
+        "Show Cars",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.show_cars = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::ShowParkedCars,
+        "Show Parked Cars",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.show_parked_cars = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::ShowTrees,
+        "Show Trees",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.show_trees = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::ShowFences,
+        "Show Fences",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.show_fences = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::AdaptiveDetail,
+        "Adaptive Detail",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.adaptive_detail = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::TrafficGizmos,
+        "Show Traffic Lanes",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.show_traffic_gizmos = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::BoundingBoxes,
+        "Show Bounding Boxes",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.show_bounding_boxes = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::Audio,
+        "Traffic Audio",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.audio_enabled = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::ClampCamera,
+        "Keep Camera Near the City",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.clamp_camera = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::Compass,
+        "Show Compass",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.show_compass = change.value;
+        },
+    );
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::OriginAxes,
+        "Show Origin Axes",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.show_origin_axes = change.value;
+        },
+    );
+    labeled_slider(
+        parent,
+        "Time Scale",
+        TIME_SCALE_RANGE,
+        settings.time_scale,
+        1,
+        |change: On<ValueChange<f32>>, mut settings: ResMut<Settings>| {
+            settings.time_scale = change.value;
+        },
+    );
+    labeled_slider(
+        parent,
+        "Car Speed",
+        CAR_SPEED_RANGE,
+        settings.car_speed_multiplier,
+        1,
+        |change: On<ValueChange<f32>>, mut settings: ResMut<Settings>| {
+            settings.car_speed_multiplier = change.value;
+        },
+    );
+    labeled_slider(
+        parent,
+        "Car Density",
+        CAR_DENSITY_RANGE,
+        settings.car_density,
+        2,
+        |change: On<ValueChange<f32>>, mut settings: ResMut<Settings>| {
+            settings.car_density = change.value;
+        },
+    );
+    labeled_slider(
+        parent,
+        "Wind",
+        WIND_STRENGTH_RANGE,
+        config.wind_strength,
+        2,
+        |change: On<ValueChange<f32>>, mut config: ResMut<CityConfig>| {
+            config.wind_strength = change.value;
+        },
+    );
+    parent.spawn((Text::new("Camera Speed"), ThemedText, CameraSpeedText));
+    parent.spawn((Text::new("Bookmarks"), ThemedText, BookmarksText));
+    parent.spawn((
+        button(
+            ButtonProps::default(),
+            (),
+            Spawn((Text::new("Toggle Orbit Camera"), ThemedText)),
+        ),
         observe(
-            |_: On<Pointer<Over>>, mut free_camera_state: Single<&mut FreeCameraState>| {
-                free_camera_state.enabled = false;
+            |_activate: On<Activate>, mut camera_mode: ResMut<CameraMode>| {
+                *camera_mode = match *camera_mode {
+                    CameraMode::Free => CameraMode::Orbit,
+                    CameraMode::Orbit | CameraMode::Tour => CameraMode::Free,
+                };
             },
         ),
+    ));
+    parent.spawn((
+        button(
+            ButtonProps::default(),
+            (),
+            Spawn((Text::new("Start Tour"), ThemedText)),
+        ),
+        observe(start_camera_tour),
+    ));
+    parent.spawn((
+        button(
+            ButtonProps::default(),
+            (),
+            Spawn((Text::new("Export City"), ThemedText)),
+        ),
         observe(
-            |_: On<Pointer<Out>>, mut free_camera_state: Single<&mut FreeCameraState>| {
-                free_camera_state.enabled = true;
+            |_activate: On<Activate>,
+             config: Res<CityConfig>,
+             entities: Query<ExportedMesh, Without<BuildingLod>>,
+             meshes: Res<Assets<Mesh>>,
+             materials: Res<Assets<StandardMaterial>>,
+             images: Res<Assets<Image>>| {
+                match export_city(config.seed, entities.iter(), &meshes, &materials, &images) {
+                    Ok(path) => info!("City exported to {}", path.display()),
+                    Err(err) => error!("Failed to export the city: {err}"),
+                }
             },
         ),
-        children![(
+    ));
+    parent.spawn((Text::new("Profile"), ThemedText));
+    parent
+        .spawn((
             Node {
                 display: Display::Flex,
                 flex_direction: FlexDirection::Column,
-                align_items: AlignItems::Stretch,
-                justify_content: JustifyContent::Start,
-                row_gap: px(8),
+                row_gap: px(4),
                 ..default()
             },
-            children![
-                (Text("Settings".to_owned())),
-                (
-                    checkbox(Checked, Spawn((Text::new("Simulate Cars"), ThemedText))),
-                    observe(checkbox_self_update),
-                    observe(
-                        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
-                            settings.simulate_cars = change.value;
-                        }
-                    )
-                ),
-                (
-                    checkbox(
-                        Checked,
-                        Spawn((Text::new("Shadow maps enabled"), ThemedText))
-                    ),
-                    observe(checkbox_self_update),
-                    observe(
-                        |change: On<ValueChange<bool>>,
-                         mut settings: ResMut<Settings>,
-                         mut directional_lights: Query<&mut DirectionalLight>| {
-                            settings.shadow_maps_enabled = change.value;
-                            for mut light in &mut directional_lights {
-                                light.shadow_maps_enabled = change.value;
-
-                            }
-                        }
-                    )
-                ),
-                (
-                    checkbox(
-                        Checked,
-                        Spawn((Text::new("Contact shadows enabled"), ThemedText))
-                    ),
-                    observe(checkbox_self_update),
-                    observe(
-                        |change: On<ValueChange<bool>>,
-                         mut settings: ResMut<Settings>,
-                         mut directional_lights: Query<&mut DirectionalLight>| {
-                            settings.contact_shadows_enabled = change.value;
-                            for mut light in &mut directional_lights {
-                                light.contact_shadows_enabled = change.value;
-
-                            }
-                        }
-                    )
-                ),
-                (
-                    checkbox((), Spawn((Text::new("Wireframe Enabled"), ThemedText))),
-                    observe(checkbox_self_update),
-                    observe(
-                        |change: On<ValueChange<bool>>,
-                         mut settings: ResMut<Settings>,
-                         mut wireframe_config: ResMut<WireframeConfig>| {
-                            settings.wireframe_enabled = change.value;
-                            wireframe_config.global = change.value;
-                        }
-                    )
-                ),
-                (
-                    button(
-                        ButtonProps::default(),
-                        (),
-                        Spawn((Text::new("Regenerate City"), ThemedText))
-                    ),
-                    observe(
-                        |_activate: On<Activate>,
-                         mut commands: Commands,
-                         city_root: Single<Entity, With<CityRoot>>,
-                         assets: Res<CityAssets>| {
-                            commands.entity(*city_root).despawn();
-
-                            let mut rng = rand::rng();
-                            let seed = rng.random::<u64>();
-                            println!("new seed: {seed}");
-                            spawn_city(&mut commands, &assets, seed, 32);
-                        }
-                    )
-                ),
-            ]
-        )],
+            RadioGroup,
+            observe(select_profile),
+        ))
+        .with_children(|parent| {
+            for profile in profiles::ALL {
+                let mut radio = parent.spawn((
+                    radio((), Spawn((Text::new(profile.name), ThemedText))),
+                    ProfileRadio(profile),
+                ));
+                if profile.matches(config) {
+                    radio.insert(Checked);
+                }
+            }
+        });
+    parent.spawn(Text::new("Density Noise"));
+    settings_checkbox(
+        parent,
+        settings,
+        SettingsCheckbox::DensityHeatmap,
+        "Density Heatmap",
+        |change: On<ValueChange<bool>>, mut settings: ResMut<Settings>| {
+            settings.show_density_heatmap = change.value;
+        },
+    );
+    noise_slider(
+        parent,
+        "Octaves",
+        OCTAVES_RANGE,
+        config.noise.octaves as f32,
+        0,
+        |change: On<ValueChange<f32>>,
+         mut config: ResMut<CityConfig>,
+         drag_states: Query<&CoreSliderDragState>,
+         mut commands: Commands| {
+            config.noise.octaves = change.value as u32;
+            regenerate_unless_dragging(&mut commands, change.source, &drag_states);
+        },
+    );
+    noise_slider(
+        parent,
+        "Lacunarity",
+        LACUNARITY_RANGE,
+        config.noise.lacunarity as f32,
+        2,
+        |change: On<ValueChange<f32>>,
+         mut config: ResMut<CityConfig>,
+         drag_states: Query<&CoreSliderDragState>,
+         mut commands: Commands| {
+            config.noise.lacunarity = change.value as f64;
+            regenerate_unless_dragging(&mut commands, change.source, &drag_states);
+        },
+    );
+    noise_slider(
+        parent,
+        "Persistence",
+        PERSISTENCE_RANGE,
+        config.noise.persistence as f32,
+        2,
+        |change: On<ValueChange<f32>>,
+         mut config: ResMut<CityConfig>,
+         drag_states: Query<&CoreSliderDragState>,
+         mut commands: Commands| {
+            config.noise.persistence = change.value as f64;
+            regenerate_unless_dragging(&mut commands, change.source, &drag_states);
+        },
+    );
+    noise_slider(
+        parent,
+        "Scale",
+        NOISE_SCALE_RANGE,
+        config.noise.scale as f32,
+        3,
+        |change: On<ValueChange<f32>>,
+         mut config: ResMut<CityConfig>,
+         drag_states: Query<&CoreSliderDragState>,
+         mut commands: Commands| {
+            config.noise.scale = change.value as f64;
+            regenerate_unless_dragging(&mut commands, change.source, &drag_states);
+        },
+    );
+    noise_slider(
+        parent,
+        "Terrain Height",
+        TERRAIN_HEIGHT_RANGE,
+        config.terrain_amplitude,
+        1,
+        |change: On<ValueChange<f32>>,
+         mut config: ResMut<CityConfig>,
+         drag_states: Query<&CoreSliderDragState>,
+         mut commands: Commands| {
+            config.terrain_amplitude = change.value;
+            regenerate_unless_dragging(&mut commands, change.source, &drag_states);
+        },
+    );
+    parent.spawn((
+        button(
+            ButtonProps::default(),
+            (),
+            Spawn((Text::new("Save Layout"), ThemedText)),
+        ),
+        observe(
+            |_activate: On<Activate>, config: Res<CityConfig>, blocks: Query<&CityBlock>| {
+                let layout = CityLayout::from_blocks(config.seed, config.rng_kind, blocks.iter());
+                match save_layout(&layout) {
+                    Ok(path) => info!("Layout saved to {}", path.display()),
+                    Err(err) => error!("Failed to save the layout: {err}"),
+                }
+            },
+        ),
+    ));
+    parent.spawn((
+        button(
+            ButtonProps::default(),
+            (),
+            Spawn((Text::new("Regenerate City"), ThemedText)),
+        ),
+        observe(
+            |_activate: On<Activate>, mut commands: Commands, mut config: ResMut<CityConfig>| {
+                let mut rng = rand::rng();
+                config.seed = rng.random::<u64>();
+                commands.trigger(RegenerateCity);
+            },
+        ),
+    ));
+    parent.spawn((
+        button(
+            ButtonProps::default(),
+            (),
+            Spawn((Text::new("Browse Seeds"), ThemedText)),
+        ),
+        observe(|_activate: On<Activate>, mut commands: Commands| {
+            commands.trigger(BrowseSeeds);
+        }),
     ));
 }
+
+/// The part of the settings panel hidden when it's collapsed
+#[derive(Component)]
+struct SettingsPanelContent;
+
+fn panel_display(settings: &Settings) -> Display {
+    if settings.panel_collapsed {
+        Display::None
+    } else {
+        Display::Flex
+    }
+}
+
+fn update_settings_panel_collapsed(
+    settings: Res<Settings>,
+    mut content: Single<&mut Node, With<SettingsPanelContent>>,
+) {
+    if settings.is_changed() {
+        let display = panel_display(&settings);
+        if content.display != display {
+            content.display = display;
+        }
+    }
+}
+
+/// How many pixels the panel scrolls by for each line of the mouse wheel
+const SCROLL_LINE_HEIGHT: f32 = 21.0;
+
+/// Scrolls the settings panel with the mouse wheel when it's taller than the window
+fn scroll_settings_panel(
+    pointer_over_settings: Res<PointerOverSettings>,
+    mut mouse_wheel: MessageReader<MouseWheel>,
+    mut content: Single<(&mut ScrollPosition, &ComputedNode), With<SettingsPanelContent>>,
+) {
+    if !pointer_over_settings.0 {
+        mouse_wheel.clear();
+        return;
+    }
+    let (scroll_position, computed) = &mut *content;
+    let max_offset =
+        (computed.content_size().y - computed.size().y) * computed.inverse_scale_factor();
+    for event in mouse_wheel.read() {
+        let delta = match event.unit {
+            MouseScrollUnit::Line => event.y * SCROLL_LINE_HEIGHT,
+            MouseScrollUnit::Pixel => event.y,
+        };
+        scroll_position.y = (scroll_position.y - delta).clamp(0.0, max_offset.max(0.0));
+    }
+}
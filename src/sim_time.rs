@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+
+use crate::settings::Settings;
+
+/// Toggles the pause of the simulation
+const PAUSE_KEY: KeyCode = KeyCode::Space;
+
+/// The clock of the simulation, the cars, buses, pedestrians and trees move with it instead of
+/// [`Time`]
+///
+/// It follows the [`Time`] of the schedule it's updated in, scaled by the time scale of the
+/// settings, and stops while paused.
+#[derive(Resource, Default)]
+pub struct SimTime {
+    delta: f32,
+    frame_delta: f32,
+    elapsed: f32,
+    pub paused: bool,
+}
+
+impl SimTime {
+    pub fn delta_secs(&self) -> f32 {
+        self.delta
+    }
+
+    /// How much the simulation advanced since the last frame, for the effects moving in `Update`
+    ///
+    /// This is the same as [`SimTime::delta_secs`] unless the traffic is deterministic, the
+    /// simulation is then updated in `FixedUpdate` where the delta is the fixed timestep.
+    pub fn frame_delta_secs(&self) -> f32 {
+        self.frame_delta
+    }
+
+    pub fn elapsed_secs(&self) -> f32 {
+        self.elapsed
+    }
+}
+
+/// Advances the [`SimTime`], this needs to run before the systems reading it
+pub fn update_sim_time(time: Res<Time>, settings: Res<Settings>, mut sim_time: ResMut<SimTime>) {
+    sim_time.delta = if sim_time.paused {
+        0.0
+    } else {
+        time.delta_secs() * settings.time_scale
+    };
+    sim_time.elapsed += sim_time.delta;
+}
+
+/// Updates [`SimTime::frame_delta_secs`] from the [`Time`] of the frame
+pub fn update_sim_frame_delta(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut sim_time: ResMut<SimTime>,
+) {
+    sim_time.frame_delta = if sim_time.paused {
+        0.0
+    } else {
+        time.delta_secs() * settings.time_scale
+    };
+}
+
+pub fn toggle_pause(keyboard: Res<ButtonInput<KeyCode>>, mut sim_time: ResMut<SimTime>) {
+    if keyboard.just_pressed(PAUSE_KEY) {
+        sim_time.paused = !sim_time.paused;
+    }
+}
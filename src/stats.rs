@@ -0,0 +1,208 @@
+use bevy::{diagnostic::DiagnosticsStore, platform::collections::HashSet, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    city::CityRegenerated,
+    generate_city::{
+        BuildingLod, BuildingMarker, CityBlock, DensityTier, IndustrialMarker, ParkedCarMarker,
+        RoadMarker, SidewalkMarker, TreeMarker,
+    },
+    traffic::{Car, Pedestrian},
+};
+
+/// Number of entities of each kind spawned by the city
+///
+/// The city is generated deterministically from its `CityConfig` so these can be compared
+/// against the counts of a previous run to catch unintended changes to the layout.
+#[derive(Resource, Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct SceneStats {
+    pub cars_spawned: u32,
+    pub parked_cars: u32,
+    pub low_density_buildings: u32,
+    pub medium_density_buildings: u32,
+    pub skyscrapers: u32,
+    pub industrial_buildings: u32,
+    pub road_segments: u32,
+    pub sidewalks: u32,
+    pub trees: u32,
+    pub pedestrians: u32,
+    pub parks: u32,
+    /// Number of distinct mesh and material pairs of the buildings and their low poly boxes
+    ///
+    /// Each pair is drawn as one batch, compared to `building_instances` this shows if tinting
+    /// or the low poly boxes split the buildings into too many draw calls.
+    pub building_batches: u32,
+    /// Number of buildings and low poly boxes making up the batches
+    pub building_instances: u32,
+}
+
+impl SceneStats {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Filter for the buildings and their low poly boxes in the city
+type BuildingMesh = (Or<(With<BuildingMarker>, With<BuildingLod>)>, With<ChildOf>);
+
+/// Filter for the entities of a kind that are part of the city, the entities waiting in the
+/// [`EntityPool`](crate::pool::EntityPool) keep their markers but have no parent
+type InCity<T> = (With<T>, With<ChildOf>);
+
+/// Counts the entities of the city from their marker components
+///
+/// This runs each time the content of the city changes instead of having every spawn function
+/// update the stats, so the stats stay correct when entities get despawned or reused. The
+/// pooled entities are left out since they're hidden until they're reused or despawned.
+#[allow(clippy::too_many_arguments)]
+pub fn recount_stats(
+    _: On<CityRegenerated>,
+    mut stats: ResMut<SceneStats>,
+    cars: Query<(), InCity<Car>>,
+    parked_cars: Query<(), InCity<ParkedCarMarker>>,
+    buildings: Query<(&BuildingMarker, Has<IndustrialMarker>), With<ChildOf>>,
+    building_meshes: Query<(&Mesh3d, &MeshMaterial3d<StandardMaterial>), BuildingMesh>,
+    road_segments: Query<(), InCity<RoadMarker>>,
+    sidewalks: Query<(), InCity<SidewalkMarker>>,
+    trees: Query<(), InCity<TreeMarker>>,
+    pedestrians: Query<(), InCity<Pedestrian>>,
+    blocks: Query<&CityBlock>,
+) {
+    stats.reset();
+    stats.cars_spawned = cars.iter().count() as u32;
+    stats.parked_cars = parked_cars.iter().count() as u32;
+    for (BuildingMarker { density }, industrial) in &buildings {
+        if industrial {
+            stats.industrial_buildings += 1;
+            continue;
+        }
+        match density {
+            DensityTier::Low => stats.low_density_buildings += 1,
+            DensityTier::Medium => stats.medium_density_buildings += 1,
+            DensityTier::High => stats.skyscrapers += 1,
+            DensityTier::Forest | DensityTier::Park | DensityTier::Water => {}
+        }
+    }
+    let mut batches = HashSet::new();
+    for (mesh, material) in &building_meshes {
+        batches.insert((mesh.id(), material.id()));
+        stats.building_instances += 1;
+    }
+    stats.building_batches = batches.len() as u32;
+    stats.road_segments = road_segments.iter().count() as u32;
+    stats.sidewalks = sidewalks.iter().count() as u32;
+    stats.trees = trees.iter().count() as u32;
+    stats.pedestrians = pedestrians.iter().count() as u32;
+    stats.parks = blocks
+        .iter()
+        .filter(|block| block.tier == DensityTier::Park && block.detail_visible)
+        .count() as u32;
+}
+
+#[derive(Component)]
+pub struct StatsText;
+
+pub fn spawn_stats_ui(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(10.0),
+                left: Val::Px(10.0),
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.75)),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: FontSize::Px(20.0),
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                StatsText,
+            ));
+        });
+}
+
+pub fn format_large_number(value: u32) -> String {
+    let mut s = String::new();
+    for (i, char) in value.to_string().chars().rev().enumerate() {
+        if i % 3 == 0 && i != 0 {
+            s.insert(0, ',');
+        }
+        s.insert(0, char);
+    }
+    s
+}
+
+/// Sums the number of triangles that made it through clipping in every render pass
+///
+/// This comes from the pipeline statistics recorded by the `RenderDiagnosticsPlugin` which are
+/// only supported on Vulkan and DX12, it returns `None` when they aren't available.
+fn rendered_triangles(diagnostics: &DiagnosticsStore) -> Option<u32> {
+    let mut triangles = None;
+    for diagnostic in diagnostics.iter() {
+        if !diagnostic
+            .path()
+            .as_str()
+            .ends_with("/clipper_primitives_out")
+        {
+            continue;
+        }
+        if let Some(value) = diagnostic.value() {
+            *triangles.get_or_insert(0.0) += value;
+        }
+    }
+    triangles.map(|triangles| triangles as u32)
+}
+
+pub fn update_stats_ui(
+    mut stats_text: Single<&mut Text, With<StatsText>>,
+    stats: Res<SceneStats>,
+    entities: Query<Entity>,
+    diagnostics: Res<DiagnosticsStore>,
+    meshes: Res<Assets<Mesh>>,
+    materials: Res<Assets<StandardMaterial>>,
+    scenes: Res<Assets<Scene>>,
+) {
+    let total_entities = entities.iter().count();
+    let triangles = rendered_triangles(&diagnostics)
+        .map(format_large_number)
+        .unwrap_or_else(|| "N/A".to_owned());
+    stats_text.0 = format!(
+        "Cars: {}\nParked Cars: {}\nLow Density: {}\nMedium Density: {}\nSkyscrapers: {}\nIndustrial: {}\nRoad Segments: {}\nSidewalks: {}\nTrees: {}\nPedestrians: {}\nParks: {}\nBuilding Batches: {} for {} instances\nTotal spawned mesh: {}\nTotal Entities: {}\nTriangles: {}\nMesh Assets: {}\nMaterial Assets: {}\nScene Assets: {}",
+        format_large_number(stats.cars_spawned),
+        format_large_number(stats.parked_cars),
+        format_large_number(stats.low_density_buildings),
+        format_large_number(stats.medium_density_buildings),
+        format_large_number(stats.skyscrapers),
+        format_large_number(stats.industrial_buildings),
+        format_large_number(stats.road_segments),
+        format_large_number(stats.sidewalks),
+        format_large_number(stats.trees),
+        format_large_number(stats.pedestrians),
+        format_large_number(stats.parks),
+        format_large_number(stats.building_batches),
+        format_large_number(stats.building_instances),
+        format_large_number(
+            stats.cars_spawned
+                + stats.parked_cars
+                + stats.low_density_buildings
+                + stats.medium_density_buildings
+                + stats.skyscrapers
+                + stats.industrial_buildings
+                + stats.road_segments
+                + stats.sidewalks
+                + stats.trees
+                + stats.pedestrians
+        ),
+        format_large_number(total_entities as u32),
+        triangles,
+        format_large_number(meshes.len() as u32),
+        format_large_number(materials.len() as u32),
+        format_large_number(scenes.len() as u32),
+    );
+}
@@ -0,0 +1,103 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::assets::BuildingMaterial;
+
+/// Fraction of the buildings with their windows lit at night
+const LIT_FRACTION: f32 = 0.6;
+
+/// Moves the sun with the [`TimeOfDay`] and lights the windows of the buildings at night
+pub struct TimeOfDayPlugin;
+
+impl Plugin for TimeOfDayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>()
+            .add_systems(Update, (update_sun, update_window_lights));
+    }
+}
+
+/// The hour of the day, in the 0..24 range
+#[derive(Resource)]
+pub struct TimeOfDay {
+    pub hours: f32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        // Early in the morning, the sun is low enough to cast long shadows over the city
+        Self { hours: 6.4 }
+    }
+}
+
+impl TimeOfDay {
+    /// Angle of the sun above the horizon, it rises at 6 and sets at 18
+    pub fn sun_elevation(&self) -> f32 {
+        (self.hours - 6.0) / 12.0 * PI
+    }
+
+    pub fn is_night(&self) -> bool {
+        !(0.0..=PI).contains(&self.sun_elevation())
+    }
+}
+
+/// The day and night materials of a building
+#[derive(Component)]
+pub struct BuildingWindows {
+    pub material: BuildingMaterial,
+    /// Whether the windows of this building are lit at night, this is decided once when the
+    /// building is spawned so the same buildings stay lit
+    pub lit_at_night: bool,
+}
+
+impl BuildingWindows {
+    pub fn new(material: BuildingMaterial, transform: &Transform) -> Self {
+        Self {
+            material,
+            lit_at_night: lit_at_night(transform.translation),
+        }
+    }
+}
+
+/// Picks the buildings with lit windows from their position so a city always has the same
+/// pattern of lit windows without drawing from the rng of the block
+fn lit_at_night(translation: Vec3) -> bool {
+    let hash = ((translation.x * 12.9898 + translation.z * 78.233).sin() * 43_758.547).fract();
+    hash.abs() < LIT_FRACTION
+}
+
+fn update_sun(
+    time_of_day: Res<TimeOfDay>,
+    mut suns: Query<&mut Transform, With<DirectionalLight>>,
+) {
+    if !time_of_day.is_changed() {
+        return;
+    }
+    let elevation = time_of_day.sun_elevation();
+    let horizontal = Vec3::new(1.0, 0.0, 1.0).normalize() * elevation.cos();
+    let direction = horizontal + Vec3::Y * elevation.sin();
+    for mut transform in &mut suns {
+        *transform = Transform::from_translation(direction).looking_at(Vec3::ZERO, Vec3::Y);
+    }
+}
+
+/// Swaps the material of the buildings with lit windows when the night starts or ends
+fn update_window_lights(
+    time_of_day: Res<TimeOfDay>,
+    mut buildings: Query<(Ref<BuildingWindows>, &mut MeshMaterial3d<StandardMaterial>)>,
+) {
+    let night = time_of_day.is_night();
+    for (windows, mut material) in &mut buildings {
+        if !time_of_day.is_changed() && !windows.is_changed() {
+            continue;
+        }
+        let target = if night && windows.lit_at_night {
+            &windows.material.lit
+        } else {
+            &windows.material.unlit
+        };
+        if material.0 != *target {
+            material.0 = target.clone();
+        }
+    }
+}
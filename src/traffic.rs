@@ -0,0 +1,590 @@
+use bevy::prelude::*;
+
+use crate::{
+    bus::Bus,
+    emergency::EmergencyVehicle,
+    generate_city::{CAR_SPACING, CityConfig},
+    settings::Settings,
+    sim_time::SimTime,
+};
+
+/// Distance from the middle of the roads to the middle of their lanes
+pub const LANE_OFFSET: f32 = 0.15;
+/// Width of a lane, the distance between the middles of two lanes side by side
+pub const LANE_WIDTH: f32 = 0.3;
+
+#[derive(Component, Clone)]
+pub struct Road {
+    pub start: Vec3,
+    pub end: Vec3,
+    /// Bends the road between `start` and `end`, the road is a straight line without it
+    pub curve: Option<RoadCurve>,
+    /// Distance from the middle of the road to the middle of the lanes closest to it
+    pub lane_offset: f32,
+    /// Distance between the middles of two lanes side by side on the same side of the road
+    pub lane_width: f32,
+    pub lanes: [CarLane; 2],
+}
+
+impl Road {
+    /// A road with a lane going in each of the directions of `dirs`, each one on the right of
+    /// the cars driving on it
+    pub fn new(start: Vec3, end: Vec3, curve: Option<RoadCurve>, dirs: [f32; 2]) -> Self {
+        let mut road = Self {
+            start,
+            end,
+            curve,
+            lane_offset: LANE_OFFSET,
+            lane_width: LANE_WIDTH,
+            lanes: dirs.map(|dir| CarLane {
+                dir,
+                offset: Vec3::ZERO,
+            }),
+        };
+        for i in 0..road.lanes.len() {
+            road.lanes[i].offset = road.lane_offset(road.lanes[i].dir, 0);
+        }
+        road
+    }
+
+    /// Sideways offset from the middle of the road of the lane `index` lanes away from the
+    /// middle, on the right of the road when going from `start` to `end` when `side` is 1 and on
+    /// its left when it's -1
+    pub fn lane_offset(&self, side: f32, index: usize) -> Vec3 {
+        let (_, direction) = self.sample(0.0);
+        let right = Vec3::new(-direction.z, 0.0, direction.x);
+        right * side * (self.lane_offset + index as f32 * self.lane_width)
+    }
+
+    /// Length of the middle of the road
+    pub fn length(&self) -> f32 {
+        match &self.curve {
+            Some(curve) => curve.length(),
+            None => (self.end - self.start).length(),
+        }
+    }
+
+    /// Number of spots along the road a car can be spawned at
+    pub fn spot_count(&self) -> usize {
+        (self.length() / CAR_SPACING).round() as usize
+    }
+
+    /// Position and direction of the middle of the road at `distance` from `start`
+    fn sample(&self, distance: f32) -> (Vec3, Vec3) {
+        match &self.curve {
+            Some(curve) => curve.sample(distance),
+            None => {
+                let direction = (self.end - self.start).normalize();
+                (self.start + direction * distance, direction)
+            }
+        }
+    }
+
+    /// Distance from `start` of a car that drove `distance` along the lane
+    ///
+    /// The distance along the lane is measured from where the cars enter the lane, `start` for
+    /// the lanes going forward and `end` for the lanes going backward, so it goes from 0 to the
+    /// length of the road for both lanes.
+    fn distance_from_start(&self, lane: CarLane, distance: f32) -> f32 {
+        if lane.dir > 0.0 {
+            distance
+        } else {
+            self.length() - distance
+        }
+    }
+
+    /// The direction the cars of the lane drive in after driving `distance` along the lane
+    pub fn lane_direction(&self, lane: CarLane, distance: f32) -> Vec3 {
+        let (_, direction) = self.sample(self.distance_from_start(lane, distance));
+        direction * lane.dir
+    }
+
+    /// Position of a car that drove `distance` along the lane
+    pub fn lane_position(&self, lane: CarLane, distance: f32) -> Vec3 {
+        let (position, direction) = self.sample(self.distance_from_start(lane, distance));
+        // The lane offset is sideways from the start of the road, it turns with the curve
+        let (_, start_direction) = self.sample(0.0);
+        let turn = yaw_rotation(direction) * yaw_rotation(start_direction).inverse();
+        position + turn * lane.offset
+    }
+}
+
+/// Number of points the length of a [`RoadCurve`] is measured at
+const CURVE_SAMPLES: usize = 64;
+
+/// A road bending along bezier curves
+///
+/// The cars drive along the curve at a constant speed so the position of a car is looked up
+/// from the length of the curve measured at evenly spaced points instead of the curve parameter.
+#[derive(Clone)]
+pub struct RoadCurve {
+    curve: CubicCurve<Vec3>,
+    /// Length of the curve from its start to each of the [`CURVE_SAMPLES`] points
+    lengths: [f32; CURVE_SAMPLES],
+}
+
+impl RoadCurve {
+    /// Chains the bezier curves, each one going from its first point to its last one, leaving
+    /// toward the second point and arriving from the third one
+    pub fn new(segments: impl IntoIterator<Item = [Vec3; 4]>) -> Self {
+        let curve = CubicCurve::from_segments(segments.into_iter().map(CubicSegment::new_bezier))
+            .expect("A road curve has at least one segment");
+        let mut lengths = [0.0; CURVE_SAMPLES];
+        let mut previous = curve.position(0.0);
+        for i in 1..CURVE_SAMPLES {
+            let position = curve.position(Self::parameter(&curve, i as f32));
+            lengths[i] = lengths[i - 1] + position.distance(previous);
+            previous = position;
+        }
+        Self { curve, lengths }
+    }
+
+    /// The curve parameter of the sample at the given, possibly fractional, index
+    fn parameter(curve: &CubicCurve<Vec3>, sample: f32) -> f32 {
+        sample / (CURVE_SAMPLES - 1) as f32 * curve.segments().len() as f32
+    }
+
+    fn length(&self) -> f32 {
+        self.lengths[CURVE_SAMPLES - 1]
+    }
+
+    /// Position and direction of the curve at `distance` from its start
+    fn sample(&self, distance: f32) -> (Vec3, Vec3) {
+        let distance = distance.clamp(0.0, self.length());
+        let i = self
+            .lengths
+            .partition_point(|length| *length < distance)
+            .clamp(1, CURVE_SAMPLES - 1);
+        let (before, after) = (self.lengths[i - 1], self.lengths[i]);
+        let fraction = (distance - before) / (after - before).max(f32::EPSILON);
+        let t = Self::parameter(&self.curve, i as f32 - 1.0 + fraction);
+        (self.curve.position(t), self.curve.velocity(t).normalize())
+    }
+}
+
+/// Rotation around the up axis turning +Z toward the direction
+fn yaw_rotation(direction: Vec3) -> Quat {
+    Quat::from_rotation_y(direction.x.atan2(direction.z))
+}
+
+/// One direction of a road, the cars drive from `Road::start` to `Road::end` when `dir` is 1 and
+/// the other way around when it's -1
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CarLane {
+    pub dir: f32,
+    /// Sideways offset from the middle of the road
+    pub offset: Vec3,
+}
+
+#[derive(Component)]
+pub struct Car {
+    pub speed: f32,
+    pub lane: CarLane,
+    /// Distance from where the car entered its lane
+    pub distance_traveled: f32,
+    /// Set while the car moves over from another lane to `lane`
+    pub lane_change: Option<LaneChange>,
+}
+
+/// A car moving over to the lane next to it, it keeps its speed and its distance along the road
+#[derive(Clone, Copy)]
+pub struct LaneChange {
+    /// Sideways offset of the lane the car is leaving
+    pub from: Vec3,
+    /// Fraction of the lane change done, from 0 to 1
+    pub progress: f32,
+}
+
+/// A car this close behind a slower car of its lane changes lanes, and this close ahead of an
+/// emergency vehicle of its lane makes way for it
+const LANE_CHANGE_GAP: f32 = 0.8;
+/// The lane a car moves to needs to be free this far ahead and behind the car
+const LANE_CHANGE_CLEARANCE: f32 = 0.5;
+/// How long it takes to move over to the next lane, in seconds
+const LANE_CHANGE_DURATION: f32 = 0.8;
+
+/// The cars along with their transform, they are children of their [`Road`]
+type CarQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Car,
+        &'static mut Transform,
+        Option<&'static mut TrafficInterpolation>,
+        Has<EmergencyVehicle>,
+    ),
+    Without<Road>,
+>;
+
+pub fn simulate_cars(
+    settings: Res<Settings>,
+    config: Res<CityConfig>,
+    roads: Query<(&Road, &Transform, &Children), Without<Car>>,
+    mut cars: CarQuery,
+    time: Res<SimTime>,
+) {
+    if !settings.simulate_cars {
+        return;
+    }
+    for (road, _, children) in &roads {
+        let emergency = children
+            .iter()
+            .any(|child| cars.get(child).is_ok_and(|(_, _, _, emergency)| emergency));
+        let stopped = if config.lane_changing || emergency {
+            change_lanes(road, children, &mut cars, config.lane_changing)
+        } else {
+            Vec::new()
+        };
+        for child in children {
+            let Ok((mut car, mut car_transform, interpolation, _)) = cars.get_mut(*child) else {
+                continue;
+            };
+
+            let speed = if stopped.contains(child) {
+                0.0
+            } else {
+                car.speed * settings.car_speed_multiplier
+            };
+            let road_len = road.length();
+            let distance_traveled =
+                advance_car(car.distance_traveled, speed, time.delta_secs(), road_len);
+            // The car went back to the start of the road
+            let teleported = distance_traveled < car.distance_traveled;
+            car.distance_traveled = distance_traveled;
+
+            let mut lane = car.lane;
+            if let Some(mut lane_change) = car.lane_change {
+                lane_change.progress += time.delta_secs() / LANE_CHANGE_DURATION;
+                car.lane_change = (lane_change.progress < 1.0).then_some(lane_change);
+                // Eased so the car swerves in and out of the lane smoothly
+                let t = lane_change.progress.clamp(0.0, 1.0);
+                lane.offset = lane_change.from.lerp(lane.offset, t * t * (3.0 - 2.0 * t));
+            }
+            let translation = road.lane_position(lane, car.distance_traveled);
+            match interpolation {
+                Some(mut interpolation) => interpolation.advance(translation, teleported),
+                None => car_transform.translation = translation,
+            }
+            if road.curve.is_some() {
+                // The car models face +Z
+                let direction = road.lane_direction(car.lane, car.distance_traveled);
+                car_transform.rotation = yaw_rotation(direction);
+            }
+        }
+    }
+}
+
+/// What the cars of a road see of each other
+#[derive(Clone, Copy)]
+struct TrafficCar {
+    entity: Entity,
+    lane: CarLane,
+    distance: f32,
+    speed: f32,
+    changing_lanes: bool,
+    emergency: bool,
+}
+
+/// What a car does to get past a slower car or to make way for an emergency vehicle
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Maneuver {
+    ChangeLanes(CarLane),
+    /// Waits for the emergency vehicle to pass, there is no free lane to move over to
+    Stop,
+}
+
+/// Moves the cars stuck close behind a slower car, when `lane_changing` is set, and the cars
+/// close ahead of an emergency vehicle to another lane going the same way
+///
+/// The cars that can't make way for an emergency vehicle stop until it passed them, their
+/// entities are returned.
+fn change_lanes(
+    road: &Road,
+    children: &Children,
+    cars: &mut CarQuery,
+    lane_changing: bool,
+) -> Vec<Entity> {
+    let mut traffic = children
+        .iter()
+        .filter_map(|child| {
+            let (car, _, _, emergency) = cars.get(child).ok()?;
+            Some(TrafficCar {
+                entity: child,
+                lane: car.lane,
+                distance: car.distance_traveled,
+                speed: car.speed,
+                changing_lanes: car.lane_change.is_some(),
+                emergency,
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut stopped = Vec::new();
+    for (entity, maneuver) in plan_maneuvers(road, &mut traffic, lane_changing) {
+        match maneuver {
+            Maneuver::ChangeLanes(target) => {
+                let Ok((mut car, _, _, _)) = cars.get_mut(entity) else {
+                    continue;
+                };
+                car.lane_change = Some(LaneChange {
+                    from: car.lane.offset,
+                    progress: 0.0,
+                });
+                car.lane = target;
+            }
+            Maneuver::Stop => stopped.push(entity),
+        }
+    }
+    stopped
+}
+
+/// Picks the [`Maneuver`] of each car of the road that needs one, the cars already changing
+/// lanes finish their lane change first
+///
+/// Only the one-way streets have several lanes going the same way, the cars ahead of an
+/// emergency vehicle on the other streets stop. The emergency vehicles themselves never make way.
+fn plan_maneuvers(
+    road: &Road,
+    traffic: &mut [TrafficCar],
+    lane_changing: bool,
+) -> Vec<(Entity, Maneuver)> {
+    let mut maneuvers = Vec::new();
+    for i in 0..traffic.len() {
+        let car = traffic[i];
+        if car.changing_lanes {
+            continue;
+        }
+        let same_lane = |other: &&TrafficCar| other.lane == car.lane;
+        let blocked = lane_changing
+            && traffic.iter().filter(same_lane).any(|other| {
+                other.speed < car.speed
+                    && other.distance > car.distance
+                    && other.distance - car.distance < LANE_CHANGE_GAP
+            });
+        let yielding = !car.emergency
+            && traffic.iter().filter(same_lane).any(|other| {
+                other.emergency
+                    && other.distance < car.distance
+                    && car.distance - other.distance < LANE_CHANGE_GAP
+            });
+        if !blocked && !yielding {
+            continue;
+        }
+        let free = |target: &CarLane| {
+            traffic.iter().all(|other| {
+                other.lane != *target
+                    || (other.distance - car.distance).abs() > LANE_CHANGE_CLEARANCE
+            })
+        };
+        match road
+            .lanes
+            .into_iter()
+            .find(|target| target.dir == car.lane.dir && *target != car.lane && free(target))
+        {
+            Some(target) => {
+                maneuvers.push((car.entity, Maneuver::ChangeLanes(target)));
+                // The next cars see this one in its new lane
+                traffic[i].lane = target;
+                traffic[i].changing_lanes = true;
+            }
+            None if yielding => maneuvers.push((car.entity, Maneuver::Stop)),
+            None => {}
+        }
+    }
+    maneuvers
+}
+
+/// Largest fraction of the road a car can advance by in a single step
+const MAX_CAR_STEP: f32 = 0.25;
+/// Largest number of steps a single delta is split in, a huge delta after a hitch would otherwise
+/// take as many steps as the number of times the car goes around its road
+const MAX_CAR_STEPS: u32 = 16;
+
+/// Moves a car along its road, going back to the start of the road when reaching its end
+///
+/// Large deltas are split in steps of at most [`MAX_CAR_STEP`] of the road so the end of the road
+/// is handled the same way no matter the frame rate. What's left past the end of the road carries
+/// over to the start, so a car ends up at the same place with one large delta as with many small
+/// ones.
+fn advance_car(mut distance_traveled: f32, speed: f32, delta: f32, road_len: f32) -> f32 {
+    let advance = speed * delta;
+    if road_len <= 0.0 {
+        return distance_traveled + advance;
+    }
+    let max_step = road_len * MAX_CAR_STEP;
+    let steps = ((advance / max_step).ceil() as u32).clamp(1, MAX_CAR_STEPS);
+    for _ in 0..steps {
+        distance_traveled += advance / steps as f32;
+        if distance_traveled > road_len {
+            distance_traveled = distance_traveled.rem_euclid(road_len);
+        }
+    }
+    distance_traveled
+}
+
+/// A path pedestrians walk back and forth on
+#[derive(Component)]
+pub struct WalkPath {
+    pub start: Vec3,
+    pub end: Vec3,
+}
+
+#[derive(Component)]
+pub struct Pedestrian {
+    pub distance_traveled: f32,
+    pub dir: f32,
+}
+
+pub fn simulate_pedestrians(
+    settings: Res<Settings>,
+    paths: Query<(&WalkPath, &Children), Without<Pedestrian>>,
+    mut pedestrians: Query<
+        (
+            &mut Pedestrian,
+            &mut Transform,
+            Option<&mut TrafficInterpolation>,
+        ),
+        Without<WalkPath>,
+    >,
+    time: Res<SimTime>,
+) {
+    if !settings.simulate_pedestrians {
+        return;
+    }
+
+    // Pedestrians are a lot slower than cars
+    let speed = 0.3;
+
+    for (path, children) in &paths {
+        let path_len = (path.end - path.start).length();
+        let direction = (path.end - path.start).normalize();
+
+        for child in children {
+            let Ok((mut pedestrian, mut transform, interpolation)) = pedestrians.get_mut(*child)
+            else {
+                continue;
+            };
+
+            pedestrian.distance_traveled += speed * pedestrian.dir * time.delta_secs();
+            // Turn around at the end of the path instead of teleporting back to the start
+            if pedestrian.distance_traveled > path_len {
+                pedestrian.distance_traveled = 2.0 * path_len - pedestrian.distance_traveled;
+                pedestrian.dir = -1.0;
+            } else if pedestrian.distance_traveled < 0.0 {
+                pedestrian.distance_traveled = -pedestrian.distance_traveled;
+                pedestrian.dir = 1.0;
+            }
+
+            let translation = path.start + direction * pedestrian.distance_traveled;
+            match interpolation {
+                Some(mut interpolation) => interpolation.advance(translation, false),
+                None => transform.translation = translation,
+            }
+            transform.look_to(direction * pedestrian.dir, Vec3::Y);
+        }
+    }
+}
+
+/// Previous and next position of a car or pedestrian when the traffic runs on the fixed timestep
+///
+/// The simulation only updates these, the rendered position is interpolated between them.
+#[derive(Component)]
+pub struct TrafficInterpolation {
+    previous: Vec3,
+    next: Vec3,
+}
+
+impl TrafficInterpolation {
+    pub fn advance(&mut self, translation: Vec3, teleported: bool) {
+        self.previous = if teleported { translation } else { self.next };
+        self.next = translation;
+    }
+}
+
+/// Starts interpolating the position of the cars, buses and pedestrians from their current position
+pub fn add_traffic_interpolation(
+    add: On<Add, (Car, Bus, Pedestrian)>,
+    mut commands: Commands,
+    transforms: Query<&Transform>,
+) {
+    let Ok(transform) = transforms.get(add.entity) else {
+        return;
+    };
+    commands.entity(add.entity).insert(TrafficInterpolation {
+        previous: transform.translation,
+        next: transform.translation,
+    });
+}
+
+pub fn interpolate_traffic(
+    fixed_time: Res<Time<Fixed>>,
+    mut traffic: Query<(&TrafficInterpolation, &mut Transform)>,
+) {
+    let alpha = fixed_time.overstep_fraction();
+    for (interpolation, mut transform) in &mut traffic {
+        transform.translation = interpolation.previous.lerp(interpolation.next, alpha);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_car_is_independent_of_the_delta() {
+        let (speed, road_len) = (1.5, 5.5);
+        let small_deltas = (0..600).fold(0.0, |distance, _| {
+            advance_car(distance, speed, 1.0 / 60.0, road_len)
+        });
+        let large_delta = advance_car(0.0, speed, 10.0, road_len);
+        assert!(
+            (small_deltas - large_delta).abs() < 1e-3,
+            "{small_deltas} != {large_delta}"
+        );
+        assert!((large_delta - (speed * 10.0) % road_len).abs() < 1e-3);
+    }
+
+    #[test]
+    fn advance_car_caps_the_steps() {
+        let distance = advance_car(0.0, 1.0, 1e9, 5.5);
+        assert!((0.0..=5.5).contains(&distance));
+    }
+
+    /// A car of the lane of the road at `distance` along it
+    fn traffic_car(road: &Road, index: u32, distance: f32, emergency: bool) -> TrafficCar {
+        TrafficCar {
+            entity: Entity::from_raw_u32(index).unwrap(),
+            lane: road.lanes[0],
+            distance,
+            speed: if emergency { 1.6 } else { 1.0 },
+            changing_lanes: false,
+            emergency,
+        }
+    }
+
+    #[test]
+    fn cars_make_way_for_emergency_vehicles() {
+        let mut road = Road::new(Vec3::ZERO, Vec3::X * 10.0, None, [1.0, -1.0]);
+        let mut traffic = [
+            traffic_car(&road, 1, 2.0, true),
+            traffic_car(&road, 2, 2.5, false),
+            // Too far ahead to be in the way yet
+            traffic_car(&road, 3, 5.0, false),
+        ];
+        // Both directions of a two-way street have a single lane
+        assert_eq!(
+            plan_maneuvers(&road, &mut traffic, false),
+            [(traffic[1].entity, Maneuver::Stop)]
+        );
+
+        // A one-way street has another lane to move over to
+        road.lanes[1].dir = 1.0;
+        let mut traffic = traffic.map(|car| TrafficCar {
+            lane: road.lanes[0],
+            ..car
+        });
+        assert_eq!(
+            plan_maneuvers(&road, &mut traffic, false),
+            [(traffic[1].entity, Maneuver::ChangeLanes(road.lanes[1]))]
+        );
+    }
+}
@@ -0,0 +1,329 @@
+use bevy::{
+    camera_controller::free_camera::FreeCamera,
+    light::NotShadowCaster,
+    pbr::AtmosphereSettings,
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+    scene::SceneInstanceReady,
+    ui::Checked,
+    ui_widgets::ValueChange,
+};
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::CityAssets,
+    generate_city::{CityConfig, RoadMarker},
+    settings::Settings,
+    sim_time::SimTime,
+};
+
+/// Number of rain drops falling around the camera
+const RAIN_DROP_COUNT: usize = 3000;
+/// Half the size of the box around the camera the rain drops fall in, they wrap around it as the
+/// camera moves so it's always raining around it
+const RAIN_EXTENT: Vec3 = Vec3::new(6.0, 4.0, 6.0);
+/// Speed the rain drops fall at, slightly slanted by the wind
+const RAIN_VELOCITY: Vec3 = Vec3::new(0.4, -9.0, 0.3);
+/// How dark the roads get when wet, 1 would make them black
+const WET_DARKENING: f32 = 0.45;
+/// Roughness of the roads when wet, the puddles reflect the city with the screen space
+/// reflections enabled
+const WET_ROUGHNESS: f32 = 0.12;
+
+/// Changes the lighting, the fog and the roads with the [`Weather`] of the [`CityConfig`], and
+/// makes it rain around the camera
+pub struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WetRoads>()
+            .init_resource::<RainAssets>()
+            .add_observer(wet_road_scene)
+            .add_systems(Update, (apply_weather, wet_roads, update_rain));
+    }
+}
+
+/// The weather over the city, it can be changed without regenerating the city
+///
+/// The clear weather is the look of the city without any weather.
+#[derive(Component, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Weather {
+    #[default]
+    Clear,
+    /// Clouds dim the sun
+    Overcast,
+    /// Dark clouds with rain falling around the camera and wet reflective roads
+    Rain,
+    /// A thick fog hides the distance
+    Fog,
+}
+
+impl Weather {
+    pub const ALL: [Weather; 4] = [
+        Weather::Clear,
+        Weather::Overcast,
+        Weather::Rain,
+        Weather::Fog,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Weather::Clear => "Clear",
+            Weather::Overcast => "Overcast",
+            Weather::Rain => "Rain",
+            Weather::Fog => "Fog",
+        }
+    }
+
+    /// Fraction of the sunlight going through the clouds
+    pub fn sunlight(self) -> f32 {
+        match self {
+            Weather::Clear => 1.0,
+            Weather::Overcast => 0.35,
+            Weather::Rain => 0.2,
+            Weather::Fog => 0.5,
+        }
+    }
+
+    /// Multiplies the fog density of the settings
+    pub fn fog_multiplier(self) -> f32 {
+        match self {
+            Weather::Clear => 1.0,
+            Weather::Overcast => 1.5,
+            Weather::Rain => 3.0,
+            Weather::Fog => 10.0,
+        }
+    }
+
+    /// How wet the roads are, from 0 for dry roads to 1 for soaked ones
+    pub fn wetness(self) -> f32 {
+        match self {
+            Weather::Rain => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+/// The fog density of the atmosphere for the fog density of the settings in this weather
+pub fn weather_fog_density(settings: &Settings, weather: Weather) -> f32 {
+    settings.fog_density * weather.fog_multiplier()
+}
+
+/// The materials of the roads wet by the rain
+///
+/// The materials of the road scenes are shared by every road using the same scene, so they are
+/// changed in place and their dry look is kept to restore it once the rain stops.
+#[derive(Resource, Default)]
+struct WetRoads {
+    wetness: f32,
+    /// The base color and roughness of each wet material before the rain
+    dry: HashMap<AssetId<StandardMaterial>, (Color, f32)>,
+}
+
+impl WetRoads {
+    fn wet(&mut self, materials: &mut Assets<StandardMaterial>, id: AssetId<StandardMaterial>) {
+        let Some(material) = materials.get_mut(id) else {
+            return;
+        };
+        let (color, roughness) = *self
+            .dry
+            .entry(id)
+            .or_insert((material.base_color, material.perceptual_roughness));
+        material.base_color = color.mix(&Color::BLACK, WET_DARKENING * self.wetness);
+        material.perceptual_roughness = roughness.lerp(WET_ROUGHNESS, self.wetness);
+    }
+
+    /// Gives the wet materials back their dry look
+    fn dry(&mut self, materials: &mut Assets<StandardMaterial>) {
+        for (id, (color, roughness)) in self.dry.drain() {
+            if let Some(material) = materials.get_mut(id) {
+                material.base_color = color;
+                material.perceptual_roughness = roughness;
+            }
+        }
+    }
+}
+
+/// Wets the materials of a road once its scene is spawned when it's raining
+fn wet_road_scene(
+    ready: On<SceneInstanceReady>,
+    roads: Query<(), With<RoadMarker>>,
+    mut wet_roads: ResMut<WetRoads>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    children: Query<&Children>,
+    mesh_materials: Query<&MeshMaterial3d<StandardMaterial>>,
+) {
+    if !roads.contains(ready.entity) || wet_roads.wetness == 0.0 {
+        return;
+    }
+    for entity in children.iter_descendants(ready.entity) {
+        if let Ok(mesh_material) = mesh_materials.get(entity)
+            && !wet_roads.dry.contains_key(&mesh_material.id())
+        {
+            wet_roads.wet(&mut materials, mesh_material.id());
+        }
+    }
+}
+
+/// Dims the sun and thickens the fog when the weather changes
+///
+/// The fog is also thickened on the atmospheres added after the weather changed, like when the
+/// atmosphere is turned back on in the settings.
+fn apply_weather(
+    config: Res<CityConfig>,
+    settings: Res<Settings>,
+    mut applied: Local<Option<Weather>>,
+    mut suns: Query<&mut DirectionalLight>,
+    mut atmospheres: Query<&mut AtmosphereSettings>,
+) {
+    let changed = *applied != Some(config.weather);
+    *applied = Some(config.weather);
+    for mut atmosphere in &mut atmospheres {
+        if changed || atmosphere.is_added() {
+            atmosphere.scene_units_to_m = weather_fog_density(&settings, config.weather);
+        }
+    }
+    if !changed {
+        return;
+    }
+    for mut sun in &mut suns {
+        sun.illuminance = light_consts::lux::RAW_SUNLIGHT * config.weather.sunlight();
+    }
+}
+
+/// Wets the roads and the asphalt of the high density blocks when it starts raining, and dries
+/// them when it stops
+fn wet_roads(
+    config: Res<CityConfig>,
+    assets: Option<Res<CityAssets>>,
+    mut wet_roads: ResMut<WetRoads>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    roads: Query<Entity, With<RoadMarker>>,
+    children: Query<&Children>,
+    mesh_materials: Query<&MeshMaterial3d<StandardMaterial>>,
+) {
+    let Some(assets) = assets else {
+        return;
+    };
+    let wetness = config.weather.wetness();
+    if wet_roads.wetness == wetness {
+        return;
+    }
+    wet_roads.wetness = wetness;
+    if wetness == 0.0 {
+        wet_roads.dry(&mut materials);
+        return;
+    }
+    let road_materials = roads
+        .iter()
+        .flat_map(|road| children.iter_descendants(road))
+        .filter_map(|entity| Some(mesh_materials.get(entity).ok()?.id()))
+        .collect::<HashSet<_>>();
+    wet_roads.wet(&mut materials, assets.ground_tiles.asphalt_material.id());
+    for id in road_materials {
+        wet_roads.wet(&mut materials, id);
+    }
+}
+
+/// A drop of the rain falling around the camera
+#[derive(Component)]
+struct RainDrop;
+
+/// The mesh and material shared by the rain drops, a thin streak
+#[derive(Resource)]
+struct RainAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for RainAssets {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            mesh: world
+                .resource_mut::<Assets<Mesh>>()
+                .add(Cuboid::new(0.006, 0.15, 0.006)),
+            material: world
+                .resource_mut::<Assets<StandardMaterial>>()
+                .add(StandardMaterial {
+                    base_color: Color::srgba(0.75, 0.8, 0.9, 0.35),
+                    alpha_mode: AlphaMode::Blend,
+                    unlit: true,
+                    ..default()
+                }),
+        }
+    }
+}
+
+/// Spawns the rain drops when it starts raining, despawns them when it stops, and makes them
+/// fall around the camera
+///
+/// The drops all share the same mesh and material so they are drawn in a single instanced batch.
+/// Instead of following the camera, the drops wrap around the box centered on it so moving the
+/// camera doesn't drag the rain along.
+fn update_rain(
+    mut commands: Commands,
+    config: Res<CityConfig>,
+    rain_assets: Res<RainAssets>,
+    time: Res<SimTime>,
+    camera: Option<Single<&GlobalTransform, With<FreeCamera>>>,
+    mut drops: Query<(Entity, &mut Transform), With<RainDrop>>,
+) {
+    let Some(camera) = camera else {
+        return;
+    };
+    let center = camera.translation();
+    let raining = config.weather == Weather::Rain;
+    if !raining {
+        for (entity, _) in &drops {
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+    if drops.is_empty() {
+        let mut rng = rand::rng();
+        for _ in 0..RAIN_DROP_COUNT {
+            let offset = Vec3::new(
+                rng.random_range(-1.0..1.0),
+                rng.random_range(-1.0..1.0),
+                rng.random_range(-1.0..1.0),
+            ) * RAIN_EXTENT;
+            commands.spawn((
+                Mesh3d(rain_assets.mesh.clone()),
+                MeshMaterial3d(rain_assets.material.clone()),
+                Transform::from_translation(center + offset).aligned_by(
+                    Dir3::Y,
+                    RAIN_VELOCITY,
+                    Dir3::X,
+                    Vec3::X,
+                ),
+                NotShadowCaster,
+                RainDrop,
+            ));
+        }
+        return;
+    }
+    let fall = RAIN_VELOCITY * time.frame_delta_secs();
+    for (_, mut transform) in &mut drops {
+        let offset = transform.translation + fall - center;
+        transform.translation =
+            center + (offset + RAIN_EXTENT).rem_euclid(RAIN_EXTENT * 2.0) - RAIN_EXTENT;
+    }
+}
+
+/// Changes the weather when one is picked in the settings
+pub fn select_weather(
+    change: On<ValueChange<Entity>>,
+    weathers: Query<(Entity, &Weather)>,
+    mut commands: Commands,
+    mut config: ResMut<CityConfig>,
+) {
+    for (radio, weather) in &weathers {
+        if radio != change.value {
+            commands.entity(radio).remove::<Checked>();
+            continue;
+        }
+        commands.entity(radio).insert(Checked);
+        config.weather = *weather;
+    }
+}
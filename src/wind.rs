@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+
+use crate::{
+    generate_city::{CityConfig, TreeMarker},
+    sim_time::SimTime,
+};
+
+/// How fast the trees sway back and forth, in radians per second
+const SWAY_SPEED: f32 = 1.6;
+/// How much the phase of the sway changes per unit of distance along the wind, the trees
+/// further along sway later so the gusts look like they travel across the city
+const GUST_SPREAD: f32 = 0.35;
+/// Direction the wind blows toward on the ground
+const WIND_DIRECTION: Vec2 = Vec2::new(0.8, 0.6);
+
+/// Sways the trees in the wind by tilting them around their base
+///
+/// The trees are tilted along the wind with a slower sway across it so they don't look like
+/// they are all swinging on the same hinge. The hidden trees are skipped so this costs nothing
+/// when the trees are turned off or hidden by the adaptive detail.
+pub fn animate_trees(
+    config: Res<CityConfig>,
+    time: Res<SimTime>,
+    mut trees: Query<(&mut Transform, &InheritedVisibility), With<TreeMarker>>,
+) {
+    // Straighten the trees once when the wind stops
+    if config.wind_strength == 0.0 && !config.is_changed() {
+        return;
+    }
+    let elapsed = time.elapsed_secs();
+    let across = WIND_DIRECTION.perp();
+    for (mut transform, visibility) in &mut trees {
+        if !visibility.get() {
+            continue;
+        }
+        let phase = transform.translation.xz().dot(WIND_DIRECTION) * GUST_SPREAD;
+        let along_tilt = config.wind_strength * (elapsed * SWAY_SPEED - phase).sin();
+        let across_tilt = 0.3 * config.wind_strength * (elapsed * SWAY_SPEED * 0.7 + phase).sin();
+        // Tilting toward a direction on the ground is a rotation around the axis perpendicular
+        // to it
+        transform.rotation = Quat::from_axis_angle(Vec3::new(across.x, 0.0, across.y), -along_tilt)
+            * Quat::from_axis_angle(
+                Vec3::new(WIND_DIRECTION.x, 0.0, WIND_DIRECTION.y),
+                across_tilt,
+            );
+    }
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+use bevy_city::{
+    assets::BuildingModel,
+    generate_city::{CityBlock, CityConfig, SpawnInfo},
+    layout::{BlockLayout, CityLayout},
+    traffic::Car,
+};
+
+/// What the buildings and cars of a block were spawned as
+///
+/// The moving cars are described by their road, speed and lane since they drive around while the
+/// city is generated.
+fn block_choices(app: &mut App, coord: IVec2) -> Vec<String> {
+    app.world_mut()
+        .run_system_once(
+            move |blocks: Query<(Entity, &CityBlock)>,
+                  children: Query<&Children>,
+                  spawned: Query<(&SpawnInfo, &Transform, Option<&BuildingModel>)>,
+                  cars: Query<(&Car, &ChildOf)>,
+                  transforms: Query<&Transform>| {
+                let (block, _) = blocks
+                    .iter()
+                    .find(|(_, block)| block.coord == coord)
+                    .expect("the block should be spawned");
+                let mut choices = children
+                    .iter_descendants(block)
+                    .filter_map(|entity| {
+                        let (SpawnInfo(label), transform, model) = spawned.get(entity).ok()?;
+                        Some(match cars.get(entity) {
+                            Ok((car, road)) => format!(
+                                "{label} {:?} {} {:?} {}",
+                                transforms.get(road.parent()).ok()?,
+                                car.speed,
+                                car.lane.offset,
+                                car.lane.dir,
+                            ),
+                            Err(_) => format!("{label} {transform:?} {model:?}"),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                choices.sort_unstable();
+                choices
+            },
+        )
+        .unwrap()
+}
+
+/// The content of a block only depends on the seed and the coordinate of the block, not on the
+/// blocks spawned before it
+#[test]
+fn block_is_the_same_on_its_own() {
+    let coord = IVec2::new(3, 7);
+    let config = CityConfig {
+        seed: 42,
+        ..Default::default()
+    };
+
+    let mut grid = common::generate_city(config.clone());
+    let in_grid = block_choices(&mut grid, coord);
+    let block = grid
+        .world_mut()
+        .query::<&CityBlock>()
+        .iter(grid.world())
+        .find(|block| block.coord == coord)
+        .map(|block| BlockLayout {
+            coord: block.coord.to_array(),
+            tier: block.tier,
+            district: Some(block.district),
+            reroll: block.reroll,
+        })
+        .unwrap();
+
+    let layout = CityLayout {
+        seed: config.seed,
+        rng_kind: config.rng_kind,
+        blocks: vec![block],
+    };
+    let mut alone = common::spawn_layout(config, layout);
+    let on_its_own = block_choices(&mut alone, coord);
+
+    assert!(!in_grid.is_empty());
+    assert_eq!(in_grid, on_its_own);
+}
+
+/// Number of moving cars spawned on the roads of a block
+fn block_car_count(app: &mut App, coord: IVec2) -> usize {
+    app.world_mut()
+        .run_system_once(
+            move |blocks: Query<(Entity, &CityBlock)>,
+                  children: Query<&Children>,
+                  cars: Query<(), With<Car>>| {
+                let (block, _) = blocks
+                    .iter()
+                    .find(|(_, block)| block.coord == coord)
+                    .expect("the block should be spawned");
+                children
+                    .iter_descendants(block)
+                    .filter(|entity| cars.contains(*entity))
+                    .count()
+            },
+        )
+        .unwrap()
+}
+
+/// The cars have their own rng so the number of cars of a block doesn't change when the rest of
+/// the block draws more from the block rng
+#[test]
+fn block_car_count_is_stable() {
+    let config = CityConfig {
+        seed: 42,
+        ..Default::default()
+    };
+    let mut app = common::generate_city(config.clone());
+    assert_eq!(block_car_count(&mut app, IVec2::ZERO), 6);
+
+    let mut app = common::generate_city(CityConfig {
+        tint_buildings: true,
+        randomize_building_yaw: true,
+        ..config
+    });
+    assert_eq!(block_car_count(&mut app, IVec2::ZERO), 6);
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+use bevy_city::{
+    generate_city::{CityBlock, CityConfig},
+    settings::Settings,
+    traffic::{Car, Road},
+};
+
+/// Where the cars are, sorted so the cars can be compared between two apps
+fn car_choices(app: &mut App) -> Vec<String> {
+    app.world_mut()
+        .run_system_once(
+            |cars: Query<(&Car, &ChildOf)>,
+             roads: Query<(&Transform, &ChildOf), With<Road>>,
+             blocks: Query<&CityBlock>| {
+                let mut choices = cars
+                    .iter()
+                    .map(|(car, road)| {
+                        let (transform, block) = roads.get(road.parent()).unwrap();
+                        let block = blocks.get(block.parent()).unwrap();
+                        format!(
+                            "{} {} {:?} {} {}",
+                            block.coord,
+                            transform.translation,
+                            car.lane.offset,
+                            car.distance_traveled,
+                            car.speed
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                choices.sort_unstable();
+                choices
+            },
+        )
+        .unwrap()
+}
+
+/// Generates the city and moves the car density slider through `densities`
+fn change_car_density(densities: &[f32]) -> Vec<String> {
+    let mut app = common::generate_city_without_traffic(CityConfig {
+        seed: 42,
+        size: 8,
+        ..Default::default()
+    });
+    for density in densities {
+        app.world_mut().resource_mut::<Settings>().car_density = *density;
+        app.update();
+    }
+    car_choices(&mut app)
+}
+
+/// The cars added and removed by the car density slider come from the seed, like the cars the
+/// city was generated with
+#[test]
+fn car_density_changes_are_deterministic() {
+    let densities = [0.2, 0.7, 0.5];
+    let cars = change_car_density(&densities);
+    assert!(!cars.is_empty());
+    assert_eq!(cars, change_car_density(&densities));
+    assert_ne!(cars, change_car_density(&[0.2]));
+}
@@ -0,0 +1,84 @@
+// Each test crate only uses some of the helpers
+#![allow(dead_code)]
+
+use bevy::prelude::*;
+use bevy_city::{
+    city::{CityGenerated, CityPlugin},
+    generate_city::CityConfig,
+    layout::CityLayout,
+    settings::Settings,
+};
+
+/// Number of updates after which a city that isn't generated yet is considered stuck
+const MAX_UPDATES: u32 = 1000;
+
+/// Set once [`CityGenerated`] is triggered
+#[derive(Resource, Default)]
+struct Generated(bool);
+
+/// Generates a city headlessly, without a window or any asset, like the `generate` binary does
+///
+/// The app is returned once the city is generated so its entities and resources can be checked.
+pub fn generate_city(config: CityConfig) -> App {
+    generate(CityPlugin {
+        config,
+        headless: true,
+        ..default()
+    })
+}
+
+/// Generates a city headlessly with the traffic stopped, see [`generate_city`]
+///
+/// The cars stay where they were spawned so where they are doesn't depend on how long the city
+/// took to generate.
+pub fn generate_city_without_traffic(config: CityConfig) -> App {
+    let mut app = App::new();
+    app.insert_resource(Settings {
+        simulate_cars: false,
+        simulate_pedestrians: false,
+        ..default()
+    });
+    generate_in(
+        app,
+        CityPlugin {
+            config,
+            headless: true,
+            ..default()
+        },
+    )
+}
+
+/// Spawns a layout headlessly instead of generating one from the config, see [`generate_city`]
+pub fn spawn_layout(config: CityConfig, layout: CityLayout) -> App {
+    generate(CityPlugin {
+        config,
+        layout: Some(layout),
+        headless: true,
+    })
+}
+
+fn generate(city: CityPlugin) -> App {
+    generate_in(App::new(), city)
+}
+
+fn generate_in(mut app: App, city: CityPlugin) -> App {
+    app.add_plugins((MinimalPlugins, city))
+        .init_resource::<Generated>()
+        .add_observer(|_: On<CityGenerated>, mut generated: ResMut<Generated>| {
+            generated.0 = true;
+        });
+    wait_for_city(&mut app);
+    app
+}
+
+/// Updates the app until the next [`CityGenerated`]
+pub fn wait_for_city(app: &mut App) {
+    app.world_mut().resource_mut::<Generated>().0 = false;
+    for _ in 0..MAX_UPDATES {
+        app.update();
+        if app.world().resource::<Generated>().0 {
+            return;
+        }
+    }
+    panic!("the city wasn't generated after {MAX_UPDATES} updates");
+}
@@ -0,0 +1,24 @@
+mod common;
+
+use bevy_city::{generate_city::CityConfig, rng::RngKind, stats::SceneStats};
+
+/// The stats of the default city with the seed 42, regenerate it by running
+/// `cargo run --bin generate -- --seed 42 --rng chacha` when the layout changes on purpose
+const SNAPSHOT: &str = include_str!("snapshots/scene_stats_seed_42.json");
+
+/// The generation is deterministic, any change to the spacing, the density thresholds or the
+/// draws of the rng shows up in the counts
+///
+/// This uses the ChaCha rng since unlike the default one its output is the same on every
+/// platform.
+#[test]
+fn scene_stats_match_snapshot() {
+    let app = common::generate_city(CityConfig {
+        seed: 42,
+        rng_kind: RngKind::ChaCha,
+        ..Default::default()
+    });
+    let stats = app.world().resource::<SceneStats>();
+    let snapshot: SceneStats = serde_json::from_str(SNAPSHOT).unwrap();
+    assert_eq!(*stats, snapshot);
+}
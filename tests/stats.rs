@@ -0,0 +1,136 @@
+mod common;
+
+use std::f32::consts::FRAC_PI_4;
+
+use bevy::{ecs::system::RunSystemOnce, platform::collections::HashSet, prelude::*};
+use bevy_city::{
+    camera::frame_city,
+    city::RegenerateCity,
+    generate_city::{
+        BuildingLod, BuildingMarker, CityBlock, CityConfig, CityRoot, DensityTier,
+        IndustrialMarker, ParkedCarMarker, RoadMarker, SidewalkMarker, TreeMarker,
+    },
+    stats::SceneStats,
+    traffic::{Car, Pedestrian},
+};
+
+/// What the markers of the entities under the root of the city add up to
+#[derive(Default)]
+struct MarkerCounts {
+    cars: u32,
+    parked_cars: u32,
+    buildings: [u32; 3],
+    industrial_buildings: u32,
+    road_segments: u32,
+    sidewalks: u32,
+    trees: u32,
+    pedestrians: u32,
+    parks: u32,
+    batches: HashSet<(AssetId<Mesh>, AssetId<StandardMaterial>)>,
+    instances: u32,
+}
+
+/// Counts the markers of the entities in the city, leaving out the entities waiting in the pool
+fn count_markers(app: &mut App) -> SceneStats {
+    app.world_mut()
+        .run_system_once(
+            |root: Single<Entity, With<CityRoot>>, children: Query<&Children>, world: &World| {
+                let mut counts = MarkerCounts::default();
+                for entity in children.iter_descendants(*root) {
+                    let entity = world.entity(entity);
+                    counts.cars += u32::from(entity.contains::<Car>());
+                    counts.parked_cars += u32::from(entity.contains::<ParkedCarMarker>());
+                    counts.road_segments += u32::from(entity.contains::<RoadMarker>());
+                    counts.sidewalks += u32::from(entity.contains::<SidewalkMarker>());
+                    counts.trees += u32::from(entity.contains::<TreeMarker>());
+                    counts.pedestrians += u32::from(entity.contains::<Pedestrian>());
+                    if let Some(block) = entity.get::<CityBlock>() {
+                        counts.parks +=
+                            u32::from(block.tier == DensityTier::Park && block.detail_visible);
+                    }
+                    if let Some(BuildingMarker { density }) = entity.get::<BuildingMarker>() {
+                        match density {
+                            _ if entity.contains::<IndustrialMarker>() => {
+                                counts.industrial_buildings += 1;
+                            }
+                            DensityTier::Low => counts.buildings[0] += 1,
+                            DensityTier::Medium => counts.buildings[1] += 1,
+                            DensityTier::High => counts.buildings[2] += 1,
+                            _ => {}
+                        }
+                    }
+                    if entity.contains::<BuildingMarker>() || entity.contains::<BuildingLod>() {
+                        let mesh = entity.get::<Mesh3d>().unwrap();
+                        let material = entity.get::<MeshMaterial3d<StandardMaterial>>().unwrap();
+                        counts.batches.insert((mesh.id(), material.id()));
+                        counts.instances += 1;
+                    }
+                }
+                SceneStats {
+                    cars_spawned: counts.cars,
+                    parked_cars: counts.parked_cars,
+                    low_density_buildings: counts.buildings[0],
+                    medium_density_buildings: counts.buildings[1],
+                    skyscrapers: counts.buildings[2],
+                    industrial_buildings: counts.industrial_buildings,
+                    road_segments: counts.road_segments,
+                    sidewalks: counts.sidewalks,
+                    trees: counts.trees,
+                    pedestrians: counts.pedestrians,
+                    parks: counts.parks,
+                    building_batches: counts.batches.len() as u32,
+                    building_instances: counts.instances,
+                }
+            },
+        )
+        .unwrap()
+}
+
+/// The stats are recounted from the markers, they should match the entities actually in the
+/// city, including after regenerating a smaller city that doesn't reuse every pooled entity
+#[test]
+fn stats_match_markers() {
+    let mut app = common::generate_city(CityConfig {
+        seed: 42,
+        size: 12,
+        ..Default::default()
+    });
+    let counted = count_markers(&mut app);
+    assert_eq!(*app.world().resource::<SceneStats>(), counted);
+    assert!(counted.cars_spawned > 0 && counted.low_density_buildings > 0);
+
+    app.world_mut().resource_mut::<CityConfig>().size = 6;
+    app.world_mut().trigger(RegenerateCity);
+    common::wait_for_city(&mut app);
+    let counted = count_markers(&mut app);
+    assert_eq!(*app.world().resource::<SceneStats>(), counted);
+}
+
+/// A grid smaller than two blocks still spawns a single block, without any NaN transform and with
+/// the camera framing it from a finite distance
+#[test]
+fn single_block_city() {
+    for size in [0, 1] {
+        let mut app = common::generate_city(CityConfig {
+            seed: 42,
+            size,
+            ..Default::default()
+        });
+        let stats = app.world().resource::<SceneStats>().clone();
+        assert!(stats.road_segments > 0 && stats.sidewalks > 0, "{stats:?}");
+        assert!(
+            stats.road_segments <= 8 && stats.sidewalks <= 4,
+            "{stats:?}"
+        );
+        assert_eq!(stats, count_markers(&mut app));
+        let config = app.world().resource::<CityConfig>();
+        assert!(frame_city(config, FRAC_PI_4).is_finite());
+
+        let world = app.world_mut();
+        let blocks = world.query::<&CityBlock>().iter(world).count();
+        assert_eq!(blocks, 1);
+        for transform in world.query::<&Transform>().iter(world) {
+            assert!(transform.is_finite(), "{transform:?}");
+        }
+    }
+}